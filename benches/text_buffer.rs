@@ -0,0 +1,121 @@
+// compares the rope backed TextBuffer against the old Vec<String> content
+// run with: cargo bench
+
+#[allow(dead_code)]
+#[path = "../src/text_buffer.rs"]
+mod text_buffer;
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use text_buffer::TextBuffer;
+
+const LINE: &str = "2024-01-01T00:00:00Z INFO request handled in 12ms path=/api/v1/items status=200";
+
+fn sample(size: usize) -> String {
+    let mut text = String::with_capacity(size + LINE.len() + 1);
+    while text.len() < size {
+        text.push_str(LINE);
+        text.push('\n');
+    }
+    text
+}
+
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
+
+fn report(name: &str, vec: Duration, rope: Duration) {
+    println!("{:<28} Vec<String> {:>10.2?}   TextBuffer {:>10.2?}", name, vec, rope);
+}
+
+fn bench(size: usize) {
+    let text = sample(size);
+    println!("-- {} MB --", size / (1024 * 1024));
+
+    let mut lines: Vec<String> = Vec::new();
+    let mut buffer = TextBuffer::new();
+
+    report(
+        "open",
+        time(|| lines = text.lines().map(String::from).collect()),
+        time(|| buffer = TextBuffer::from_text(&text)),
+    );
+
+    let middle = lines.len() / 2;
+
+    report(
+        "type 1000 chars mid file",
+        time(|| {
+            for i in 0..1000 {
+                lines[middle].insert(i, 'x');
+            }
+        }),
+        time(|| {
+            for i in 0..1000 {
+                buffer.insert(middle, i, "x");
+            }
+        }),
+    );
+
+    report(
+        "insert 1000 lines mid file",
+        time(|| {
+            for _ in 0..1000 {
+                lines.insert(middle, String::new());
+            }
+        }),
+        time(|| {
+            for _ in 0..1000 {
+                buffer.insert(middle, 0, "\n");
+            }
+        }),
+    );
+
+    report(
+        "join 1000 lines mid file",
+        time(|| {
+            for _ in 0..1000 {
+                let next = lines.remove(middle + 1);
+                lines[middle].push_str(&next);
+            }
+        }),
+        time(|| {
+            for _ in 0..1000 {
+                let end = buffer.line_len(middle);
+                buffer.delete((middle, end), (middle + 1, 0));
+            }
+        }),
+    );
+
+    report(
+        "read 100 screen lines",
+        time(|| {
+            for line in &lines[middle..middle + 100] {
+                black_box(line);
+            }
+        }),
+        time(|| {
+            for i in middle..middle + 100 {
+                black_box(buffer.line(i));
+            }
+        }),
+    );
+
+    report(
+        "snapshot (undo)",
+        time(|| {
+            black_box(lines.clone());
+        }),
+        time(|| {
+            black_box(buffer.clone());
+        }),
+    );
+}
+
+fn main() {
+    for size in [1, 10, 100] {
+        bench(size * 1024 * 1024);
+    }
+}
@@ -0,0 +1,157 @@
+// a tiny language server over stdio for the lsp tests, built by cargo test.
+// it only knows what the tests ask of it:
+// - diagnostics for every "bad" (error) and "warn" (warning) in an opened file
+// - hover, definition (as a LocationLink to "fn target") and completion
+// - a workspace/configuration request right after initialize
+// - complaints, as showMessage errors, about anything sent before initialized
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::thread;
+use std::time::Duration;
+use serde_json::{Value, json};
+
+fn read(reader: &mut impl BufRead) -> Option<Value> {
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            length = value.trim().parse().ok()?;
+        }
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+fn send(message: Value) {
+    let body = message.to_string();
+    let mut stdout = io::stdout().lock();
+    let _ = write!(stdout, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+    let _ = stdout.flush();
+}
+
+fn reply(id: &Value, result: Value) {
+    send(json!({ "jsonrpc": "2.0", "id": id, "result": result }));
+}
+
+fn complain(text: String) {
+    send(json!({ "jsonrpc": "2.0", "method": "window/showMessage", "params": { "type": 1, "message": text } }));
+}
+
+fn utf16_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+fn publish_diagnostics(uri: &str, text: &str) {
+    let mut diagnostics = Vec::new();
+    for (line, content) in text.split('\n').enumerate() {
+        for (word, severity) in [("bad", 1), ("warn", 2)] {
+            if let Some(start) = content.find(word) {
+                let character = utf16_len(&content[..start]);
+                diagnostics.push(json!({
+                    "range": {
+                        "start": { "line": line, "character": character },
+                        "end": { "line": line, "character": character + word.len() },
+                    },
+                    "severity": severity,
+                    "message": format!("{} word here\nsecond line", word),
+                }));
+            }
+        }
+    }
+    send(json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    }));
+}
+
+fn main() {
+    let mut reader = BufReader::new(io::stdin().lock());
+    let mut documents: HashMap<String, String> = HashMap::new();
+    let mut initialized = false;
+
+    while let Some(message) = read(&mut reader) {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let id = &message["id"];
+
+        if !initialized && !matches!(method, "initialize" | "initialized" | "") {
+            complain(format!("{} before initialized", method));
+        }
+
+        match method {
+            "initialize" => {
+                // slow enough that the client has to queue what comes next
+                thread::sleep(Duration::from_millis(100));
+                reply(id, json!({ "capabilities": { "completionProvider": { "triggerCharacters": [".", ":"] } } }));
+                send(json!({
+                    "jsonrpc": "2.0",
+                    "id": "config",
+                    "method": "workspace/configuration",
+                    "params": { "items": [{ "section": "a" }, { "section": "b" }] },
+                }));
+            },
+            "initialized" => initialized = true,
+            "textDocument/didOpen" => {
+                let document = &params["textDocument"];
+                let uri = document["uri"].as_str().unwrap_or_default().to_string();
+                let text = document["text"].as_str().unwrap_or_default().to_string();
+                publish_diagnostics(&uri, &text);
+                documents.insert(uri, text);
+            },
+            "textDocument/didChange" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_string();
+                let text = params["contentChanges"][0]["text"].as_str().unwrap_or_default().to_string();
+                publish_diagnostics(&uri, &text);
+                documents.insert(uri, text);
+            },
+            "textDocument/hover" => {
+                let position = &params["position"];
+                reply(id, json!({ "contents": {
+                    "kind": "markdown",
+                    "value": format!("```rust\nfn hello() -> i32\n```\n\nSays hello at {}:{}\n\n", position["line"], position["character"]),
+                } }));
+            },
+            "textDocument/definition" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                let text = documents.get(uri).map(String::as_str).unwrap_or_default();
+                let target = text.split('\n').enumerate().find_map(|(line, content)| {
+                    let start = utf16_len(&content[..content.find("fn target")?]) + 3;
+                    let range = json!({
+                        "start": { "line": line, "character": start },
+                        "end": { "line": line, "character": start + 6 },
+                    });
+                    Some(json!([{ "targetUri": uri, "targetRange": range, "targetSelectionRange": range }]))
+                });
+                reply(id, target.unwrap_or(Value::Null));
+            },
+            "textDocument/completion" => {
+                reply(id, json!({ "isIncomplete": false, "items": [
+                    { "label": "hello_world", "detail": "fn()\nmore", "sortText": "b" },
+                    { "label": "help", "insertText": "help()", "insertTextFormat": 1, "sortText": "c" },
+                    { "label": "format", "insertText": "format!(${1:fmt})$0", "insertTextFormat": 2, "sortText": "a" },
+                ] }));
+            },
+            "shutdown" => reply(id, Value::Null),
+            "exit" => break,
+            // the answer to workspace/configuration
+            "" if id == "config" => {
+                let items = message["result"].as_array().map_or(0, Vec::len);
+                complain(format!("configuration answered with {} items", items));
+            },
+            _ => {},
+        }
+    }
+}
@@ -0,0 +1,582 @@
+use std::fs::{self, File, OpenOptions};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use crate::git::Diff;
+use crate::history::History;
+use crate::lsp::Diagnostic;
+use crate::swap;
+use crate::syntax::Highlighter;
+use crate::text_buffer::TextBuffer;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    // the `:set fileformat` names
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "unix",
+            LineEnding::CrLf => "dos",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "unix" => Some(LineEnding::Lf),
+            "dos" => Some(LineEnding::CrLf),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Encoding {
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    // any byte is a char, so files that aren't valid UTF-8 still load and
+    // save back untouched
+    Latin1,
+}
+
+impl Encoding {
+    pub const NAMES: &[&str] = &["utf-8", "utf-8-bom", "utf-16le", "utf-16be", "latin1"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf8Bom => "utf-8-bom",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Latin1 => "latin1",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            "utf-8-bom" | "utf8-bom" => Some(Encoding::Utf8Bom),
+            "utf-16le" | "utf16le" | "utf-16" => Some(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Some(Encoding::Utf16Be),
+            "latin1" | "latin-1" | "iso-8859-1" => Some(Encoding::Latin1),
+            _ => None,
+        }
+    }
+
+    fn bom(self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8Bom => &[0xef, 0xbb, 0xbf],
+            Encoding::Utf16Le => &[0xff, 0xfe],
+            Encoding::Utf16Be => &[0xfe, 0xff],
+            _ => &[],
+        }
+    }
+
+    // the encoding a BOM names, or UTF-8 when the bytes are valid UTF-8,
+    // or Latin-1 when nothing else fits. only one that decodes every byte is
+    // taken, a BOM followed by bytes it can't decode makes the file Latin-1
+    // so saving writes back what was read
+    fn detect(bytes: &[u8]) -> (Self, String) {
+        [Encoding::Utf8Bom, Encoding::Utf16Le, Encoding::Utf16Be]
+            .into_iter()
+            .filter(|encoding| bytes.starts_with(encoding.bom()))
+            .chain([Encoding::Utf8, Encoding::Latin1])
+            .find_map(|encoding| Some((encoding, encoding.decode(bytes)?)))
+            .unwrap()
+    }
+
+    // None when the bytes aren't all valid in this encoding
+    fn decode(self, bytes: &[u8]) -> Option<String> {
+        let bytes = bytes.strip_prefix(self.bom())?;
+        let utf16 = |unit: fn([u8; 2]) -> u16| {
+            if bytes.len() % 2 != 0 {
+                return None;
+            }
+            let units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+            char::decode_utf16(units).collect::<Result<String, _>>().ok()
+        };
+
+        match self {
+            Encoding::Utf8 | Encoding::Utf8Bom => String::from_utf8(bytes.to_vec()).ok(),
+            Encoding::Utf16Le => utf16(u16::from_le_bytes),
+            Encoding::Utf16Be => utf16(u16::from_be_bytes),
+            Encoding::Latin1 => Some(bytes.iter().map(|b| *b as char).collect()),
+        }
+    }
+
+    fn encode(self, text: &str) -> io::Result<Vec<u8>> {
+        match self {
+            Encoding::Utf8 | Encoding::Utf8Bom => Ok(text.as_bytes().to_vec()),
+            Encoding::Utf16Le => Ok(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+            Encoding::Utf16Be => Ok(text.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+            Encoding::Latin1 => text.chars()
+                .map(|c| u8::try_from(c).map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("{} can't be written as latin1", c))
+                }))
+                .collect(),
+        }
+    }
+}
+
+// what `:set` changes on the current buffer instead of the config
+pub const OPTIONS: &[&str] = &["fileformat", "fileencoding"];
+
+// how the file looked on disk, so saving writes it back the same way
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Format {
+    pub encoding: Encoding,
+    pub line_ending: LineEnding,
+    pub final_newline: bool,
+}
+
+impl Default for Format {
+    // new files get UTF-8, unix line endings and a newline at the end
+    fn default() -> Self {
+        Format { encoding: Encoding::Utf8, line_ending: LineEnding::Lf, final_newline: true }
+    }
+}
+
+// the file as it was last read or written, to notice another program
+// changing it. mtime and size are cheap to check, the hash tells a real
+// change from a touch
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DiskState {
+    modified: Option<SystemTime>,
+    size: u64,
+    hash: u64,
+}
+
+impl DiskState {
+    pub fn read(path: &Path) -> Option<Self> {
+        let bytes = fs::read(path).ok()?;
+        DiskState::of(path, &bytes)
+    }
+
+    fn of(path: &Path, bytes: &[u8]) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        let mut hasher = DefaultHasher::new();
+        bytes.hash(&mut hasher);
+
+        Some(DiskState { modified: metadata.modified().ok(), size: metadata.len(), hash: hasher.finish() })
+    }
+}
+
+// an open file. the cursor and scroll are kept here so switching back to a
+// buffer puts you where you left it
+pub struct Buffer {
+    pub content: TextBuffer,
+    pub file_path: String,
+    pub history: History,
+    pub modified: bool,
+    pub format: Format,
+    // None until the file exists
+    pub disk: Option<DiskState>,
+    pub highlighter: Highlighter,
+    pub diff: Diff,
+    // false once edited, until the language server has the new text
+    pub synced: bool,
+    pub diagnostics: Vec<Diagnostic>,
+    // None for a buffer without a file, or one another editor is swapping
+    pub swap_file: Option<PathBuf>,
+    // false once edited, until the swap file has the new text
+    pub swapped: bool,
+
+    pub cursor_l: usize,
+    pub cursor_c: usize,
+    pub scroll_offset: usize,
+}
+
+impl Buffer {
+    pub fn new(file_path: &str) -> Self {
+        let path = Path::new(file_path);
+        let (content, format, disk) = match fs::read(path) {
+            Ok(bytes) if file_path != "." && path.is_file() => {
+                let (content, format) = decode_file(&bytes);
+                (content, format, DiskState::of(path, &bytes))
+            },
+            _ => (TextBuffer::new(), Format::default(), None),
+        };
+
+        Buffer {
+            content,
+            file_path: file_path.to_string(),
+            history: History::new(),
+            modified: false,
+            format,
+            disk,
+            highlighter: Highlighter::new(file_path),
+            diff: Diff::load(file_path),
+            synced: false,
+            diagnostics: Vec::new(),
+            swap_file: if file_path == "." { None } else { swap::path(file_path) },
+            swapped: true,
+            cursor_l: 0,
+            cursor_c: 0,
+            scroll_offset: 0,
+        }
+    }
+
+    pub fn is_empty_file(&self) -> bool {
+        self.file_path == "."
+    }
+
+    pub fn is_path(&self, path: &Path) -> bool {
+        if self.is_empty_file() {
+            return false;
+        }
+
+        let own = Path::new(&self.file_path);
+        match (own.canonicalize(), path.canonicalize()) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => own == path,
+        }
+    }
+
+    // true when another program wrote the file since it was read or saved.
+    // a touch that left the bytes alone only updates the stamp
+    pub fn changed_on_disk(&mut self) -> bool {
+        if self.is_empty_file() {
+            return false;
+        }
+
+        let path = Path::new(&self.file_path);
+        // a deleted file is just written again on save
+        let Ok(metadata) = fs::metadata(path) else { return false };
+        if let Some(disk) = self.disk && metadata.modified().ok() == disk.modified && metadata.len() == disk.size {
+            return false;
+        }
+
+        match DiskState::read(path) {
+            Some(now) if self.disk.is_some_and(|disk| disk.hash == now.hash) => {
+                self.disk = Some(now);
+                false
+            },
+            Some(_) => true,
+            None => false,
+        }
+    }
+
+    pub fn remove_swap(&self) {
+        if let Some(swap_file) = &self.swap_file {
+            swap::remove(swap_file);
+        }
+    }
+}
+
+// the text with '\n' line breaks and no final newline, and the format it
+// had. a file is dos only when every line ends in \r\n, a mixed one keeps
+// its \r chars in the text so it's written back the same
+fn decode_file(bytes: &[u8]) -> (TextBuffer, Format) {
+    let (encoding, mut text) = Encoding::detect(bytes);
+
+    let breaks = text.matches('\n').count();
+    let line_ending = if breaks > 0 && text.matches("\r\n").count() == breaks {
+        text = text.replace("\r\n", "\n");
+        LineEnding::CrLf
+    } else {
+        LineEnding::Lf
+    };
+
+    let final_newline = text.ends_with('\n');
+    if final_newline {
+        text.pop();
+    }
+
+    (TextBuffer::from_text(&text), Format { encoding, line_ending, final_newline })
+}
+
+// writes to a temp file next to the real one, syncs it and renames it over,
+// so a crash or a full disk never leaves half a file behind. a symlink is
+// followed and the file it points to is replaced, keeping its permissions.
+// a folder that can't take the temp file can still hold a file that can be
+// written, `force` (from `:w!`) then writes over it in place
+pub fn write_file(path: &Path, content: &TextBuffer, format: Format, force: bool) -> io::Result<()> {
+    let target = resolve_link(path);
+    let dir = target.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let name = target.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file name"))?;
+    let temp = dir.join(format!(".{}.reedit-{}", name.to_string_lossy(), std::process::id()));
+
+    let file = match OpenOptions::new().write(true).create_new(true).open(&temp) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied && force => {
+            return write_in_place(&target, content, format);
+        },
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+            let message = format!("can't write to {} (add ! to write the file in place)", dir.display());
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, message));
+        },
+        Err(err) => return Err(err),
+    };
+
+    let result = (|| {
+        if let Ok(metadata) = fs::metadata(&target) {
+            fs::set_permissions(&temp, metadata.permissions())?;
+        }
+        write_text(file, content, format)?;
+        fs::rename(&temp, &target)?;
+
+        // the rename itself is only safe once the folder is synced
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+        Ok(())
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+// the text is encoded before the file is opened, so a char the encoding
+// can't hold doesn't leave the file cut short
+fn write_in_place(target: &Path, content: &TextBuffer, format: Format) -> io::Result<()> {
+    let mut data = Vec::new();
+    encode_text(&mut data, content, format)?;
+
+    let mut file = OpenOptions::new().write(true).open(target)?;
+    file.write_all(&data)?;
+    file.set_len(data.len() as u64)?;
+    file.sync_all()
+}
+
+fn write_text(file: File, content: &TextBuffer, format: Format) -> io::Result<()> {
+    let mut writer = BufWriter::new(file);
+    encode_text(&mut writer, content, format)?;
+
+    let file = writer.into_inner().map_err(|err| err.into_error())?;
+    file.sync_all()
+}
+
+fn encode_text(writer: &mut impl Write, content: &TextBuffer, format: Format) -> io::Result<()> {
+    let encoding = format.encoding;
+    let ending = encoding.encode(format.line_ending.as_str())?;
+    let last = content.line_count() - 1;
+
+    writer.write_all(encoding.bom())?;
+    if format.line_ending == LineEnding::Lf && matches!(encoding, Encoding::Utf8 | Encoding::Utf8Bom) {
+        content.write_to(&mut *writer)?;
+    } else {
+        for i in 0..=last {
+            writer.write_all(&encoding.encode(&content.line(i))?)?;
+            if i < last {
+                writer.write_all(&ending)?;
+            }
+        }
+    }
+
+    if format.final_newline {
+        writer.write_all(&ending)?;
+    }
+    Ok(())
+}
+
+// where a symlink ends up, or the path itself
+fn resolve_link(path: &Path) -> PathBuf {
+    if let Ok(target) = fs::canonicalize(path) {
+        return target;
+    }
+
+    // a link to a file that doesn't exist yet
+    match fs::read_link(path) {
+        Ok(link) => path.parent().map_or(link.clone(), |dir| dir.join(link)),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt, symlink};
+    use crate::test_util::TempDir;
+
+    fn write(path: &Path, text: &str, format: Format) -> io::Result<()> {
+        write_file(path, &TextBuffer::from_text(text), format, false)
+    }
+
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    fn entries(dir: &TempDir) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir.path()).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn writes_keep_the_mode_and_leave_no_temp_file() {
+        let dir = TempDir::new("write-mode");
+        let path = dir.join("run.sh");
+        fs::write(&path, "old\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o751)).unwrap();
+
+        write(&path, "echo hi", Format::default()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "echo hi\n");
+        assert_eq!(mode(&path), 0o751);
+        assert_eq!(entries(&dir), ["run.sh"]);
+
+        let new = dir.join("new.txt");
+        write(&new, "x", Format::default()).unwrap();
+        assert_eq!(fs::read_to_string(&new).unwrap(), "x\n");
+        assert_eq!(entries(&dir), ["new.txt", "run.sh"]);
+    }
+
+    #[test]
+    fn writes_go_through_symlinks() {
+        let dir = TempDir::new("write-link");
+        let target = dir.join("target.txt");
+        let link = dir.join("link.txt");
+        fs::write(&target, "old\n").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o600)).unwrap();
+        symlink("target.txt", &link).unwrap();
+
+        write(&link, "new", Format::default()).unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new\n");
+        assert_eq!(mode(&target), 0o600);
+
+        // a link to a file that isn't there yet
+        let dangling = dir.join("dangling.txt");
+        symlink("later.txt", &dangling).unwrap();
+        write(&dangling, "later", Format::default()).unwrap();
+        assert!(fs::symlink_metadata(&dangling).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(dir.join("later.txt")).unwrap(), "later\n");
+        assert_eq!(entries(&dir), ["dangling.txt", "later.txt", "link.txt", "target.txt"]);
+    }
+
+    #[test]
+    fn line_endings_and_final_newline_are_kept() {
+        let dir = TempDir::new("write-format");
+        let path = dir.join("file.txt");
+
+        for bytes in ["a\r\nb\r\n", "a\r\nb", "a\nb\n", "a\nb", "", "\n", "\r\n", "a\r\nb\nc"] {
+            fs::write(&path, bytes).unwrap();
+            let buffer = Buffer::new(path.to_str().unwrap());
+            write_file(&path, &buffer.content, buffer.format, false).unwrap();
+            assert_eq!(fs::read(&path).unwrap(), bytes.as_bytes(), "{:?}", bytes);
+        }
+
+        let crlf = Format { line_ending: LineEnding::CrLf, ..Format::default() };
+        write(&path, "a\nb", crlf).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"a\r\nb\r\n");
+        let no_newline = Format { final_newline: false, ..Format::default() };
+        write(&path, "a\nb", no_newline).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"a\nb");
+    }
+
+    // what saving a file right after reading it writes
+    fn round_trip(bytes: &[u8]) -> (Format, Vec<u8>) {
+        let (content, format) = decode_file(bytes);
+        let mut written = Vec::new();
+        encode_text(&mut written, &content, format).unwrap();
+        (format, written)
+    }
+
+    #[test]
+    fn every_encoding_and_line_ending_round_trips() {
+        for encoding in Encoding::NAMES.iter().map(|name| Encoding::from_name(name).unwrap()) {
+            let text = if encoding == Encoding::Latin1 { "héllo\nwörld ±" } else { "héllo\nwörld ✓ 👋" };
+            for line_ending in [LineEnding::Lf, LineEnding::CrLf] {
+                for final_newline in [true, false] {
+                    let format = Format { encoding, line_ending, final_newline };
+                    let mut bytes = Vec::new();
+                    encode_text(&mut bytes, &TextBuffer::from_text(text), format).unwrap();
+
+                    let (content, read) = decode_file(&bytes);
+                    assert_eq!(read, format);
+                    assert_eq!(content.text(), text, "{:?}", format);
+                    assert_eq!(round_trip(&bytes), (format, bytes));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn odd_line_endings_round_trip() {
+        for bytes in ["a\nb\r\nc\n", "a\r\nb\nc\r\n", "a\rb\r", "\r", "a\r\r\nb\r\n", "a\n\n\n", "\n\r\n"] {
+            assert_eq!(round_trip(bytes.as_bytes()).1, bytes.as_bytes(), "{:?}", bytes);
+        }
+        assert_eq!(round_trip(b"a\r\r\nb\r\n").0.line_ending, LineEnding::CrLf);
+        assert_eq!(round_trip(b"a\nb\r\n").0.line_ending, LineEnding::Lf);
+    }
+
+    #[test]
+    fn bytes_the_bom_can_not_decode_are_latin1() {
+        let broken: [&[u8]; 6] = [
+            // invalid UTF-8 after a UTF-8 BOM, and without one
+            b"\xef\xbb\xbfa\xff\n",
+            b"caf\xe9\r\n",
+            // an odd byte at the end of UTF-16
+            b"\xff\xfea\x00b",
+            b"\xfe\xff\x00a\x00",
+            // surrogates without their other half
+            b"\xff\xfe\x00\xd8a\x00",
+            b"\xfe\xff\xdc\x00\x00a",
+        ];
+        for bytes in broken {
+            let (format, written) = round_trip(bytes);
+            assert_eq!(format.encoding, Encoding::Latin1, "{:?}", bytes);
+            assert_eq!(written, bytes);
+        }
+
+        // and a good pair still makes it UTF-16
+        assert_eq!(round_trip(b"\xff\xfe\x3d\xd8\x4b\xdc").0.encoding, Encoding::Utf16Le);
+        assert_eq!(decode_file(b"\xff\xfe\x3d\xd8\x4b\xdc").0.text(), "👋");
+    }
+
+    #[test]
+    fn in_place_writes_keep_the_file_and_cut_it_to_length() {
+        let dir = TempDir::new("write-in-place");
+        let path = dir.join("file.txt");
+        fs::write(&path, "a much longer old text\n").unwrap();
+        let inode = fs::metadata(&path).unwrap().ino();
+
+        write_in_place(&path, &TextBuffer::from_text("new"), Format::default()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+        assert_eq!(fs::metadata(&path).unwrap().ino(), inode);
+
+        // a char the encoding can't hold leaves the file alone
+        let latin1 = Format { encoding: Encoding::Latin1, ..Format::default() };
+        assert!(write_in_place(&path, &TextBuffer::from_text("ok\n€"), latin1).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+    }
+
+    #[test]
+    fn read_only_folders_need_force() {
+        let dir = TempDir::new("write-folder");
+        let path = dir.join("file.txt");
+        fs::write(&path, "old\n").unwrap();
+        fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o555)).unwrap();
+
+        if File::create(dir.join("probe")).is_ok() {
+            fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o755)).unwrap();
+            eprintln!("skipped read_only_folders_need_force: this user (root?) writes to read-only folders");
+            return;
+        }
+
+        let result = write(&path, "new", Format::default());
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old\n");
+        assert_eq!(entries(&dir), ["file.txt"]);
+
+        write_file(&path, &TextBuffer::from_text("new"), Format::default(), true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+        fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
@@ -0,0 +1,138 @@
+use std::env;
+use std::io::{Write, stdout};
+use std::process::{Command, Stdio};
+
+// where the "+ register goes. `get` returns None when the clipboard can't
+// be read
+pub trait ClipboardProvider {
+    fn set(&mut self, text: &str) -> Result<(), String>;
+    fn get(&mut self) -> Option<String>;
+}
+
+// an external program pair such as wl-copy / wl-paste
+pub struct CommandProvider {
+    copy: Vec<&'static str>,
+    paste: Vec<&'static str>,
+}
+
+impl ClipboardProvider for CommandProvider {
+    fn set(&mut self, text: &str) -> Result<(), String> {
+        let mut child = Command::new(self.copy[0])
+            .args(&self.copy[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| format!("{}: {}", self.copy[0], err))?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes()).map_err(|err| format!("{}: {}", self.copy[0], err))?;
+        }
+        child.wait().map_err(|err| format!("{}: {}", self.copy[0], err))?;
+        Ok(())
+    }
+
+    fn get(&mut self) -> Option<String> {
+        let output = Command::new(self.paste[0])
+            .args(&self.paste[1..])
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+
+        output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+// asks the terminal to set its clipboard, which also works over ssh.
+// terminals don't answer reads, so the last copied text is kept instead
+#[derive(Default)]
+pub struct Osc52 {
+    last: Option<String>,
+}
+
+impl ClipboardProvider for Osc52 {
+    fn set(&mut self, text: &str) -> Result<(), String> {
+        let mut stdout = stdout();
+        write!(stdout, "\x1b]52;c;{}\x07", base64(text.as_bytes()))
+            .and_then(|_| stdout.flush())
+            .map_err(|err| format!("OSC 52: {}", err))?;
+        self.last = Some(text.to_string());
+        Ok(())
+    }
+
+    fn get(&mut self) -> Option<String> {
+        self.last.clone()
+    }
+}
+
+// keeps the text inside the editor, for `clipboard = "none"` and tests
+#[derive(Default)]
+pub struct StubProvider {
+    pub contents: Option<String>,
+}
+
+impl ClipboardProvider for StubProvider {
+    fn set(&mut self, text: &str) -> Result<(), String> {
+        self.contents = Some(text.to_string());
+        Ok(())
+    }
+
+    fn get(&mut self) -> Option<String> {
+        self.contents.clone()
+    }
+}
+
+// the `clipboard` option: auto, wl-copy, xclip, xsel, pbcopy, osc52 or none
+pub fn provider(name: &str) -> Box<dyn ClipboardProvider> {
+    let command = |copy: &[&'static str], paste: &[&'static str]| -> Box<dyn ClipboardProvider> {
+        Box::new(CommandProvider { copy: copy.to_vec(), paste: paste.to_vec() })
+    };
+
+    match name {
+        "wl-copy" => command(&["wl-copy"], &["wl-paste", "--no-newline"]),
+        "xclip" => command(&["xclip", "-selection", "clipboard"], &["xclip", "-selection", "clipboard", "-o"]),
+        "xsel" => command(&["xsel", "--clipboard", "--input"], &["xsel", "--clipboard", "--output"]),
+        "pbcopy" => command(&["pbcopy"], &["pbpaste"]),
+        "osc52" => Box::<Osc52>::default(),
+        "none" => Box::<StubProvider>::default(),
+        _ => provider(detect()),
+    }
+}
+
+pub const PROVIDERS: [&str; 7] = ["auto", "wl-copy", "xclip", "xsel", "pbcopy", "osc52", "none"];
+
+fn detect() -> &'static str {
+    if env::var_os("WAYLAND_DISPLAY").is_some() && in_path("wl-copy") {
+        "wl-copy"
+    } else if env::var_os("DISPLAY").is_some() && in_path("xclip") {
+        "xclip"
+    } else if env::var_os("DISPLAY").is_some() && in_path("xsel") {
+        "xsel"
+    } else if in_path("pbcopy") {
+        "pbcopy"
+    } else {
+        "osc52"
+    }
+}
+
+fn in_path(program: &str) -> bool {
+    env::var_os("PATH").is_some_and(|paths| env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(TABLE[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
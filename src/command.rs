@@ -0,0 +1,464 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::buffer;
+use crate::config;
+use crate::substitute;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Cmd {
+    Edit,
+    Write,
+    WriteQuit,
+    Quit,
+    QuitAll,
+    Close,
+    Split,
+    VSplit,
+    BufferNext,
+    BufferPrev,
+    Buffers,
+    BufferDelete,
+    Buffer,
+    Set,
+    NoHighlight,
+    Map,
+    NMap,
+    IMap,
+    VMap,
+    Help,
+    Find,
+    Grep,
+    QuickfixNext,
+    QuickfixPrev,
+    QuickfixList,
+    Create,
+    Rename,
+    Remove,
+    Copy,
+    Move,
+    Cd,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Args {
+    None,
+    Optional,
+    Required,
+}
+
+// what Tab offers for the argument
+#[derive(Clone, Copy, PartialEq)]
+enum Complete {
+    Nothing,
+    File,
+    Buffer,
+    Option,
+    Topic,
+}
+
+struct Spec {
+    // vim notation: "e[dit]" takes anything from "e" to "edit"
+    pattern: &'static str,
+    cmd: Cmd,
+    bang: bool,
+    args: Args,
+    complete: Complete,
+    help: &'static str,
+}
+
+impl Spec {
+    fn name(&self) -> String {
+        self.pattern.replace(['[', ']'], "")
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        let short = self.pattern.find('[').unwrap_or(self.pattern.len());
+        name.len() >= short && self.name().starts_with(name)
+    }
+}
+
+const fn spec(pattern: &'static str, cmd: Cmd, bang: bool, args: Args, complete: Complete, help: &'static str) -> Spec {
+    Spec { pattern, cmd, bang, args, complete, help }
+}
+
+// several entries with the same Cmd are aliases. the first match wins, so
+// "b" is :buffer and not :bnext
+const COMMANDS: &[Spec] = &[
+    spec("e[dit]", Cmd::Edit, true, Args::Optional, Complete::File, "edit a file, or reload this one"),
+    spec("w[rite]", Cmd::Write, true, Args::Optional, Complete::File, "save, or save as another file"),
+    spec("wq", Cmd::WriteQuit, true, Args::None, Complete::Nothing, "save and quit"),
+    spec("x[it]", Cmd::WriteQuit, true, Args::None, Complete::Nothing, "save and quit"),
+    spec("q[uit]", Cmd::Quit, true, Args::None, Complete::Nothing, "close the window, or quit"),
+    spec("qa[ll]", Cmd::QuitAll, true, Args::None, Complete::Nothing, "quit"),
+    spec("clo[se]", Cmd::Close, false, Args::None, Complete::Nothing, "close the window"),
+    spec("sp[lit]", Cmd::Split, false, Args::Optional, Complete::File, "split the window"),
+    spec("vs[plit]", Cmd::VSplit, false, Args::Optional, Complete::File, "split the window vertically"),
+    spec("bn[ext]", Cmd::BufferNext, false, Args::None, Complete::Nothing, "next buffer"),
+    spec("bp[revious]", Cmd::BufferPrev, false, Args::None, Complete::Nothing, "previous buffer"),
+    spec("ls", Cmd::Buffers, false, Args::None, Complete::Nothing, "list buffers"),
+    spec("buffers", Cmd::Buffers, false, Args::None, Complete::Nothing, "list buffers"),
+    spec("files", Cmd::Buffers, false, Args::None, Complete::Nothing, "list buffers"),
+    spec("bd[elete]", Cmd::BufferDelete, true, Args::Optional, Complete::Buffer, "close a buffer"),
+    spec("b[uffer]", Cmd::Buffer, false, Args::Required, Complete::Buffer, "go to a buffer by number or name"),
+    spec("se[t]", Cmd::Set, false, Args::Required, Complete::Option, "change or show a setting"),
+    spec("noh[lsearch]", Cmd::NoHighlight, false, Args::None, Complete::Nothing, "clear search highlight"),
+    spec("map", Cmd::Map, false, Args::Optional, Complete::Nothing, "bind keys in normal and visual mode"),
+    spec("nm[ap]", Cmd::NMap, false, Args::Optional, Complete::Nothing, "bind keys in normal mode"),
+    spec("im[ap]", Cmd::IMap, false, Args::Optional, Complete::Nothing, "bind keys in insert mode"),
+    spec("vm[ap]", Cmd::VMap, false, Args::Optional, Complete::Nothing, "bind keys in visual mode"),
+    spec("fin[d]", Cmd::Find, false, Args::Optional, Complete::Nothing, "fuzzy find a file in the project"),
+    spec("gr[ep]", Cmd::Grep, false, Args::Required, Complete::Nothing, "search files for a regex: :grep pattern [path]"),
+    spec("cn[ext]", Cmd::QuickfixNext, false, Args::None, Complete::Nothing, "go to the next :grep hit"),
+    spec("cp[revious]", Cmd::QuickfixPrev, false, Args::None, Complete::Nothing, "go to the previous :grep hit"),
+    spec("cope[n]", Cmd::QuickfixList, false, Args::None, Complete::Nothing, "show the :grep hits"),
+    spec("cl[ist]", Cmd::QuickfixList, false, Args::None, Complete::Nothing, "show the :grep hits"),
+    spec("cre[ate]", Cmd::Create, false, Args::Required, Complete::File, "create a file, or a folder ending in /"),
+    spec("ren[ame]", Cmd::Rename, false, Args::Required, Complete::Nothing, "rename the file selected in the sidebar"),
+    spec("rem[ove]", Cmd::Remove, true, Args::None, Complete::Nothing, "delete the selected file (! skips the question)"),
+    spec("cop[y]", Cmd::Copy, false, Args::Required, Complete::File, "copy the selected file or folder"),
+    spec("mov[e]", Cmd::Move, false, Args::Required, Complete::File, "move the selected file or folder"),
+    spec("cd", Cmd::Cd, false, Args::Optional, Complete::File, "make a folder the sidebar root"),
+    spec("h[elp]", Cmd::Help, false, Args::Optional, Complete::Topic, "list commands, or :help keys"),
+];
+
+pub const HELP_TOPICS: &[&str] = &["commands", "keys"];
+
+#[derive(PartialEq, Debug)]
+pub struct ExCommand {
+    pub cmd: Cmd,
+    pub name: String,
+    pub bang: bool,
+    pub arg: String,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Parsed {
+    Empty,
+    Substitute(String),
+    GoToLine(usize),
+    Command(ExCommand),
+}
+
+// a `:` line without the colon
+pub fn parse(line: &str) -> Result<Parsed, String> {
+    let line = line.trim();
+
+    if line.is_empty() {
+        return Ok(Parsed::Empty);
+    }
+    if substitute::is_substitute(line) {
+        return Ok(Parsed::Substitute(line.to_string()));
+    }
+    if let Ok(n) = line.parse::<usize>() {
+        return Ok(Parsed::GoToLine(n));
+    }
+
+    let name_len = line.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(line.len());
+    let (name, rest) = line.split_at(name_len);
+    if name.is_empty() {
+        return Err(format!("Not an editor command: {}", line));
+    }
+
+    let spec = lookup(name).ok_or_else(|| format!("Not an editor command: {}", name))?;
+
+    let (bang, rest) = match rest.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    if bang && !spec.bang {
+        return Err(format!("No ! allowed for :{}", spec.name()));
+    }
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return Err(format!("Trailing characters: {}", rest));
+    }
+
+    let arg = rest.trim();
+    match spec.args {
+        Args::None if !arg.is_empty() => return Err(format!("Trailing characters: {}", arg)),
+        Args::Required if arg.is_empty() => return Err(format!("Argument required for :{}", spec.name())),
+        _ => {},
+    }
+
+    Ok(Parsed::Command(ExCommand { cmd: spec.cmd, name: spec.name(), bang, arg: arg.to_string() }))
+}
+
+fn lookup(name: &str) -> Option<&'static Spec> {
+    COMMANDS.iter().find(|spec| spec.matches(name))
+}
+
+// one line per command for `:help`
+pub fn help_lines() -> Vec<String> {
+    COMMANDS.iter()
+        .map(|spec| {
+            let name = if spec.bang { format!(":{}[!]", spec.pattern) } else { format!(":{}", spec.pattern) };
+            format!("    {:<16} {}", name, spec.help)
+        })
+        .collect()
+}
+
+// every way the `:` line (without the colon) can be completed, each one the
+// whole new line
+pub fn complete(line: &str, current_dir: &Path, buffers: &[String]) -> Vec<String> {
+    let Some(space) = line.find(char::is_whitespace) else {
+        let mut names: Vec<String> = COMMANDS.iter()
+            .map(Spec::name)
+            .filter(|name| name.starts_with(line))
+            .collect();
+        names.dedup();
+        return names;
+    };
+
+    let name = line[..space].trim_end_matches('!');
+    let Some(spec) = lookup(name) else { return Vec::new() };
+
+    let arg = line[space..].trim_start();
+    let base = &line[..line.len() - arg.len()];
+
+    let words: Vec<String> = match spec.complete {
+        Complete::Nothing => Vec::new(),
+        Complete::File => complete_path(arg, current_dir),
+        Complete::Buffer => {
+            let mut matches: Vec<String> = buffers.iter().filter(|b| b.starts_with(arg)).cloned().collect();
+            matches.extend(buffers.iter().filter(|b| !b.starts_with(arg) && b.contains(arg)).cloned());
+            matches
+        },
+        Complete::Option => config::OPTIONS.iter().chain(buffer::OPTIONS)
+            .filter(|option| option.starts_with(arg))
+            .map(|option| option.to_string())
+            .collect(),
+        Complete::Topic => HELP_TOPICS.iter()
+            .filter(|topic| topic.starts_with(arg))
+            .map(|topic| topic.to_string())
+            .collect(),
+    };
+
+    words.into_iter().map(|word| format!("{}{}", base, word)).collect()
+}
+
+// folders come back with a trailing / so Tab can go on into them
+fn complete_path(arg: &str, current_dir: &Path) -> Vec<String> {
+    let (dir_part, prefix) = match arg.rfind('/') {
+        Some(idx) => arg.split_at(idx + 1),
+        None => ("", arg),
+    };
+
+    let dir = if dir_part.is_empty() {
+        current_dir.to_path_buf()
+    } else if Path::new(dir_part).is_absolute() {
+        PathBuf::from(dir_part)
+    } else {
+        current_dir.join(dir_part)
+    };
+
+    let Ok(entries) = fs::read_dir(&dir) else { return Vec::new() };
+
+    let mut names: Vec<String> = entries.flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", dir_part, name, slash))
+        })
+        .collect();
+
+    names.sort();
+    names
+}
+
+const HISTORY_SIZE: usize = 200;
+
+// the `:` lines run before, kept in $XDG_STATE_HOME/reedit/history
+// (~/.local/state/reedit/history) so they survive a restart. the default
+// one has no file and is only kept in memory
+#[derive(Default)]
+pub struct CommandHistory {
+    entries: Vec<String>,
+    path: Option<PathBuf>,
+    index: Option<usize>,
+    draft: String,
+}
+
+impl CommandHistory {
+    pub fn load() -> Self {
+        let path = history_path();
+        let entries = path.as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|text| text.lines().filter(|l| !l.is_empty()).map(String::from).collect())
+            .unwrap_or_default();
+
+        CommandHistory { entries, path, index: None, draft: String::new() }
+    }
+
+    pub fn push(&mut self, line: &str) {
+        self.reset();
+        if line.trim().is_empty() {
+            return;
+        }
+
+        self.entries.retain(|entry| entry != line);
+        self.entries.push(line.to_string());
+        if self.entries.len() > HISTORY_SIZE {
+            self.entries.drain(..self.entries.len() - HISTORY_SIZE);
+        }
+
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                let _ = fs::create_dir_all(dir);
+            }
+            let _ = fs::write(path, self.entries.join("\n") + "\n");
+        }
+    }
+
+    // Up: the previous line that starts with what was typed before browsing
+    pub fn older(&mut self, current: &str) -> Option<String> {
+        if self.index.is_none() {
+            self.draft = current.to_string();
+        }
+
+        let end = self.index.unwrap_or(self.entries.len());
+        let found = self.entries[..end].iter().rposition(|entry| entry.starts_with(&self.draft))?;
+        self.index = Some(found);
+        Some(self.entries[found].clone())
+    }
+
+    // Down: the next one, or back to the typed text
+    pub fn newer(&mut self) -> Option<String> {
+        let start = self.index? + 1;
+
+        match self.entries[start..].iter().position(|entry| entry.starts_with(&self.draft)) {
+            Some(offset) => {
+                self.index = Some(start + offset);
+                Some(self.entries[start + offset].clone())
+            },
+            None => {
+                self.index = None;
+                Some(self.draft.clone())
+            },
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.index = None;
+        self.draft.clear();
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    Some(state_dir()?.join("history"))
+}
+
+// where the history and swap files live
+pub fn state_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state")))?;
+
+    Some(base.join("reedit"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn command(line: &str) -> ExCommand {
+        match parse(line) {
+            Ok(Parsed::Command(command)) => command,
+            other => panic!("{}: {:?}", line, other),
+        }
+    }
+
+    fn cmd(line: &str) -> Cmd {
+        command(line).cmd
+    }
+
+    #[test]
+    fn names_can_be_cut_short() {
+        for name in ["e", "ed", "edi", "edit"] {
+            assert_eq!(cmd(name), Cmd::Edit);
+        }
+        assert_eq!(command("e").name, "edit");
+        assert_eq!(cmd("w"), Cmd::Write);
+        assert_eq!(cmd("wq"), Cmd::WriteQuit);
+        assert_eq!(cmd("x"), Cmd::WriteQuit);
+        assert_eq!(cmd("q"), Cmd::Quit);
+        assert_eq!(cmd("qa"), Cmd::QuitAll);
+        assert_eq!(cmd("b 2"), Cmd::Buffer);
+        assert_eq!(cmd("bn"), Cmd::BufferNext);
+        assert_eq!(cmd("cop x"), Cmd::Copy);
+        assert_eq!(cmd("cope"), Cmd::QuickfixList);
+        assert_eq!(cmd("ls"), Cmd::Buffers);
+
+        assert_eq!(parse("edits"), Err("Not an editor command: edits".to_string()));
+        assert_eq!(parse("cl"), Ok(Parsed::Command(ExCommand { cmd: Cmd::QuickfixList, name: "clist".to_string(), bang: false, arg: String::new() })));
+        assert_eq!(parse("c"), Err("Not an editor command: c".to_string()));
+        assert_eq!(parse("!ls"), Err("Not an editor command: !ls".to_string()));
+    }
+
+    #[test]
+    fn other_lines() {
+        assert_eq!(parse("  "), Ok(Parsed::Empty));
+        assert_eq!(parse("42"), Ok(Parsed::GoToLine(42)));
+        assert_eq!(parse("%s/a/b/g"), Ok(Parsed::Substitute("%s/a/b/g".to_string())));
+        assert_eq!(parse("'<,'>s/a/b/"), Ok(Parsed::Substitute("'<,'>s/a/b/".to_string())));
+    }
+
+    #[test]
+    fn bang_and_arguments() {
+        let write = command("w! other file.txt ");
+        assert!(write.bang);
+        assert_eq!(write.arg, "other file.txt");
+        assert!(command("q!").bang);
+        assert!(!command("q").bang);
+        assert!(command("e!").bang);
+
+        assert_eq!(parse("sp!"), Err("No ! allowed for :split".to_string()));
+        assert_eq!(parse("q foo"), Err("Trailing characters: foo".to_string()));
+        assert_eq!(parse("w!foo"), Err("Trailing characters: foo".to_string()));
+        assert_eq!(parse("q!!"), Err("Trailing characters: !".to_string()));
+        assert_eq!(parse("b"), Err("Argument required for :buffer".to_string()));
+        assert_eq!(parse("set"), Err("Argument required for :set".to_string()));
+    }
+
+    #[test]
+    fn command_names_complete() {
+        assert_eq!(complete("vs", Path::new("."), &[]), ["vsplit"]);
+        assert_eq!(complete("b", Path::new("."), &[]), ["bnext", "bprevious", "buffers", "bdelete", "buffer"]);
+        assert_eq!(complete("h k", Path::new("."), &[]), ["h keys"]);
+        assert_eq!(complete("se tab", Path::new("."), &[]), ["se tab_width"]);
+        assert_eq!(complete("set fileenc", Path::new("."), &[]), ["set fileencoding"]);
+        assert!(complete("q ", Path::new("."), &[]).is_empty());
+        assert!(complete("nosuch x", Path::new("."), &[]).is_empty());
+    }
+
+    #[test]
+    fn paths_complete() {
+        let dir = TempDir::new("complete");
+        fs::write(dir.join("alpha.txt"), "").unwrap();
+        fs::write(dir.join("beta"), "").unwrap();
+        fs::write(dir.join(".hidden"), "").unwrap();
+        fs::create_dir(dir.join("alps")).unwrap();
+        fs::write(dir.join("alps/inner.rs"), "").unwrap();
+
+        let complete = |line| complete(line, dir.path(), &[]);
+        assert_eq!(complete("e al"), ["e alpha.txt", "e alps/"]);
+        assert_eq!(complete("e  "), ["e  alpha.txt", "e  alps/", "e  beta"]);
+        assert_eq!(complete("w! alps/"), ["w! alps/inner.rs"]);
+        assert_eq!(complete("e ."), ["e .hidden"]);
+        assert!(complete("e nothing/").is_empty());
+
+        let absolute = format!("sp {}/be", dir.path().display());
+        assert_eq!(complete(&absolute), [format!("sp {}/beta", dir.path().display())]);
+    }
+
+    #[test]
+    fn buffer_names_complete() {
+        let buffers = ["main.rs", "src/main.rs", "lib.rs", "Makefile"].map(String::from);
+        assert_eq!(complete("b ma", Path::new("."), &buffers), ["b main.rs", "b src/main.rs"]);
+        assert_eq!(complete("bd! lib", Path::new("."), &buffers), ["bd! lib.rs"]);
+        assert_eq!(complete("b ", Path::new("."), &buffers).len(), 4);
+    }
+}
@@ -0,0 +1,460 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use crossterm::style::Color;
+use toml::{Table, Value};
+use crate::clipboard;
+
+// per filetype overrides, from [filetype.<name>] in the config file
+#[derive(Default, Clone)]
+pub struct FiletypeConfig {
+    pub tab_width: Option<usize>,
+    pub auto_pairs: Option<Vec<(char, char)>>,
+}
+
+// the language server of a filetype, from [lsp.<name>] in the config file
+#[derive(Default, Clone)]
+pub struct LspConfig {
+    pub command: String,
+    pub args: Vec<String>,
+}
+
+pub struct Config {
+    pub sidebar_width: f32,
+    pub tab_width: usize,
+    pub auto_pairs: Vec<(char, char)>,
+    pub insert_color: Color,
+    pub command_color: Color,
+    pub visual_color: Color,
+    pub clipboard: String,
+    pub key_timeout: u64,
+    pub show_hidden: bool,
+    // [keys.<mode>] tables: (mode, keys, action or keys)
+    pub keys: Vec<(String, String, String)>,
+    pub filetypes: HashMap<String, FiletypeConfig>,
+    pub lsp: HashMap<String, LspConfig>,
+}
+
+// what `:set` knows about, besides filetype.<name>.tab_width and auto_pairs
+// and lsp.<name>.command and args
+pub const OPTIONS: &[&str] = &[
+    "sidebar_width",
+    "tab_width",
+    "auto_pairs",
+    "colors.insert",
+    "colors.command",
+    "colors.visual",
+    "clipboard",
+    "key_timeout",
+    "show_hidden",
+];
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            sidebar_width: 0.1,
+            tab_width: 4,
+            auto_pairs: vec![('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')],
+            insert_color: Color::Green,
+            command_color: Color::Red,
+            visual_color: Color::Blue,
+            clipboard: "auto".to_string(),
+            key_timeout: 1000,
+            show_hidden: true,
+            keys: Vec::new(),
+            filetypes: HashMap::new(),
+            lsp: HashMap::new(),
+        }
+    }
+}
+
+impl Config {
+    // ~/.config/reedit/config.toml, or $XDG_CONFIG_HOME/reedit/config.toml.
+    // a missing file just gives the defaults, bad values are skipped and
+    // reported back so they can be shown in the status line
+    pub fn load() -> (Self, Vec<String>) {
+        let Some(path) = config_path() else { return (Config::default(), Vec::new()) };
+        let Ok(text) = fs::read_to_string(&path) else { return (Config::default(), Vec::new()) };
+
+        Config::parse(&text, &path.display().to_string())
+    }
+
+    // the text of a config file, `source` names it in a syntax error
+    fn parse(text: &str, source: &str) -> (Self, Vec<String>) {
+        let mut config = Config::default();
+        let mut errors = Vec::new();
+
+        match text.parse::<Table>() {
+            Ok(table) => config.apply_table("", &table, &mut errors),
+            Err(err) => errors.push(format!("{}: {}", source, err.message())),
+        }
+
+        (config, errors)
+    }
+
+    fn apply_table(&mut self, prefix: &str, table: &Table, errors: &mut Vec<String>) {
+        for (key, value) in table {
+            let key = format!("{}{}", prefix, key);
+            match value {
+                // key names like "<C-.>" can't be flattened into dotted keys
+                Value::Table(modes) if key == "keys" => {
+                    for (mode, bindings) in modes {
+                        let Value::Table(bindings) = bindings else {
+                            errors.push(format!("keys.{}: expected a table", mode));
+                            continue;
+                        };
+                        for (lhs, rhs) in bindings {
+                            match rhs.as_str() {
+                                Some(rhs) => self.keys.push((mode.clone(), lhs.clone(), rhs.to_string())),
+                                None => errors.push(format!("keys.{}.{}: expected a string", mode, lhs)),
+                            }
+                        }
+                    }
+                },
+                Value::Table(inner) => self.apply_table(&format!("{}.", key), inner, errors),
+                _ => {
+                    if let Err(err) = self.set_value(&key, value) {
+                        errors.push(err);
+                    }
+                },
+            }
+        }
+    }
+
+    // `:set key=value`. the value is read as toml, so bare words are taken
+    // as strings: `:set colors.insert=cyan`
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let value = match format!("v = {}", value).parse::<Table>() {
+            Ok(mut table) => table.remove("v").unwrap(),
+            Err(_) => Value::String(value.to_string()),
+        };
+
+        self.set_value(key, &value)
+    }
+
+    pub fn set_value(&mut self, key: &str, value: &Value) -> Result<(), String> {
+        if let Some(rest) = key.strip_prefix("filetype.") {
+            let (filetype, option) = rest.split_once('.')
+                .ok_or_else(|| format!("Unknown option: {}", key))?;
+            let overrides = self.filetypes.entry(filetype.to_string()).or_default();
+
+            match option {
+                "tab_width" => overrides.tab_width = Some(parse_tab_width(key, value)?),
+                "auto_pairs" => overrides.auto_pairs = Some(parse_pairs(key, value)?),
+                _ => return Err(format!("Unknown option: {}", key)),
+            }
+            return Ok(());
+        }
+
+        if let Some(rest) = key.strip_prefix("lsp.") {
+            let (filetype, option) = rest.split_once('.')
+                .ok_or_else(|| format!("Unknown option: {}", key))?;
+
+            match option {
+                "command" => {
+                    let command = value.as_str().ok_or_else(|| format!("{}: expected a string", key))?;
+                    self.lsp.entry(filetype.to_string()).or_default().command = command.to_string();
+                },
+                "args" => {
+                    let args = value.as_array()
+                        .and_then(|args| args.iter().map(|arg| arg.as_str().map(String::from)).collect::<Option<Vec<_>>>())
+                        .ok_or_else(|| format!("{}: expected a list of strings", key))?;
+                    self.lsp.entry(filetype.to_string()).or_default().args = args;
+                },
+                _ => return Err(format!("Unknown option: {}", key)),
+            }
+            return Ok(());
+        }
+
+        match key {
+            "sidebar_width" => {
+                let width = match value {
+                    Value::Float(f) => *f as f32,
+                    Value::Integer(i) => *i as f32,
+                    _ => return Err(format!("{}: expected a number", key)),
+                };
+                if !(0.0..=0.5).contains(&width) {
+                    return Err(format!("{}: {} is out of range (0.0 to 0.5)", key, width));
+                }
+                self.sidebar_width = width;
+            },
+            "tab_width" => self.tab_width = parse_tab_width(key, value)?,
+            "auto_pairs" => self.auto_pairs = parse_pairs(key, value)?,
+            "colors.insert" => self.insert_color = parse_color(key, value)?,
+            "colors.command" => self.command_color = parse_color(key, value)?,
+            "colors.visual" => self.visual_color = parse_color(key, value)?,
+            "key_timeout" => match value {
+                Value::Integer(ms) if (0..=10000).contains(ms) => self.key_timeout = *ms as u64,
+                Value::Integer(ms) => return Err(format!("{}: {} is out of range (0 to 10000)", key, ms)),
+                _ => return Err(format!("{}: expected a number of milliseconds", key)),
+            },
+            "show_hidden" => match value {
+                Value::Boolean(show) => self.show_hidden = *show,
+                _ => return Err(format!("{}: expected true or false", key)),
+            },
+            "clipboard" => match value.as_str() {
+                Some(name) if clipboard::PROVIDERS.contains(&name) => self.clipboard = name.to_string(),
+                _ => return Err(format!("{}: expected one of {}", key, clipboard::PROVIDERS.join(", "))),
+            },
+            _ => return Err(format!("Unknown option: {}", key)),
+        }
+
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        if let Some(rest) = key.strip_prefix("filetype.") {
+            let (filetype, option) = rest.split_once('.')?;
+            let overrides = self.filetypes.get(filetype).cloned().unwrap_or_default();
+            return match option {
+                "tab_width" => Some(self.tab_width(filetype).to_string()),
+                "auto_pairs" => Some(format_pairs(overrides.auto_pairs.as_ref().unwrap_or(&self.auto_pairs))),
+                _ => None,
+            };
+        }
+
+        if let Some(rest) = key.strip_prefix("lsp.") {
+            let (filetype, option) = rest.split_once('.')?;
+            let server = self.lsp.get(filetype).cloned().unwrap_or_default();
+            return match option {
+                "command" => Some(server.command),
+                "args" => Some(server.args.join(" ")),
+                _ => None,
+            };
+        }
+
+        match key {
+            "sidebar_width" => Some(self.sidebar_width.to_string()),
+            "tab_width" => Some(self.tab_width.to_string()),
+            "auto_pairs" => Some(format_pairs(&self.auto_pairs)),
+            "colors.insert" => Some(format!("{:?}", self.insert_color)),
+            "colors.command" => Some(format!("{:?}", self.command_color)),
+            "colors.visual" => Some(format!("{:?}", self.visual_color)),
+            "clipboard" => Some(self.clipboard.clone()),
+            "key_timeout" => Some(self.key_timeout.to_string()),
+            "show_hidden" => Some(self.show_hidden.to_string()),
+            _ => None,
+        }
+    }
+
+    pub fn tab_width(&self, filetype: &str) -> usize {
+        self.filetypes.get(filetype)
+            .and_then(|f| f.tab_width)
+            .unwrap_or(self.tab_width)
+    }
+
+    // the server of a filetype, when one is set up
+    pub fn lsp(&self, filetype: &str) -> Option<&LspConfig> {
+        self.lsp.get(filetype).filter(|server| !server.command.is_empty())
+    }
+
+    pub fn auto_pairs(&self, filetype: &str) -> &[(char, char)] {
+        self.filetypes.get(filetype)
+            .and_then(|f| f.auto_pairs.as_deref())
+            .unwrap_or(&self.auto_pairs)
+    }
+}
+
+fn config_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+    Some(base.join("reedit").join("config.toml"))
+}
+
+fn parse_tab_width(key: &str, value: &Value) -> Result<usize, String> {
+    match value {
+        Value::Integer(i) if (1..=16).contains(i) => Ok(*i as usize),
+        Value::Integer(i) => Err(format!("{}: {} is out of range (1 to 16)", key, i)),
+        _ => Err(format!("{}: expected a number", key)),
+    }
+}
+
+// ["()", "[]", "\"\""], each entry an opening and a closing char
+fn parse_pairs(key: &str, value: &Value) -> Result<Vec<(char, char)>, String> {
+    let entries: Vec<&str> = match value {
+        Value::Array(items) => items.iter()
+            .map(|item| item.as_str().ok_or_else(|| format!("{}: expected a list of strings", key)))
+            .collect::<Result<_, _>>()?,
+        Value::String(s) => s.split(',').collect(),
+        _ => return Err(format!("{}: expected a list of strings", key)),
+    };
+
+    entries.into_iter()
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let mut chars = entry.chars();
+            match (chars.next(), chars.next(), chars.next()) {
+                (Some(open), Some(close), None) => Ok((open, close)),
+                _ => Err(format!("{}: \"{}\" is not a pair of chars", key, entry)),
+            }
+        })
+        .collect()
+}
+
+fn format_pairs(pairs: &[(char, char)]) -> String {
+    pairs.iter()
+        .map(|(open, close)| format!("{}{}", open, close))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// color names as crossterm knows them (red, dark_grey, ...) or #rrggbb
+fn parse_color(key: &str, value: &Value) -> Result<Color, String> {
+    let name = value.as_str().ok_or_else(|| format!("{}: expected a color name", key))?;
+
+    if let Some(hex) = name.strip_prefix('#')
+        && hex.len() == 6
+        && let Ok(rgb) = u32::from_str_radix(hex, 16)
+    {
+        return Ok(Color::Rgb { r: (rgb >> 16) as u8, g: (rgb >> 8) as u8, b: rgb as u8 });
+    }
+
+    Color::try_from(name).map_err(|_| format!("{}: unknown color {}", key, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> (Config, Vec<String>) {
+        Config::parse(text, "config.toml")
+    }
+
+    #[test]
+    fn a_full_file() {
+        let (config, errors) = parse(r##"
+            sidebar_width = 0.25
+            tab_width = 2
+            auto_pairs = ["()", "<>"]
+            clipboard = "osc52"
+            key_timeout = 300
+            show_hidden = false
+
+            [colors]
+            insert = "cyan"
+            visual = "#102030"
+
+            [keys.normal]
+            "<C-s>" = "file.save"
+            "<C-.>" = "<C-w>v"
+
+            [lsp.rust]
+            command = "rust-analyzer"
+            args = ["--stdio"]
+        "##);
+        assert_eq!(errors, Vec::<String>::new());
+        assert_eq!(config.sidebar_width, 0.25);
+        assert_eq!(config.tab_width, 2);
+        assert_eq!(config.auto_pairs, [('(', ')'), ('<', '>')]);
+        assert_eq!(config.clipboard, "osc52");
+        assert_eq!(config.key_timeout, 300);
+        assert!(!config.show_hidden);
+        assert_eq!(config.insert_color, Color::Cyan);
+        assert_eq!(config.visual_color, Color::Rgb { r: 0x10, g: 0x20, b: 0x30 });
+        assert_eq!(config.command_color, Color::Red);
+        assert_eq!(config.keys, [
+            ("normal".to_string(), "<C-.>".to_string(), "<C-w>v".to_string()),
+            ("normal".to_string(), "<C-s>".to_string(), "file.save".to_string()),
+        ]);
+        let server = config.lsp("rust").unwrap();
+        assert_eq!((server.command.as_str(), server.args.as_slice()), ("rust-analyzer", ["--stdio".to_string()].as_slice()));
+        assert!(config.lsp("python").is_none());
+    }
+
+    #[test]
+    fn filetypes_override_the_defaults() {
+        let (config, errors) = parse(r#"
+            tab_width = 8
+
+            [filetype.python]
+            tab_width = 2
+
+            [filetype.markdown]
+            auto_pairs = "**,``"
+        "#);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(config.tab_width("python"), 2);
+        assert_eq!(config.tab_width("rust"), 8);
+        assert_eq!(config.auto_pairs("markdown"), [('*', '*'), ('`', '`')]);
+        assert_eq!(config.auto_pairs("rust"), Config::default().auto_pairs);
+        assert_eq!(config.get("filetype.python.tab_width").as_deref(), Some("2"));
+        assert_eq!(config.get("filetype.markdown.auto_pairs").as_deref(), Some("**,``"));
+    }
+
+    #[test]
+    fn bad_values_are_skipped_and_reported() {
+        let (config, errors) = parse(r#"
+            tab_width = "four"
+            sidebar_width = 0.9
+            key_timeout = -1
+            show_hidden = "yes"
+            clipboard = "paper"
+            colour = "red"
+            auto_pairs = ["(", "[]"]
+
+            [colors]
+            insert = "mauve"
+
+            [filetype.python]
+            indent = 2
+
+            [keys]
+            normal = "x"
+
+            [keys.insert]
+            jk = 1
+        "#);
+        assert_eq!(errors, [
+            "auto_pairs: \"(\" is not a pair of chars",
+            "clipboard: expected one of auto, wl-copy, xclip, xsel, pbcopy, osc52, none",
+            "colors.insert: unknown color mauve",
+            "Unknown option: colour",
+            "Unknown option: filetype.python.indent",
+            "key_timeout: -1 is out of range (0 to 10000)",
+            "keys.insert.jk: expected a string",
+            "keys.normal: expected a table",
+            "show_hidden: expected true or false",
+            "sidebar_width: 0.9 is out of range (0.0 to 0.5)",
+            "tab_width: expected a number",
+        ].map(String::from));
+
+        // and the rest keeps its default
+        let default = Config::default();
+        assert_eq!((config.tab_width, config.sidebar_width, config.key_timeout), (default.tab_width, default.sidebar_width, default.key_timeout));
+        assert_eq!(config.insert_color, default.insert_color);
+    }
+
+    #[test]
+    fn syntax_errors_name_the_file() {
+        let (config, errors) = parse("tab_width = ");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("config.toml: "), "{}", errors[0]);
+        assert_eq!(config.tab_width, Config::default().tab_width);
+    }
+
+    #[test]
+    fn set_reads_words_as_strings() {
+        let mut config = Config::default();
+        config.set("colors.insert", "cyan").unwrap();
+        config.set("tab_width", "3").unwrap();
+        config.set("show_hidden", "false").unwrap();
+        config.set("auto_pairs", "(),[]").unwrap();
+        config.set("filetype.go.tab_width", "8").unwrap();
+        assert_eq!(config.insert_color, Color::Cyan);
+        assert_eq!(config.tab_width("rust"), 3);
+        assert_eq!(config.tab_width("go"), 8);
+        assert!(!config.show_hidden);
+        assert_eq!(config.get("auto_pairs").as_deref(), Some("(),[]"));
+
+        assert_eq!(config.set("tab_width", "0"), Err("tab_width: 0 is out of range (1 to 16)".to_string()));
+        assert_eq!(config.set("tab_width", "wide"), Err("tab_width: expected a number".to_string()));
+        assert_eq!(config.set("key_timeout", "1.5"), Err("key_timeout: expected a number of milliseconds".to_string()));
+        assert_eq!(config.set("nope", "1"), Err("Unknown option: nope".to_string()));
+        assert_eq!(config.set("filetype.go", "1"), Err("Unknown option: filetype.go".to_string()));
+        assert_eq!(config.set("lsp.go.args", "gopls"), Err("lsp.go.args: expected a list of strings".to_string()));
+        assert_eq!(config.tab_width, 3);
+    }
+}
@@ -1,13 +1,44 @@
+use std::collections::HashSet;
 use std::fs::{self, File};
 use std::env;
-use std::io::{self, BufReader, BufRead, Write, stdout};
+use std::io::{self, Write, stdout};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use crossterm::cursor::MoveTo;
-use crossterm::style::{Print, ResetColor, SetForegroundColor, SetBackgroundColor};
+use crossterm::event::{KeyCode, KeyModifiers};
+use crossterm::style::{Attribute, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor};
 use crossterm::*;
 use crossterm::terminal::{self, Clear, ClearType};
-
-const SIDEBAR: f32 = 0.1;
+use crate::buffer::{self, Buffer, DiskState, Encoding, Format, LineEnding};
+use crate::clipboard;
+use crate::command::{self, Cmd, CommandHistory, ExCommand, Parsed};
+use crate::config::Config;
+use crate::file_tree::{self, FileTree};
+use crate::filetype;
+use crate::finder::Finder;
+use crate::git::{Diff, FileStatus, HunkKind, RepoStatus};
+use crate::grep::{self, Quickfix};
+use crate::history::Snapshot;
+use crate::keymap::{self, Input, Key, Keymap, MapMode};
+use crate::lsp::{self, CompletionItem, CompletionMenu, Location, Lsp, Severity};
+use crate::normal::{self, Action, Command, Operator, Parse, Target};
+use crate::registers::{self, Register, RegisterKind, Registers};
+use crate::search::{self, Search};
+use crate::substitute::{self, Substitution};
+use crate::swap::{self, Swap};
+use crate::syntax::{self, Highlighter};
+use crate::text_buffer::TextBuffer;
+use crate::unicode::{char_to_grapheme, clip_width, display_width, grapheme_count, grapheme_to_byte, grapheme_to_char, screen_col};
+use crate::window::{Direction, Layout, Rect, Window};
+
+// rows of the hover and completion popups
+const POPUP_HEIGHT: usize = 10;
+
+// how long edits wait before they go to the swap files
+const SWAP_DELAY: Duration = Duration::from_secs(2);
+
+// how often open files are checked for changes by other programs
+pub const FILE_CHECK: Duration = Duration::from_secs(1);
 
 pub enum Mode {
     Insert,
@@ -15,55 +46,166 @@ pub enum Mode {
     Visual,
 }
 
+// a yes / no question in the status line, answered by the next key
+pub enum Prompt {
+    Delete(PathBuf),
+    // the swap file of the current buffer
+    Recover(PathBuf),
+    // a buffer with changes whose file was changed by another program
+    Conflict(usize),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum VisualKind {
+    Char,
+    Line,
+    Block,
+}
+
 pub struct Editor {
-    pub content: Vec<String>,
+    pub buffers: Vec<Buffer>,
+    pub current: usize,
     pub cursor_l: usize,
     pub cursor_c: usize,
-    pub file_path: String,
     pub mode: Mode,
     pub status_message: String,
     pub command: String,
 
     pub current_dir: PathBuf,
-    pub files: Vec<String>,
+    pub tree: FileTree,
+    // None outside of a git repo
+    pub git_status: Option<RepoStatus>,
     pub file_cursor: usize,
 
     pub scroll_offset: usize,
     pub sidebar_scroll: usize,
 
-    pub clipboard: Option<String>,
-    pub visual_start: Option<usize>,
+    pub registers: Registers,
+    pub register: Option<char>,
+    pub visual_start: Option<(usize, usize)>,
+    pub visual_kind: VisualKind,
+    pub block_insert: Option<(usize, usize, Vec<usize>)>,
+
+    pub windows: Vec<Window>,
+    pub layout: Layout,
+    pub focus: usize,
+
+    pub search: Option<Search>,
+    pub search_origin: Option<(usize, usize)>,
+
+    pub last_visual: Option<(usize, usize)>,
+    pub pending_substitute: Option<Substitution>,
+
+    pub config: Config,
+
+    pub pending_keys: String,
+    pub last_change: Option<Action>,
+    pub last_insert: String,
+    pub insert_log: Option<String>,
+
+    pub keymap: Keymap,
+    pub pending_input: Vec<Key>,
+    // a list shown over the text area (:help keys) and its scroll
+    pub pager: Option<(Vec<String>, usize)>,
+    pub finder: Option<Finder>,
+    pub sidebar_hidden: bool,
+    pub prompt: Option<Prompt>,
+    pub quickfix: Quickfix,
+    pub lsp: Lsp,
+    // the K popup, closed by the next key
+    pub hover: Option<Vec<String>>,
+    pub completion_menu: Option<CompletionMenu>,
+    last_swap: Instant,
+    last_file_check: Instant,
+    // whether the editor that wrote a swap file still runs
+    pub pid_alive: fn(u32) -> bool,
+
+    pub command_history: CommandHistory,
+    // Tab candidates for the `:` line and the one shown
+    pub completion: Option<(Vec<String>, usize)>,
 }
 
 impl Editor {
     pub fn new(file_path: &str) -> Self {
-       
-        let content = if file_path == "." {
-            vec![String::new()]
-        } else if Path::new(file_path).exists() {
-            read_file(file_path)
-        } else {
-            vec![String::new()]
-        };
+        let (config, mut errors) = Config::load();
+        let mut keymap = Keymap::new();
+
+        for (mode, lhs, rhs) in &config.keys {
+            let result = match mode.as_str() {
+                "normal" => keymap.map(&[MapMode::Normal], lhs, rhs),
+                "insert" => keymap.map(&[MapMode::Insert], lhs, rhs),
+                "visual" => keymap.map(&[MapMode::Visual], lhs, rhs),
+                _ => Err(format!("unknown mode {}", mode)),
+            };
+            if let Err(err) = result {
+                errors.push(format!("keys.{}.{}: {}", mode, lhs, err));
+            }
+        }
 
         let current_dir = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
-        let files = read_dir_files(&current_dir);
+        let registers = Registers::new(clipboard::provider(&config.clipboard));
+        let mut editor = Editor::with_config(config, keymap, registers, Buffer::new(file_path), current_dir);
+
+        editor.tree = FileTree::new(&editor.current_dir, editor.config.show_hidden);
+        editor.git_status = RepoStatus::load(&editor.current_dir);
+        editor.command_history = CommandHistory::load();
+        if !errors.is_empty() {
+            editor.status_message = format!("config: {}", errors.join("; "));
+        }
+
+        editor.check_swap();
+        editor
+    }
 
+    // an editor on `buffer` that reads nothing else from disk: no sidebar
+    // tree, git status or command history until they're loaded
+    pub fn with_config(config: Config, keymap: Keymap, registers: Registers, buffer: Buffer, current_dir: PathBuf) -> Self {
         Editor {
-            content,
+            buffers: vec![buffer],
+            current: 0,
             cursor_l: 0,
             cursor_c: 0,
-            file_path: file_path.to_string(),
             mode: Mode::Command,
             status_message: String::new(),
             command: String::new(),
+            lsp: Lsp::new(&current_dir),
             current_dir,
-            files,
+            tree: FileTree::default(),
+            git_status: None,
             file_cursor: 0,
             scroll_offset: 0,
             sidebar_scroll: 0,
-            clipboard: None,
+            registers,
+            register: None,
             visual_start: None,
+            visual_kind: VisualKind::Char,
+            block_insert: None,
+            windows: vec![Window { buffer: 0, cursor_l: 0, cursor_c: 0, scroll_offset: 0 }],
+            layout: Layout::Window(0),
+            focus: 0,
+            search: None,
+            search_origin: None,
+            last_visual: None,
+            pending_substitute: None,
+            config,
+            pending_keys: String::new(),
+            last_change: None,
+            last_insert: String::new(),
+            insert_log: None,
+            keymap,
+            pending_input: Vec::new(),
+            pager: None,
+            finder: None,
+            sidebar_hidden: false,
+            prompt: None,
+            quickfix: Quickfix::default(),
+            hover: None,
+            completion_menu: None,
+            last_swap: Instant::now(),
+            last_file_check: Instant::now(),
+            pid_alive: swap::is_alive,
+            command_history: CommandHistory::default(),
+            completion: None,
         }
     }
 
@@ -72,53 +214,42 @@ impl Editor {
 
         let (cols, rows) = terminal::size().unwrap();
 
-        let sidebar_width = (SIDEBAR * cols as f32).floor() as u16;
+        let sidebar_width = self.sidebar_cols(cols);
 
         let cabecalho1 = "-".repeat(cols.into());
         let cabecalho2 = "-".repeat((cols - sidebar_width).into());
         
-        let available_rows = (rows - 8) as usize;
-
         let mode_label = match self.mode {
             Mode::Insert => "-- INSERT --",
             Mode::Command => "-- COMMAND --",
-            Mode::Visual => "-- VISUAL --",
+            Mode::Visual => match self.visual_kind {
+                VisualKind::Char => "-- VISUAL --",
+                VisualKind::Line => "-- VISUAL LINE --",
+                VisualKind::Block => "-- VISUAL BLOCK --",
+            },
         };
 
         let status_color = match self.mode {
-            Mode::Command => style::Color::Red,
-            Mode::Insert => style::Color::Green,
-            Mode::Visual => style::Color::Blue,
+            Mode::Command => self.config.command_color,
+            Mode::Insert => self.config.insert_color,
+            Mode::Visual => self.config.visual_color,
         };
 
-        let file_name = if self.file_path == "." {
-            String::from("Empty File")
-        } else {
-            relative_path(&self.current_dir, &self.file_path)
-        };
+        let file_name = self.buffer_name(self.buf());
 
-        let icon = if self.file_path == "." {
-            "📄"
-        } else {
-            file_icon(&self.file_path)
-        };
+        let modified = if self.buf().modified { " [+]" } else { "" };
 
-        let (start, end) = match self.visual_start {
-            Some(start) if matches!(self.mode, Mode::Visual) => {
-                if start <= self.cursor_l {
-                    (start, self.cursor_l)
-                } else {
-                    (self.cursor_l, start)
-                }
-            },
-            _ => (0, 0),
-        };
+        let format = self.buf().format;
 
-        let status = format!("{} | {} | ln {} | col {} | {}", 
+        let status = format!("{} | {}{} | ln {} | col {} | {} {} | {}{}", 
             mode_label, 
             file_name, 
+            modified,
             self.cursor_l + 1,
             self.cursor_c + 1,
+            format.encoding.name(),
+            format.line_ending.name(),
+            self.diagnostics_status(),
             self.status_message
         );
 
@@ -132,47 +263,64 @@ impl Editor {
             stdout,
             Print("ReEdit - Terminal Text Editor"),
             MoveTo(0, 1),
-            Print(format!("{cabecalho1}")),
+            Print(cabecalho1),
             MoveTo(sidebar_width, 3),
-            Print(format!("|  < {icon} {file_name} >")),
-            MoveTo(sidebar_width, 4),
-            Print(format!("{cabecalho2}"))
+            Print("|  "),
         ).unwrap();
 
-        for (i, line) in self.content.iter().enumerate().skip(self.scroll_offset).take(available_rows) {
-            let y = (i - self.scroll_offset + 6) as u16;
-            let is_selected = i >= start && i <= end;
+        for (i, buffer) in self.buffers.iter().enumerate() {
+            let icon = if buffer.is_empty_file() {
+                "📄"
+            } else {
+                file_icon(&buffer.file_path)
+            };
+            let name = self.buffer_name(buffer);
+            let modified = if buffer.modified { " [+]" } else { "" };
 
             queue!(
                 stdout,
-                MoveTo(sidebar_width, y),
-                Clear(ClearType::CurrentLine),
-                if is_selected {
-                    SetForegroundColor(style::Color::DarkGrey)
-                } else {
+                if i == self.current {
                     SetForegroundColor(style::Color::White)
+                } else {
+                    SetForegroundColor(style::Color::DarkGrey)
                 },
-                if i < 9 {
-                    Print(format!("   {}| {}", i + 1, line))
-                } else if i < 99 {
-                    Print(format!("  {}| {}", i + 1, line))
-                } else if i < 999 {
-                    Print(format!(" {}| {}", i + 1, line))
+                if i == self.current {
+                    Print(format!("< {icon} {name}{modified} > "))
                 } else {
-                    Print(format!("{}| {}", i + 1, line))
+                    Print(format!("  {icon} {name}{modified}   "))
                 },
                 ResetColor
             ).unwrap();
         }
 
+        queue!(
+            stdout,
+            MoveTo(sidebar_width, 4),
+            Print(cabecalho2)
+        ).unwrap();
+
+        for (id, rect) in self.window_rects() {
+            let window = &self.windows[id];
+            let scroll = if id == self.focus { self.scroll_offset } else { window.scroll_offset };
+            let buffer = &mut self.buffers[window.buffer];
+            buffer.highlighter.prepare(&buffer.content, scroll + rect.height as usize);
+            buffer.diff.refresh_when_due(&buffer.content);
+
+            self.render_window(id, rect);
+        }
+
         self.render_file_browser();
+        self.render_pager();
+        self.render_finder();
+        self.render_quickfix();
+        self.render_popup();
 
         if matches!(self.mode, Mode::Command) {
             queue!(
                 stdout,
                 MoveTo(0, rows - 2),
                 Clear(ClearType::CurrentLine),
-                Print(format!("{}", self.command.to_string()))
+                Print(if self.command.is_empty() { &self.pending_keys } else { &self.command })
             ).unwrap();
         }
         
@@ -190,375 +338,3197 @@ impl Editor {
         stdout.flush().unwrap();
     }
 
-    pub fn render_file_browser(&mut self) {
+    fn render_window(&self, id: usize, rect: Rect) {
         let mut stdout = stdout();
 
-        let (cols, _) = terminal::size().unwrap();
+        let window = &self.windows[id];
+        let content = &self.buffers[window.buffer].content;
+        let highlighter = &self.buffers[window.buffer].highlighter;
+        let diff = &self.buffers[window.buffer].diff;
+        let diagnostics = &self.buffers[window.buffer].diagnostics;
+        let focused = id == self.focus;
 
-        let sidebar_width = (SIDEBAR * cols as f32).floor() as u16;
+        let scroll_offset = if focused {
+            self.scroll_offset
+        } else {
+            window.scroll_offset.min(content.line_count() - 1)
+        };
 
-        queue!(
-            stdout,
-            MoveTo(self.cursor_c as u16 + sidebar_width + 3, (self.cursor_l - self.scroll_offset + 5) as u16)
-        ).unwrap();
+        let last_line = (scroll_offset + rect.height as usize).min(content.line_count());
 
-        for (i, file) in self.files.iter().enumerate() {
-            let path = self.current_dir.join(file);
-            let display_name = truncate_string(file, sidebar_width.saturating_sub(3).into());
-            let icon = if path.is_dir() {
-                folder_icon(file)
-            } else {
-                file_icon(file)
-            };
+        for i in scroll_offset..last_line {
+            let line = content.line(i);
+            let y = rect.y + (i - scroll_offset) as u16;
+            let text = clip_width(&line, (rect.width as usize).saturating_sub(6));
 
             queue!(
                 stdout,
-                MoveTo(0, (i + 4) as u16),
-                if i == self.file_cursor {
-                    SetForegroundColor(style::Color::Green)
+                MoveTo(rect.x, y),
+                SetForegroundColor(style::Color::White),
+                if i < 9 {
+                    Print(format!("   {}| {}", i + 1, text))
+                } else if i < 99 {
+                    Print(format!("  {}| {}", i + 1, text))
+                } else if i < 999 {
+                    Print(format!(" {}| {}", i + 1, text))
                 } else {
-                    SetForegroundColor(style::Color::White)
+                    Print(format!("{}| {}", i + 1, text))
                 },
-                Print(format!("{icon} {display_name}")),
                 ResetColor
             ).unwrap();
+
+            for span in highlighter.spans(i, &line) {
+                let start = span.start.min(text.len());
+                let end = span.end.min(text.len());
+                if start >= end {
+                    continue;
+                }
+
+                queue!(
+                    stdout,
+                    MoveTo(rect.x + 6 + display_width(&text[..start]) as u16, y),
+                    SetForegroundColor(syntax::token_color(span.token)),
+                    Print(&text[start..end]),
+                    ResetColor
+                ).unwrap();
+            }
+
+            if let Some(search) = &self.search {
+                for (start, end) in search::line_matches(text, &search.pattern) {
+                    queue!(
+                        stdout,
+                        MoveTo(rect.x + 6 + display_width(&text[..start]) as u16, y),
+                        SetBackgroundColor(style::Color::Yellow),
+                        SetForegroundColor(style::Color::Black),
+                        Print(&text[start..end]),
+                        ResetColor
+                    ).unwrap();
+                }
+            }
+
+            // the selection is drawn over the syntax colors, with one extra
+            // cell when it takes the line break too
+            if focused && let Some((start, end, newline)) = self.selected_cols(i) {
+                let start = grapheme_to_byte(text, start);
+                let end = grapheme_to_byte(text, end);
+                let tail = if newline && end == line.len() { " " } else { "" };
+
+                queue!(
+                    stdout,
+                    MoveTo(rect.x + 6 + display_width(&text[..start]) as u16, y),
+                    SetBackgroundColor(style::Color::DarkGrey),
+                    SetForegroundColor(style::Color::White),
+                    Print(format!("{}{}", &text[start..end], tail)),
+                    ResetColor
+                ).unwrap();
+            }
+
+            // diagnostics underline their text and color the line number
+            // they start on
+            let mut worst: Option<Severity> = None;
+            for diagnostic in diagnostics.iter().filter(|d| d.start.0 <= i && i <= d.end.0) {
+                if diagnostic.start.0 == i {
+                    worst = Some(worst.map_or(diagnostic.severity, |w| w.min(diagnostic.severity)));
+                }
+
+                let start = if diagnostic.start.0 == i { lsp::char_col(&line, diagnostic.start.1) } else { 0 };
+                let end = if diagnostic.end.0 == i { lsp::char_col(&line, diagnostic.end.1) } else { usize::MAX };
+                let byte = |idx: usize| text.char_indices().nth(idx).map_or(text.len(), |(b, _)| b);
+                let (start, end) = (byte(start), byte(end.max(start + 1)));
+                if start >= end {
+                    continue;
+                }
+
+                queue!(
+                    stdout,
+                    MoveTo(rect.x + 6 + display_width(&text[..start]) as u16, y),
+                    SetForegroundColor(severity_color(diagnostic.severity)),
+                    SetAttribute(Attribute::Underlined),
+                    Print(&text[start..end]),
+                    SetAttribute(Attribute::NoUnderline),
+                    ResetColor
+                ).unwrap();
+            }
+
+            if let Some(severity) = worst {
+                queue!(
+                    stdout,
+                    MoveTo(rect.x, y),
+                    SetForegroundColor(severity_color(severity)),
+                    Print(format!("{:>4}", i + 1)),
+                    ResetColor
+                ).unwrap();
+            }
+
+            // changes against HEAD take the place of the bar after the number
+            if let Some((kind, top)) = diff.marker(i) {
+                let (mark, color) = match kind {
+                    HunkKind::Added => ("┃", style::Color::Green),
+                    HunkKind::Changed => ("┃", style::Color::Yellow),
+                    HunkKind::Deleted if top => ("‾", style::Color::Red),
+                    HunkKind::Deleted => ("_", style::Color::Red),
+                };
+
+                queue!(
+                    stdout,
+                    MoveTo(rect.x + 4, y),
+                    SetForegroundColor(color),
+                    Print(mark),
+                    ResetColor
+                ).unwrap();
+            }
         }
 
-        queue!(
-            stdout,
-            MoveTo(0, 2),
-            Print(format!("📁 {}", self.current_dir.display()))
-        ).unwrap();
+        let area = self.text_area();
+
+        if rect.y + rect.height < area.y + area.height {
+            let label = format!("-- {} ", self.buffer_name(&self.buffers[window.buffer]));
+            let label = clip_width(&label, rect.width as usize);
+            let fill = "-".repeat((rect.width as usize).saturating_sub(display_width(label)));
 
-        for y in 0..self.files.len() as u16 {
             queue!(
                 stdout,
-                MoveTo(sidebar_width, y + 4),
-                Print("|")
+                MoveTo(rect.x, rect.y + rect.height),
+                if focused {
+                    SetForegroundColor(style::Color::White)
+                } else {
+                    SetForegroundColor(style::Color::DarkGrey)
+                },
+                Print(format!("{label}{fill}")),
+                ResetColor
             ).unwrap();
         }
+
+        if rect.x + rect.width < area.x + area.width {
+            for y in rect.y..rect.y + rect.height {
+                queue!(
+                    stdout,
+                    MoveTo(rect.x + rect.width, y),
+                    Print("|")
+                ).unwrap();
+            }
+        }
     }
 
-    pub fn draw_cursor(&self) {
+    fn render_pager(&self) {
+        let Some((lines, scroll)) = &self.pager else { return };
         let mut stdout = stdout();
-        let (cols, _) = terminal::size().unwrap();
+        let area = self.text_area();
 
-        let sidebar_width = (SIDEBAR * cols as f32).floor() as u16;
+        for row in 0..area.height {
+            let text = match lines.get(scroll + row as usize) {
+                Some(line) => line.as_str(),
+                None if row as usize == lines.len().saturating_sub(*scroll) => "-- press any key --",
+                None => "",
+            };
 
-        let cursor_char = "";
+            queue!(
+                stdout,
+                MoveTo(area.x, area.y + row),
+                Print(format!("{:<width$}", clip_width(text, area.width as usize), width = area.width as usize))
+            ).unwrap();
+        }
+    }
+
+    // j/k and the arrows scroll the pager, any other key closes it
+    pub fn pager_key(&mut self, key: Key) {
+        let height = self.text_area().height as usize;
+        let Some((lines, scroll)) = &mut self.pager else { return };
+        let max = lines.len().saturating_sub(height.saturating_sub(1));
+
+        match key.code {
+            KeyCode::Char('j') | KeyCode::Down => *scroll = (*scroll + 1).min(max),
+            KeyCode::Char('k') | KeyCode::Up => *scroll = scroll.saturating_sub(1),
+            KeyCode::PageDown | KeyCode::Char(' ') => *scroll = (*scroll + height).min(max),
+            KeyCode::PageUp => *scroll = scroll.saturating_sub(height),
+            _ => self.pager = None,
+        }
+    }
 
-        let cursor_x = self.cursor_c as u16 + sidebar_width + 6;
-        let cursor_y = (self.cursor_l - self.scroll_offset + 6) as u16;
+    // the prompt on top, then the best matches with the matched chars in
+    // yellow and the selected one highlighted
+    fn render_finder(&self) {
+        let Some(finder) = &self.finder else { return };
+        let mut stdout = stdout();
+        let area = self.text_area();
+        let width = area.width as usize;
 
+        let count = format!("{}/{}", finder.matches.len(), finder.total());
+        let prompt = format!("> {}", finder.query);
         queue!(
             stdout,
-            MoveTo(cursor_x, cursor_y),
-            Print(cursor_char),
+            MoveTo(area.x, area.y),
+            Print(format!("{:<w$}{}", clip_width(&prompt, width.saturating_sub(count.len())), count, w = width.saturating_sub(count.len()))),
+            MoveTo(area.x, area.y + 1),
+            Print("-".repeat(width)),
         ).unwrap();
 
-        stdout.flush().unwrap();
-    }
+        for row in 0..area.height.saturating_sub(2) {
+            queue!(stdout, MoveTo(area.x, area.y + 2 + row)).unwrap();
 
-    pub fn adjust_scroll(&mut self) {
-        let (_, rows) = terminal::size().unwrap();
-        let available_rows = (rows - 8) as usize;
+            let Some((idx, positions)) = finder.matches.get(finder.scroll + row as usize) else {
+                queue!(stdout, Print(" ".repeat(width))).unwrap();
+                continue;
+            };
 
-        if self.cursor_l < self.scroll_offset {
-            self.scroll_offset = self.cursor_l;
-        } else if self.cursor_l >= self.scroll_offset + available_rows {
-            self.scroll_offset = self.cursor_l - available_rows + 1;
-        }
-    }
+            let selected = finder.scroll + row as usize == finder.selected;
+            if selected {
+                queue!(stdout, SetBackgroundColor(style::Color::DarkGrey)).unwrap();
+            }
 
-    pub fn adjust_sidebar_scroll(&mut self) {
-        let (_, rows) = terminal::size().unwrap();
-        let visible_files = (rows - 6) as usize;
-    
-        if self.file_cursor < self.sidebar_scroll {
-            self.sidebar_scroll = self.file_cursor;
-        } else if self.file_cursor >= self.sidebar_scroll + visible_files {
-            self.sidebar_scroll = self.file_cursor - visible_files + 1;
+            let path = clip_width(finder.file(*idx), width.saturating_sub(2));
+            queue!(stdout, Print(if selected { "> " } else { "  " })).unwrap();
+            for (i, c) in path.chars().enumerate() {
+                if positions.contains(&i) {
+                    queue!(stdout, SetForegroundColor(style::Color::Yellow), Print(c), SetForegroundColor(style::Color::Reset)).unwrap();
+                } else {
+                    queue!(stdout, Print(c)).unwrap();
+                }
+            }
+            queue!(stdout, Print(" ".repeat(width.saturating_sub(display_width(path) + 2))), ResetColor).unwrap();
         }
     }
 
-    pub fn open_file_from_command(&mut self, path_str: &str) {
-        let mut path = std::path::PathBuf::from(path_str);
-
-        if path.is_relative() {
-            path = self.current_dir.join(path);
-        }
-
-        if path.is_file() {
-            self.content = read_file(path.to_str().unwrap());
-            self.status_message = format!("Opened File: {}", path.display());
+    // `:find` with a query that only fits one file opens it right away
+    pub fn open_finder(&mut self, query: &str) {
+        let finder = Finder::new(&self.current_dir, query);
+
+        if finder.total() == 0 {
+            self.status_message = format!("No files under {}", self.current_dir.display());
+        } else if !query.is_empty() && finder.matches.is_empty() {
+            self.status_message = format!("No file matches {}", query);
+        } else if !query.is_empty() && finder.matches.len() == 1 {
+            let path = finder.selected_path().unwrap_or_default().to_string();
+            self.open_file_from_command(&path);
         } else {
-            self.content = vec![String::new()];
-            self.status_message = format!("New File: {}", path.display());
+            self.finder = Some(finder);
         }
-
-        self.file_path = path.to_str().unwrap().to_string();
-        self.cursor_l = 0;
-        self.cursor_c = 0;
-        self.scroll_offset = 0;
-        self.mode = Mode::Insert;
     }
 
-    pub fn insert_char(&mut self, c: char) {
-        if self.cursor_l < self.content.len() {
-            self.content[self.cursor_l].insert(self.cursor_c, c);
-            self.cursor_c += 1;
+    // typing narrows the list, Up/Down (or Ctrl-p/Ctrl-n) pick, Enter opens
+    pub fn finder_key(&mut self, key: Key) {
+        let height = self.text_area().height.saturating_sub(2) as usize;
+        let Some(finder) = &mut self.finder else { return };
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+        match key.code {
+            KeyCode::Esc => self.finder = None,
+            KeyCode::Enter => {
+                let path = finder.selected_path().map(String::from);
+                self.finder = None;
+                if let Some(path) = path {
+                    self.open_file_from_command(&path);
+                }
+                return;
+            },
+            KeyCode::Up | KeyCode::BackTab => finder.move_selection(false),
+            KeyCode::Down | KeyCode::Tab => finder.move_selection(true),
+            KeyCode::Char('p' | 'k') if ctrl => finder.move_selection(false),
+            KeyCode::Char('n' | 'j') if ctrl => finder.move_selection(true),
+            KeyCode::Backspace => finder.pop(),
+            KeyCode::Char(c) if !ctrl && !key.modifiers.contains(KeyModifiers::ALT) => finder.push(c),
+            _ => {}
+        }
+
+        if let Some(finder) = &mut self.finder {
+            finder.keep_visible(height);
         }
     }
 
-    pub fn handle_enter(&mut self) {
-        if self.cursor_l >= self.content.len() {
+    // the :grep hits as file:line:col and the line itself
+    fn render_quickfix(&self) {
+        if !self.quickfix.open {
             return;
         }
+        let mut stdout = stdout();
+        let area = self.text_area();
+        let width = area.width as usize;
 
-        let current_line = &mut self.content[self.cursor_l];
+        let title = format!("grep: {} matches (Enter to jump, Esc to close)", self.quickfix.hits.len());
+        queue!(
+            stdout,
+            MoveTo(area.x, area.y),
+            Print(format!("{:<width$}", clip_width(&title, width))),
+            MoveTo(area.x, area.y + 1),
+            Print("-".repeat(width)),
+        ).unwrap();
 
-        let current_indent = current_line
-            .chars()
-            .take_while(|c| c.is_whitespace())
-            .collect::<String>();
+        for row in 0..area.height.saturating_sub(2) {
+            let idx = self.quickfix.scroll + row as usize;
+            queue!(stdout, MoveTo(area.x, area.y + 2 + row)).unwrap();
 
-        let indent_unit = "    ";
-        let current_char = current_line.chars().nth(self.cursor_c);
-        let prev_char = if self.cursor_c > 0 {
-            current_line.chars().nth(self.cursor_c - 1)
-        } else {
-            None
-        };
+            let Some(hit) = self.quickfix.hits.get(idx) else {
+                queue!(stdout, Print(" ".repeat(width))).unwrap();
+                continue;
+            };
 
-        let suffix = current_line.split_off(self.cursor_c);
-        self.cursor_l += 1;
+            if idx == self.quickfix.current {
+                queue!(stdout, SetBackgroundColor(style::Color::DarkGrey)).unwrap();
+            }
 
-        if matches!(
-            (prev_char, current_char),
-            (Some('{'), Some('}')) |
-            (Some('['), Some(']')) |
-            (Some('('), Some(')'))
-        ) {
-            self.content.insert(self.cursor_l, format!("{}{}", current_indent, indent_unit));
-            self.content.insert(self.cursor_l + 1, format!("{}{}", current_indent, suffix));
-            self.cursor_c = (current_indent.len() + indent_unit.len());
-        } else {
-            self.content.insert(self.cursor_l, format!("{}{}", current_indent, suffix));
-            self.cursor_c = current_indent.len();
+            let location = format!("{}:{}:{}: ", relative_path(&self.current_dir, &hit.path), hit.line + 1, hit.col + 1);
+            let location = clip_width(&location, width);
+            let text = clip_width(&hit.text, width - display_width(location));
+            queue!(
+                stdout,
+                SetForegroundColor(style::Color::Cyan),
+                Print(location),
+                SetForegroundColor(style::Color::Reset),
+                Print(format!("{:<w$}", text, w = width - display_width(location))),
+                ResetColor
+            ).unwrap();
         }
     }
 
+    // the completion menu or the hover text, in a box under the cursor (or
+    // over it when there's more room there)
+    fn render_popup(&self) {
+        let (rows, selected): (Vec<String>, Option<usize>) = if let Some(menu) = &self.completion_menu {
+            let rows = menu.matches.iter()
+                .map(|idx| {
+                    let item = &menu.items[*idx];
+                    if item.detail.is_empty() { item.label.clone() } else { format!("{}  {}", item.label, item.detail) }
+                })
+                .collect();
+            (rows, Some(menu.selected))
+        } else if let Some(lines) = &self.hover {
+            (lines.clone(), None)
+        } else {
+            return;
+        };
 
-    pub fn handle_backspace(&mut self) {
-        if self.cursor_c > 0 {
-            self.content[self.cursor_l].remove(self.cursor_c - 1);
-            self.cursor_c -= 1;
-        } else if self.cursor_l > 0 {
-            let current_line = self.content.remove(self.cursor_l);
-            self.cursor_l -= 1;
-            self.cursor_c = self.content[self.cursor_l].len();
-            self.content[self.cursor_l].push_str(&current_line);
-        }
-    }
-
-    pub fn handle_delete(&mut self) {
-        if self.cursor_l >= self.content.len() {
+        let mut stdout = stdout();
+        let area = self.text_area();
+        let rect = self.focused_rect();
+        let cursor_y = (self.cursor_l - self.scroll_offset) as u16 + rect.y;
+
+        let below = (area.y + area.height).saturating_sub(cursor_y + 1) as usize;
+        let above = cursor_y.saturating_sub(area.y) as usize;
+        let height = rows.len().min(POPUP_HEIGHT).min(below.max(above));
+        if height == 0 {
             return;
         }
+        let top = if below >= height || below >= above { cursor_y + 1 } else { cursor_y - height as u16 };
+
+        // a space on each side of the text
+        let width = (rows.iter().map(|row| display_width(row)).max().unwrap_or(0) + 2).min(area.width as usize);
+        let left = (rect.x + 6 + self.cursor_x()).min(area.x + area.width - width as u16);
+        let first = selected.map_or(0, |selected| (selected + 1).saturating_sub(height));
+
+        for (row, text) in rows.iter().enumerate().skip(first).take(height) {
+            let background = if selected == Some(row) { style::Color::Blue } else { style::Color::DarkGrey };
+            let text = clip_width(text, width - 2);
 
-        if self.cursor_c < self.content[self.cursor_l].len() {
-            self.content[self.cursor_l].remove(self.cursor_c);
-        } else if self.cursor_l < self.content.len() - 1 {
-            let next_line = self.content.remove(self.cursor_l + 1);
-            self.content[self.cursor_l].push_str(&next_line);
+            queue!(
+                stdout,
+                MoveTo(left, top + (row - first) as u16),
+                SetBackgroundColor(background),
+                SetForegroundColor(style::Color::White),
+                Print(format!(" {}{} ", text, " ".repeat(width - 2 - display_width(text)))),
+                ResetColor
+            ).unwrap();
         }
     }
-    
-    pub fn handle_tab(&mut self) {
-        if self.cursor_l < self.content.len() {
-            self.content[self.cursor_l].insert_str(self.cursor_c, "    ");
-            self.cursor_c += 4;
+
+    // j/k or the arrows pick a hit, Enter jumps to it
+    pub fn quickfix_key(&mut self, key: Key) {
+        let height = self.text_area().height.saturating_sub(2) as usize;
+        let quickfix = &mut self.quickfix;
+        let last = quickfix.hits.len().saturating_sub(1);
+
+        match key.code {
+            KeyCode::Char('j') | KeyCode::Down => quickfix.current = (quickfix.current + 1).min(last),
+            KeyCode::Char('k') | KeyCode::Up => quickfix.current = quickfix.current.saturating_sub(1),
+            KeyCode::PageDown => quickfix.current = (quickfix.current + height).min(last),
+            KeyCode::PageUp => quickfix.current = quickfix.current.saturating_sub(height),
+            KeyCode::Enter => {
+                quickfix.open = false;
+                self.jump_to_hit(self.quickfix.current);
+                return;
+            },
+            KeyCode::Esc | KeyCode::Char('q') => quickfix.open = false,
+            _ => {}
+        }
+
+        if quickfix.current < quickfix.scroll {
+            quickfix.scroll = quickfix.current;
+        } else if height > 0 && quickfix.current >= quickfix.scroll + height {
+            quickfix.scroll = quickfix.current + 1 - height;
         }
     }
 
-    pub fn auto_close(&mut self, c: char) {
-        let close = match c {
-            '(' => ')',
-            '{' => '}',
-            '[' => ']',
-            '"' => '"',
-            '\'' => '\'',
-            _ => { return; }
+    // `:grep pattern [path]`, rooted at the sidebar folder
+    fn grep(&mut self, args: &str) {
+        let (pattern, path) = match grep::parse_args(args) {
+            Ok(args) => args,
+            Err(err) => {
+                self.status_message = err;
+                return;
+            },
         };
 
-        if self.cursor_l < self.content.len() {
-            self.content[self.cursor_l].insert(self.cursor_c, c);
-            self.cursor_c += 1;
+        let root = match path {
+            Some(path) => self.current_dir.join(path),
+            None => self.current_dir.clone(),
+        };
 
-            self.content[self.cursor_l].insert(self.cursor_c, close);
+        match grep::grep(&pattern, &root) {
+            Ok(hits) if hits.is_empty() => self.status_message = format!("No matches for {}", pattern),
+            Ok(hits) => {
+                self.status_message = format!("{} matches for {}", hits.len(), pattern);
+                self.quickfix = Quickfix { hits, current: 0, open: true, scroll: 0 };
+            },
+            Err(err) => self.status_message = err,
         }
     }
 
-    pub fn copy_selection(&mut self) {
-        if let Some(start) = self.visual_start {
-            let (start, end) = if start <= self.cursor_l {
-                (start, self.cursor_l)
-            } else {
-                (self.cursor_l, start)
-            };
-            let lines = self.content[start..=end].join("\n");
-            self.clipboard = Some(lines);
-            self.status_message = "copied".to_string();
-            self.mode = Mode::Command;
-            self.visual_start = None;
+    // :cn and :cp
+    fn quickfix_step(&mut self, forward: bool) {
+        let quickfix = &self.quickfix;
+        if quickfix.hits.is_empty() {
+            self.status_message = "No grep results".to_string();
+        } else if forward && quickfix.current + 1 >= quickfix.hits.len() {
+            self.status_message = "No more items".to_string();
+        } else if !forward && quickfix.current == 0 {
+            self.status_message = "Already at the first item".to_string();
+        } else {
+            let idx = if forward { quickfix.current + 1 } else { quickfix.current - 1 };
+            self.jump_to_hit(idx);
         }
     }
 
-    pub fn paste_lines(&mut self) {
-        if let Some(ref lines) = self.clipboard {
-            let split: Vec<String> = lines.lines().map(String::from).collect();
-            self.content.splice(self.cursor_l + 1..self.cursor_l + 1, split);
-            self.status_message = "pasted".to_string();
-        }
+    fn jump_to_hit(&mut self, idx: usize) {
+        let Some(hit) = self.quickfix.hits.get(idx) else { return };
+        let (path, pos, text) = (hit.path.clone(), (hit.line, hit.col), hit.text.clone());
+
+        self.quickfix.current = idx;
+        self.open_file_from_command(&path);
+        self.mode = Mode::Command;
+        self.jump_to(pos);
+        self.status_message = format!("({} of {}) {}", idx + 1, self.quickfix.hits.len(), text);
     }
 
-    pub fn open_selected(&mut self) {
-        if self.files.is_empty() { return; }
+    // opens buffers on their language server and sends the edits made since
+    // the last sync
+    pub fn sync_lsp(&mut self) {
+        let mut open = HashSet::new();
+
+        for buffer in &mut self.buffers {
+            if buffer.is_empty_file() {
+                continue;
+            }
+            let filetype = filetype::detect(&buffer.file_path);
+            let Some(server) = self.config.lsp(filetype) else { continue };
+            let Some(client) = self.lsp.start(filetype, server) else { continue };
+
+            let uri = lsp::path_uri(Path::new(&buffer.file_path));
+            if !client.is_open(&uri) {
+                client.did_open(&uri, filetype, &buffer.content.text());
+            } else if !buffer.synced {
+                client.did_change(&uri, &buffer.content.text());
+            }
+            buffer.synced = true;
+            open.insert(uri);
+        }
 
-        let selected = &self.files[self.file_cursor];
-        let path = self.current_dir.join(selected);
+        self.lsp.close_others(&open);
+    }
 
-        if path.is_file() {
-            self.content = read_file(path.to_str().unwrap());
-            self.file_path = path.to_str().unwrap().to_string();
-            self.cursor_l = 0;
-            self.cursor_c = 0;
-            self.status_message = format!("Arquivo aberto: {}", selected);
-            self.mode = Mode::Insert;
-        } else if path.is_dir() {
-            self.current_dir = path;
-            self.files = read_dir_files(&self.current_dir);
-            self.file_cursor = 0;
-            self.status_message = format!("Entrou no diretório: {}", self.current_dir.display());
+    // handles what the servers sent. true when the screen needs a redraw
+    pub fn poll_lsp(&mut self) -> bool {
+        self.sync_lsp();
+        let events = self.lsp.poll();
+        let redraw = !events.is_empty();
+
+        for event in events {
+            match event {
+                lsp::Event::Diagnostics(uri, diagnostics) => {
+                    for buffer in &mut self.buffers {
+                        if !buffer.is_empty_file() && lsp::path_uri(Path::new(&buffer.file_path)) == uri {
+                            buffer.diagnostics = diagnostics.clone();
+                        }
+                    }
+                },
+                lsp::Event::Hover(lines) if lines.is_empty() => self.status_message = "Nothing to show".to_string(),
+                lsp::Event::Hover(lines) => self.hover = Some(lines),
+                lsp::Event::Definition(locations) => self.go_to_location(locations),
+                lsp::Event::Completion(items) => self.show_completions(items),
+                lsp::Event::Message(text) => self.status_message = text,
+            }
         }
+
+        redraw
     }
 
-    pub fn go_back(&mut self) {
-        if let Some(parent) = self.current_dir.parent() {
-            self.current_dir = parent.to_path_buf();
-            self.files = read_dir_files(&self.current_dir);
-            self.file_cursor = 0;
-            self.status_message = format!("Diretório: {}", self.current_dir.display());
+    // "E2 W1 | message of the cursor line | " for the status line
+    fn diagnostics_status(&self) -> String {
+        let diagnostics = &self.buf().diagnostics;
+        if diagnostics.is_empty() {
+            return String::new();
         }
-    }
 
-    pub fn move_right(&mut self) {
-        if self.cursor_c < self.content[self.cursor_l].len() {
-            self.cursor_c += 1;
-            self.adjust_scroll();
-        } else if self.cursor_l < self.content.len() -1 {
-            self.cursor_l += 1;
-            self.cursor_c = 0;
-            self.adjust_scroll();
+        let count = |severity| diagnostics.iter().filter(|d| d.severity == severity).count();
+        let counts: Vec<String> = [(Severity::Error, "E"), (Severity::Warning, "W"), (Severity::Info, "I"), (Severity::Hint, "H")]
+            .into_iter()
+            .filter(|(severity, _)| count(*severity) > 0)
+            .map(|(severity, letter)| format!("{}{}", letter, count(severity)))
+            .collect();
+
+        let here = diagnostics.iter()
+            .filter(|d| d.start.0 <= self.cursor_l && self.cursor_l <= d.end.0)
+            .min_by_key(|d| d.severity);
+        match here {
+            Some(diagnostic) => format!("{} | {} | ", counts.join(" "), diagnostic.message.lines().next().unwrap_or_default()),
+            None => format!("{} | ", counts.join(" ")),
         }
     }
 
-    pub fn move_left(&mut self) {
-        if self.cursor_c > 0 {
-            self.cursor_c -= 1;
-            self.adjust_scroll();
-        } else if self.cursor_l > 0 {
-            self.cursor_l -= 1;
-            self.cursor_c = self.content[self.cursor_l].len();
-            self.adjust_scroll();
+    fn lsp_saved(&mut self) {
+        if self.buf().is_empty_file() {
+            return;
+        }
+        let uri = lsp::path_uri(Path::new(&self.buf().file_path));
+        self.sync_lsp();
+        if let Some(client) = self.lsp.client(self.filetype()) {
+            client.did_save(&uri);
         }
     }
 
-    pub fn move_up_files(&mut self) {
-        if self.file_cursor > 0 {
-            self.file_cursor -= 1;
-            self.adjust_sidebar_scroll();
+    // sends a request about the cursor position to the buffer's server.
+    // false when there's no server for it
+    fn lsp_request(&mut self, send: fn(&mut lsp::Client, &str, (usize, usize))) -> bool {
+        if self.buf().is_empty_file() {
+            return false;
+        }
+        self.sync_lsp();
+
+        let line = self.buf().content.line(self.cursor_l);
+        let position = (self.cursor_l, lsp::utf16_col(&line, grapheme_to_char(&line, self.cursor_c)));
+        let uri = lsp::path_uri(Path::new(&self.buf().file_path));
+
+        match self.lsp.client(self.filetype()) {
+            Some(client) => {
+                send(client, &uri, position);
+                true
+            },
+            None => false,
         }
     }
 
-    pub fn move_down_files(&mut self) {
-        if self.file_cursor + 1 < self.files.len() {
-            self.file_cursor += 1;
-            self.adjust_sidebar_scroll();
+    pub fn lsp_hover(&mut self) {
+        if !self.lsp_request(lsp::Client::hover) {
+            self.status_message = "No language server for this file".to_string();
         }
     }
 
-    pub fn move_up(&mut self) {
-        if self.cursor_l > 0 {
-            self.cursor_l -= 1;
-            self.cursor_c = std::cmp::min(self.cursor_c, self.content[self.cursor_l].len());
-            self.adjust_scroll();
+    pub fn lsp_definition(&mut self) {
+        if !self.lsp_request(lsp::Client::definition) {
+            self.status_message = "No language server for this file".to_string();
         }
     }
 
-    pub fn move_down(&mut self) {
-        if self.cursor_l < self.content.len() - 1 {
-            self.cursor_l += 1;
-            self.cursor_c = std::cmp::min(self.cursor_c, self.content[self.cursor_l].len());
-            self.adjust_scroll();
+    pub fn lsp_complete(&mut self) {
+        if !self.lsp_request(lsp::Client::completion) {
+            self.status_message = "No language server for this file".to_string();
         }
     }
 
-    pub fn refresh_sidebar(&mut self) {
-        self.files = read_dir_files(&self.current_dir);
+    // servers name chars like '.' that bring up completions by themselves
+    fn complete_on_trigger(&mut self, c: char) {
+        let filetype = self.filetype();
+        if self.lsp.client(filetype).is_some_and(|client| client.trigger_chars.contains(&c)) {
+            self.lsp_request(lsp::Client::completion);
+        }
     }
 
+    // the first location, through the same path as opening a file
+    fn go_to_location(&mut self, locations: Vec<Location>) {
+        let Some(location) = locations.into_iter().next() else {
+            self.status_message = "No definition found".to_string();
+            return;
+        };
 
-    pub fn save(&mut self) -> io::Result<()> {
-        if self.file_path == "." {
-            self.status_message = "Usage :w <file_path>".to_string();
-            return Ok(());
-        }
+        self.open_file_from_command(&location.path.to_string_lossy());
+        self.mode = Mode::Command;
 
-        let mut file = File::create(&self.file_path)?;
-        file.write_all(self.content.join("\n").as_bytes())?;
-        self.status_message = "File Saved".to_string();
-        Ok(())
+        let line = location.line.min(self.buf().content.line_count() - 1);
+        let text = self.buf().content.line(line);
+        self.jump_to((line, char_to_grapheme(&text, lsp::char_col(&text, location.character))));
+        self.status_message = relative_path(&self.current_dir, &self.buf().file_path);
     }
 
-    pub fn save_as(&mut self, new_path: &str) -> io::Result<()> {
-        let mut path = PathBuf::from(new_path);
-        if path.is_relative() {
-            path = self.current_dir.join(path);
+    // the menu is for the word before the cursor, wherever it got to while
+    // the server was answering
+    fn show_completions(&mut self, items: Vec<CompletionItem>) {
+        if !matches!(self.mode, Mode::Insert) || items.is_empty() {
+            return;
         }
 
-        let mut file = File::create(&path)?;
-        file.write_all(self.content.join("\n").as_bytes())?;
+        let line: Vec<char> = self.buf().content.line(self.cursor_l).chars().collect();
+        let cursor = self.cursor_char();
+        let start = (0..cursor).rev().take_while(|i| is_word_char(line[*i])).last().unwrap_or(cursor);
 
-        self.file_path = path.to_str().unwrap().to_string();
-        self.status_message = format!("Arquivo salvo como: {}", self.file_path);
-        Ok(())
+        self.completion_menu = Some(CompletionMenu::new(items, self.cursor_l, start));
+        self.update_completion();
     }
-}
 
-pub fn read_file(path: &str) -> Vec<String> {
-   match File::open(path) {
-       Ok(file) => BufReader::new(file).lines().filter_map(Result::ok).collect(),
-       Err(_) => vec![String::new()]
-   }
-}
+    // narrows the menu down to the word typed so far. it closes once the
+    // cursor leaves the word or nothing matches
+    pub fn update_completion(&mut self) {
+        let Some((menu_line, start)) = self.completion_menu.as_ref().map(|menu| (menu.line, menu.start)) else { return };
+        let cursor = self.cursor_char();
+        let typed: String = self.buf().content.line(self.cursor_l).chars().skip(start).take(cursor.saturating_sub(start)).collect();
+
+        if !matches!(self.mode, Mode::Insert)
+            || menu_line != self.cursor_l
+            || cursor < start
+            || !typed.chars().all(is_word_char)
+        {
+            self.completion_menu = None;
+            return;
+        }
 
-pub fn read_dir_files(path: &PathBuf) -> Vec<String> {
-    let mut entries: Vec<String> = fs::read_dir(path)
-        .unwrap()
-        .filter_map(|entry| entry.ok())
-        .map(|e| e.file_name().into_string().unwrap_or_default())
-        .collect();
+        if let Some(menu) = &mut self.completion_menu {
+            menu.refilter(&typed);
+            if menu.matches.is_empty() {
+                self.completion_menu = None;
+            }
+        }
+    }
 
-    entries.sort();
-    entries
-}
+    // Up / Down or Ctrl-n / Ctrl-p pick, Tab or Enter take it and Esc closes
+    // the menu. false for keys that go on to insert mode
+    pub fn completion_menu_key(&mut self, key: Key) -> bool {
+        let Some(menu) = &mut self.completion_menu else { return false };
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+        match key.code {
+            KeyCode::Down => menu.move_selection(true),
+            KeyCode::Up => menu.move_selection(false),
+            KeyCode::Char('n') if ctrl => menu.move_selection(true),
+            KeyCode::Char('p') if ctrl => menu.move_selection(false),
+            KeyCode::Tab | KeyCode::Enter => self.accept_completion(),
+            KeyCode::Esc => self.completion_menu = None,
+            _ => return false,
+        }
+        true
+    }
 
-fn truncate_string(s: &str, max_width: usize) -> String {
-    if s.chars().count() <= max_width {
+    // replaces the word typed so far with the item, typed in as far as `.`
+    // is concerned
+    fn accept_completion(&mut self) {
+        let Some(menu) = self.completion_menu.take() else { return };
+        let Some(item) = menu.selected_item() else { return };
+
+        self.record_edit();
+        let cursor = self.cursor_char();
+        for _ in menu.start..cursor {
+            self.log_insert('\u{8}');
+        }
+        // replayed chars go through auto pairs, so the closing char they
+        // add is deleted again
+        for c in item.insert.chars() {
+            self.log_insert(c);
+            if self.closing_pair(c).is_some() {
+                self.log_insert('\u{7f}');
+            }
+        }
+
+        self.delete_text((menu.line, menu.start), (menu.line, cursor));
+        self.insert_text(menu.line, menu.start, &item.insert);
+
+        match item.insert.rsplit_once('\n') {
+            Some((before, last)) => {
+                self.cursor_l += before.matches('\n').count() + 1;
+                self.set_cursor_char(last.chars().count());
+            },
+            None => self.set_cursor_char(menu.start + item.insert.chars().count()),
+        }
+        self.adjust_scroll();
+    }
+
+    pub fn render_file_browser(&mut self) {
+        let mut stdout = stdout();
+
+        let (cols, _) = terminal::size().unwrap();
+
+        let sidebar_width = self.sidebar_cols(cols);
+        if sidebar_width == 0 {
+            return;
+        }
+        self.adjust_sidebar_scroll();
+
+        queue!(
+            stdout,
+            MoveTo(self.cursor_x() + sidebar_width + 3, (self.cursor_l - self.scroll_offset + 5) as u16)
+        ).unwrap();
+
+        // only the rows that fit are drawn, from sidebar_scroll on
+        let visible = self.sidebar_rows();
+        let total = self.tree.entries.len();
+
+        for (row, (i, entry)) in self.tree.entries.iter().enumerate().skip(self.sidebar_scroll).take(visible).enumerate() {
+            let indent = "  ".repeat(entry.depth);
+            let status = self.git_status.as_ref().and_then(|status| status.get(&entry.path));
+            // room for the status letter at the right edge
+            let display_name = truncate_string(&entry.name, (sidebar_width as usize).saturating_sub(7 + indent.len()));
+            let (arrow, icon) = match (entry.is_dir, entry.expanded) {
+                (true, true) => ("▾", folder_icon(&entry.name)),
+                (true, false) => ("▸", folder_icon(&entry.name)),
+                _ => (" ", file_icon(&entry.name)),
+            };
+
+            queue!(
+                stdout,
+                MoveTo(0, (row + 4) as u16),
+                if i == self.file_cursor {
+                    SetForegroundColor(style::Color::Green)
+                } else if status == Some(FileStatus::Ignored) {
+                    SetForegroundColor(style::Color::DarkGrey)
+                } else {
+                    SetForegroundColor(style::Color::White)
+                },
+                Print(format!("{indent}{arrow}{icon} {display_name}")),
+                ResetColor
+            ).unwrap();
+
+            let letter = match status {
+                Some(FileStatus::Modified) => Some(("M", style::Color::Yellow)),
+                Some(FileStatus::Added) => Some(("A", style::Color::Green)),
+                Some(FileStatus::Untracked) => Some(("U", style::Color::Cyan)),
+                _ => None,
+            };
+            if let Some((letter, color)) = letter {
+                queue!(
+                    stdout,
+                    MoveTo(sidebar_width.saturating_sub(2), (row + 4) as u16),
+                    SetForegroundColor(color),
+                    Print(letter),
+                    ResetColor
+                ).unwrap();
+            }
+        }
+
+        queue!(
+            stdout,
+            MoveTo(0, 2),
+            Print(format!("📁 {}", self.current_dir.display()))
+        ).unwrap();
+
+        // the border doubles as a scroll bar when the tree doesn't fit
+        let (thumb_start, thumb_len) = if total > visible {
+            let len = (visible * visible / total).max(1);
+            let start = (self.sidebar_scroll * visible).div_ceil(total).min(visible - len);
+            (start, len)
+        } else {
+            (0, 0)
+        };
+
+        for y in 0..total.min(visible) {
+            let bar = if (thumb_start..thumb_start + thumb_len).contains(&y) { "┃" } else { "|" };
+            queue!(
+                stdout,
+                MoveTo(sidebar_width, y as u16 + 4),
+                Print(bar)
+            ).unwrap();
+        }
+    }
+
+    // columns taken by the sidebar, none while it's hidden
+    fn sidebar_cols(&self, cols: u16) -> u16 {
+        if self.sidebar_hidden {
+            0
+        } else {
+            (self.config.sidebar_width * cols as f32).floor() as u16
+        }
+    }
+
+    // rows for tree entries, between the header and the command line
+    fn sidebar_rows(&self) -> usize {
+        let (_, rows) = terminal::size().unwrap();
+        (rows.saturating_sub(6) as usize).max(1)
+    }
+
+    pub fn toggle_sidebar(&mut self) {
+        self.sidebar_hidden = !self.sidebar_hidden;
+        self.adjust_scroll();
+    }
+
+    // grows or shrinks the sidebar by a number of columns
+    pub fn resize_sidebar(&mut self, delta: i32) {
+        let (cols, _) = terminal::size().unwrap();
+        let width = self.sidebar_cols(cols) as i32 + delta;
+
+        self.config.sidebar_width = (width as f32 / cols as f32).clamp(0.0, 0.5);
+        self.sidebar_hidden = false;
+        self.status_message = format!("sidebar_width={:.2}", self.config.sidebar_width);
+        self.adjust_scroll();
+    }
+
+    pub fn draw_cursor(&self) {
+        let mut stdout = stdout();
+
+        if let Some(finder) = &self.finder {
+            let area = self.text_area();
+            let x = area.x + 2 + display_width(&finder.query) as u16;
+            queue!(stdout, MoveTo(x.min(area.x + area.width.saturating_sub(1)), area.y)).unwrap();
+            stdout.flush().unwrap();
+            return;
+        }
+        let rect = self.focused_rect();
+
+        let cursor_char = "";
+
+        let cursor_x = self.cursor_x() + rect.x + 6;
+        let cursor_y = (self.cursor_l - self.scroll_offset) as u16 + rect.y;
+
+        queue!(
+            stdout,
+            MoveTo(cursor_x, cursor_y),
+            Print(cursor_char),
+        ).unwrap();
+
+        stdout.flush().unwrap();
+    }
+
+    pub fn adjust_scroll(&mut self) {
+        let available_rows = (self.focused_rect().height as usize).max(1);
+
+        if self.cursor_l < self.scroll_offset {
+            self.scroll_offset = self.cursor_l;
+        } else if self.cursor_l >= self.scroll_offset + available_rows {
+            self.scroll_offset = self.cursor_l - available_rows + 1;
+        }
+    }
+
+    pub fn adjust_sidebar_scroll(&mut self) {
+        let visible_files = self.sidebar_rows();
+        self.sidebar_scroll = self.sidebar_scroll.min(self.tree.entries.len().saturating_sub(visible_files));
+
+        if self.file_cursor < self.sidebar_scroll {
+            self.sidebar_scroll = self.file_cursor;
+        } else if self.file_cursor >= self.sidebar_scroll + visible_files {
+            self.sidebar_scroll = self.file_cursor - visible_files + 1;
+        }
+    }
+
+    pub fn open_file_from_command(&mut self, path_str: &str) {
+        let mut path = std::path::PathBuf::from(path_str);
+
+        if path.is_relative() {
+            path = self.current_dir.join(path);
+        }
+
+        if let Some(idx) = self.buffers.iter().position(|b| b.is_path(&path)) {
+            self.switch_buffer(idx);
+            self.status_message = format!("Buffer {}: {}", idx + 1, path.display());
+        } else {
+            if path.is_file() {
+                self.status_message = format!("Opened File: {}", path.display());
+            } else {
+                self.status_message = format!("New File: {}", path.display());
+            }
+            self.add_buffer(Buffer::new(path.to_str().unwrap()));
+            self.check_swap();
+        }
+
+        self.mode = Mode::Insert;
+    }
+
+    pub fn buf(&self) -> &Buffer {
+        &self.buffers[self.current]
+    }
+
+    pub fn buf_mut(&mut self) -> &mut Buffer {
+        &mut self.buffers[self.current]
+    }
+
+    // the untouched empty buffer from startup is replaced instead of kept
+    fn add_buffer(&mut self, buffer: Buffer) {
+        if self.buf().is_empty_file() && !self.buf().modified {
+            self.buffers[self.current] = buffer;
+            self.load_view();
+        } else {
+            self.buffers.push(buffer);
+            self.switch_buffer(self.buffers.len() - 1);
+        }
+    }
+
+    pub fn switch_buffer(&mut self, idx: usize) {
+        if idx >= self.buffers.len() || idx == self.current {
+            return;
+        }
+
+        self.buf_mut().history.close_group();
+        self.save_view();
+        self.current = idx;
+        self.windows[self.focus].buffer = idx;
+        self.load_view();
+    }
+
+    pub fn next_buffer(&mut self) {
+        self.switch_buffer((self.current + 1) % self.buffers.len());
+    }
+
+    pub fn prev_buffer(&mut self) {
+        self.switch_buffer((self.current + self.buffers.len() - 1) % self.buffers.len());
+    }
+
+    pub fn delete_buffer(&mut self, force: bool) {
+        if self.buf().modified && !force {
+            self.status_message = "No write since last change (add ! to override)".to_string();
+            return;
+        }
+
+        let name = self.buffer_name(self.buf());
+        let removed = self.current;
+        self.buf().remove_swap();
+        self.buffers.remove(removed);
+        if self.buffers.is_empty() {
+            self.buffers.push(Buffer::new("."));
+        }
+
+        let fallback = removed.min(self.buffers.len() - 1);
+        for window in self.windows.iter_mut() {
+            if window.buffer == removed {
+                window.buffer = fallback;
+                window.cursor_l = 0;
+                window.cursor_c = 0;
+                window.scroll_offset = 0;
+            } else if window.buffer > removed {
+                window.buffer -= 1;
+            }
+        }
+
+        self.current = self.windows[self.focus].buffer;
+        self.load_view();
+        self.status_message = format!("Buffer closed: {}", name);
+    }
+
+    pub fn list_buffers(&mut self) {
+        let list: Vec<String> = self.buffers.iter().enumerate().map(|(i, buffer)| {
+            let current = if i == self.current { "%" } else { " " };
+            let modified = if buffer.modified { " [+]" } else { "" };
+            format!("{}{} \"{}\"{}", i + 1, current, self.buffer_name(buffer), modified)
+        }).collect();
+
+        self.status_message = list.join(" | ");
+    }
+
+    pub fn modified_buffer(&self) -> Option<usize> {
+        self.buffers.iter().position(|b| b.modified)
+    }
+
+    fn save_view(&mut self) {
+        let (cursor_l, cursor_c, scroll_offset) = (self.cursor_l, self.cursor_c, self.scroll_offset);
+        let buffer = self.buf_mut();
+        buffer.cursor_l = cursor_l;
+        buffer.cursor_c = cursor_c;
+        buffer.scroll_offset = scroll_offset;
+    }
+
+    fn load_view(&mut self) {
+        let buffer = &self.buffers[self.current];
+        self.cursor_l = buffer.cursor_l;
+        self.cursor_c = buffer.cursor_c;
+        self.scroll_offset = buffer.scroll_offset;
+    }
+
+    // called while /pattern or ?pattern is typed, moves the cursor to the
+    // first match from where the search started
+    pub fn update_search(&mut self) {
+        let origin = *self.search_origin.get_or_insert((self.cursor_l, self.cursor_c));
+        let forward = !self.command.starts_with('?');
+        let pattern = self.command[1..].to_string();
+
+        // only reads up to the first match, this runs on every key
+        let found = search::find_next(&self.buf().content, &pattern, origin, forward);
+        self.search = Some(Search::new(pattern, forward));
+
+        match found {
+            Some((pos, _)) => self.jump_to(pos),
+            None => self.jump_to(origin),
+        }
+    }
+
+    pub fn finish_search(&mut self) {
+        self.search_origin = None;
+
+        let content = &self.buffers[self.current].content;
+        match &mut self.search {
+            Some(search) if !search.pattern.is_empty() => {
+                self.registers.set_search(&search.pattern);
+                let cursor = (self.cursor_l, self.cursor_c);
+                let matches = search.matches(content);
+                self.status_message = match matches.iter().position(|m| *m == cursor) {
+                    Some(i) => format!("match {}/{}", i + 1, matches.len()),
+                    None => format!("Pattern not found: {}", search.pattern),
+                };
+            },
+            _ => self.search = None,
+        }
+    }
+
+    pub fn cancel_search(&mut self) {
+        if let Some(origin) = self.search_origin.take() {
+            self.jump_to(origin);
+            self.search = None;
+        }
+    }
+
+    // n goes in the direction of the last search, N (reverse) the other way
+    pub fn search_next(&mut self, reverse: bool) {
+        let content = &self.buffers[self.current].content;
+        let Some(search) = &mut self.search else {
+            self.status_message = "No previous search".to_string();
+            return;
+        };
+
+        let forward = search.forward != reverse;
+        let pattern = search.pattern.clone();
+        let matches = search.matches(content);
+        let found = search::next_match(matches, (self.cursor_l, self.cursor_c), forward)
+            .map(|(i, wrapped)| (matches[i], i, matches.len(), wrapped));
+
+        match found {
+            Some((pos, i, count, wrapped)) => {
+                self.jump_to(pos);
+                self.status_message = format!("match {}/{}", i + 1, count);
+                if wrapped {
+                    self.status_message += if forward {
+                        " | search hit BOTTOM, continuing at TOP"
+                    } else {
+                        " | search hit TOP, continuing at BOTTOM"
+                    };
+                }
+            },
+            None => self.status_message = format!("Pattern not found: {}", pattern),
+        }
+    }
+
+    // ':' in visual mode starts a command over the selected lines
+    pub fn command_from_visual(&mut self) {
+        self.leave_visual();
+        self.command = ":'<,'>".to_string();
+    }
+
+    pub fn substitute(&mut self, command: &str) {
+        let line_count = self.buf().content.line_count();
+
+        match substitute::parse(command, self.cursor_l, line_count, self.last_visual) {
+            Ok(sub) => self.run_substitute(sub, None),
+            Err(err) => self.status_message = err,
+        }
+    }
+
+    pub fn confirm_substitute(&mut self, answer: char) {
+        if let Some(sub) = self.pending_substitute.take() {
+            self.run_substitute(sub, Some(answer));
+        }
+    }
+
+    // walks the matches in the range. with the c flag it stops at each match
+    // and resumes from confirm_substitute with the answer
+    fn run_substitute(&mut self, mut sub: Substitution, mut answer: Option<char>) {
+        let mut stop = false;
+
+        while !stop && sub.line <= sub.end && sub.line < self.buf().content.line_count() {
+            let line = self.buf().content.line(sub.line);
+
+            let caps = if sub.byte <= line.len() {
+                sub.regex.captures_at(&line, sub.byte)
+            } else {
+                None
+            };
+            let Some(caps) = caps else {
+                sub.line += 1;
+                sub.byte = 0;
+                continue;
+            };
+
+            let found = caps.get(0).unwrap();
+            let (start, end) = (found.start(), found.end());
+
+            let replace = if sub.confirm {
+                match answer.take() {
+                    None => {
+                        let col = char_to_grapheme(&line, line[..start].chars().count());
+                        self.jump_to((sub.line, col));
+                        self.status_message = format!("replace with {} (y/n/a/q/l)?", sub.expand(&caps));
+                        self.pending_substitute = Some(sub);
+                        return;
+                    },
+                    Some('y') => true,
+                    Some('n') => false,
+                    Some('a') => {
+                        sub.confirm = false;
+                        true
+                    },
+                    Some('l') => {
+                        stop = true;
+                        true
+                    },
+                    Some(_) => break,
+                }
+            } else {
+                true
+            };
+
+            if replace {
+                if !sub.recorded {
+                    self.record_edit();
+                    sub.recorded = true;
+                }
+
+                let text = sub.expand(&caps);
+                let start_c = line[..start].chars().count();
+                let end_c = start_c + line[start..end].chars().count();
+                self.delete_text((sub.line, start_c), (sub.line, end_c));
+                self.insert_text(sub.line, start_c, &text);
+
+                sub.count += 1;
+                if sub.last_changed != Some(sub.line) {
+                    sub.lines += 1;
+                }
+
+                let added = text.matches('\n').count();
+                sub.end += added;
+                sub.line += added;
+                sub.last_changed = Some(sub.line);
+                sub.byte = match text.rfind('\n') {
+                    Some(pos) => text.len() - pos - 1,
+                    None => start + text.len(),
+                };
+            } else {
+                sub.byte = end;
+            }
+
+            // an empty match would be found again at the same place
+            if start == end {
+                let current = self.buf().content.line(sub.line);
+                sub.byte += current[sub.byte.min(current.len())..].chars().next().map_or(1, char::len_utf8);
+            }
+
+            if !sub.global {
+                sub.line += 1;
+                sub.byte = 0;
+            }
+        }
+
+        if let Some(line) = sub.last_changed {
+            self.jump_to((line, 0));
+        }
+
+        self.status_message = if sub.count == 0 {
+            format!("Pattern not found: {}", sub.regex.as_str())
+        } else {
+            format!("{} substitution{} on {} line{}",
+                sub.count,
+                if sub.count == 1 { "" } else { "s" },
+                sub.lines,
+                if sub.lines == 1 { "" } else { "s" }
+            )
+        };
+    }
+
+    fn jump_to(&mut self, (line, col): (usize, usize)) {
+        self.cursor_l = line.min(self.buf().content.line_count() - 1);
+        self.cursor_c = col.min(self.line_cols(self.cursor_l));
+        self.adjust_scroll();
+    }
+
+    pub fn split_window(&mut self, direction: Direction) {
+        self.save_window();
+        let window = &self.windows[self.focus];
+        let new = window.clone();
+        self.windows.push(new);
+
+        let id = self.windows.len() - 1;
+        self.layout.split(self.focus, id, direction);
+        self.focus_window(id);
+    }
+
+    pub fn close_window(&mut self) {
+        if self.windows.len() == 1 {
+            self.status_message = "Cannot close last window".to_string();
+            return;
+        }
+
+        let closed = self.focus;
+        self.windows.remove(closed);
+        self.layout.remove(closed);
+
+        self.focus = closed.saturating_sub(1);
+        self.load_window();
+    }
+
+    pub fn next_window(&mut self) {
+        let rects = self.window_rects();
+        let pos = rects.iter().position(|(id, _)| *id == self.focus).unwrap_or(0);
+        let (next, _) = rects[(pos + 1) % rects.len()];
+        self.focus_window(next);
+    }
+
+    // moves to the closest window on the side given by a vim key (h j k l)
+    pub fn focus_toward(&mut self, key: char) {
+        let current = self.focused_rect();
+        let (cx, cy) = (current.x + current.width / 2, current.y + current.height / 2);
+
+        let target = self.window_rects().into_iter()
+            .filter(|(id, r)| *id != self.focus && match key {
+                'h' => r.x + r.width < current.x,
+                'l' => r.x > current.x + current.width,
+                'k' => r.y + r.height < current.y,
+                'j' => r.y > current.y + current.height,
+                _ => false,
+            })
+            .min_by_key(|(_, r)| {
+                let (x, y) = (r.x + r.width / 2, r.y + r.height / 2);
+                cx.abs_diff(x) as u32 + cy.abs_diff(y) as u32
+            });
+
+        if let Some((id, _)) = target {
+            self.focus_window(id);
+        }
+    }
+
+    pub fn focus_window(&mut self, id: usize) {
+        if id == self.focus || id >= self.windows.len() {
+            return;
+        }
+
+        self.buf_mut().history.close_group();
+        self.save_window();
+        self.focus = id;
+        self.load_window();
+    }
+
+    fn save_window(&mut self) {
+        let window = &mut self.windows[self.focus];
+        window.cursor_l = self.cursor_l;
+        window.cursor_c = self.cursor_c;
+        window.scroll_offset = self.scroll_offset;
+    }
+
+    // another window may have changed the buffer meanwhile, so the saved
+    // cursor is clamped before use
+    fn load_window(&mut self) {
+        let window = &self.windows[self.focus];
+        self.current = window.buffer;
+        self.cursor_l = window.cursor_l.min(self.buf().content.line_count() - 1);
+        self.cursor_c = window.cursor_c.min(self.line_cols(self.cursor_l));
+        self.scroll_offset = window.scroll_offset.min(self.cursor_l);
+        self.adjust_scroll();
+    }
+
+    fn text_area(&self) -> Rect {
+        let (cols, rows) = terminal::size().unwrap();
+        let sidebar_width = self.sidebar_cols(cols);
+
+        Rect {
+            x: sidebar_width,
+            y: 6,
+            width: cols - sidebar_width,
+            height: rows.saturating_sub(8),
+        }
+    }
+
+    fn window_rects(&self) -> Vec<(usize, Rect)> {
+        let mut rects = Vec::new();
+        self.layout.rects(self.text_area(), &mut rects);
+        rects
+    }
+
+    fn focused_rect(&self) -> Rect {
+        self.window_rects().into_iter()
+            .find(|(id, _)| *id == self.focus)
+            .map(|(_, rect)| rect)
+            .unwrap_or_else(|| self.text_area())
+    }
+
+    fn filetype(&self) -> &'static str {
+        filetype::detect(&self.buf().file_path)
+    }
+
+    fn indent_unit(&self) -> String {
+        " ".repeat(self.config.tab_width(self.filetype()))
+    }
+
+    // `:set key=value` changes an option, `:set key` shows its value
+    pub fn set_option(&mut self, arg: &str) {
+        let arg = arg.trim();
+
+        let (key, value) = match arg.split_once('=') {
+            Some((key, value)) => (key.trim(), Some(value.trim())),
+            None => (arg.trim_end_matches('?'), None),
+        };
+        if matches!(key, "fileformat" | "ff" | "fileencoding" | "fenc") {
+            self.set_buffer_option(key, value);
+            return;
+        }
+
+        if let Some((key, value)) = arg.split_once('=') {
+            match self.config.set(key.trim(), value.trim()) {
+                Ok(()) => {
+                    self.status_message = format!("{}={}", key.trim(), value.trim());
+                    self.registers.set_provider(clipboard::provider(&self.config.clipboard));
+                    self.tree.show_hidden = self.config.show_hidden;
+                    self.refresh_sidebar();
+                    self.adjust_scroll();
+                },
+                Err(err) => self.status_message = err,
+            }
+        } else {
+            let key = arg.trim_end_matches('?');
+            self.status_message = match self.config.get(key) {
+                Some(value) => format!("{}={}", key, value),
+                None => format!("Unknown option: {}", key),
+            };
+        }
+    }
+
+    // fileformat (ff) and fileencoding (fenc) belong to the buffer and take
+    // effect the next time it's written
+    fn set_buffer_option(&mut self, key: &str, value: Option<&str>) {
+        let format = self.buf().format;
+        let line_ending = matches!(key, "fileformat" | "ff");
+        let name = if line_ending { "fileformat" } else { "fileencoding" };
+
+        let Some(value) = value else {
+            let current = if line_ending { format.line_ending.name() } else { format.encoding.name() };
+            self.status_message = format!("{}={}", name, current);
+            return;
+        };
+
+        let changed = if line_ending {
+            LineEnding::from_name(value).map(|line_ending| Format { line_ending, ..format })
+        } else {
+            Encoding::from_name(value).map(|encoding| Format { encoding, ..format })
+        };
+
+        match changed {
+            Some(changed) => {
+                if changed != format {
+                    self.buf_mut().format = changed;
+                    self.buf_mut().modified = true;
+                }
+                self.status_message = format!("{}={}", name, value);
+            },
+            None if line_ending => self.status_message = format!("{}: expected unix or dos", name),
+            None => self.status_message = format!("{}: expected one of {}", name, Encoding::NAMES.join(", ")),
+        }
+    }
+
+    fn buffer_name(&self, buffer: &Buffer) -> String {
+        if buffer.is_empty_file() {
+            String::from("Empty File")
+        } else {
+            relative_path(&self.current_dir, &buffer.file_path)
+        }
+    }
+
+    pub fn insert_char(&mut self, c: char) {
+        if self.cursor_l < self.buf().content.line_count() {
+            self.record_edit();
+            let idx = self.cursor_char();
+            self.insert_text(self.cursor_l, idx, c.encode_utf8(&mut [0; 4]));
+            self.set_cursor_char(idx + 1);
+            self.log_insert(c);
+        }
+    }
+
+    pub fn handle_enter(&mut self) {
+        if self.cursor_l >= self.buf().content.line_count() {
+            return;
+        }
+
+        self.record_edit();
+        self.log_insert('\n');
+        let current_line = self.buf().content.line(self.cursor_l);
+
+        let current_indent = current_line
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .collect::<String>();
+
+        let indent_unit = self.indent_unit();
+        let idx = self.cursor_char();
+        let current_char = current_line.chars().nth(idx);
+        let prev_char = if idx > 0 {
+            current_line.chars().nth(idx - 1)
+        } else {
+            None
+        };
+
+        if matches!(
+            (prev_char, current_char),
+            (Some('{'), Some('}')) |
+            (Some('['), Some(']')) |
+            (Some('('), Some(')'))
+        ) {
+            let text = format!("\n{}{}\n{}", current_indent, indent_unit, current_indent);
+            self.insert_text(self.cursor_l, idx, &text);
+            self.cursor_c = grapheme_count(&current_indent) + grapheme_count(&indent_unit);
+        } else {
+            self.insert_text(self.cursor_l, idx, &format!("\n{}", current_indent));
+            self.cursor_c = grapheme_count(&current_indent);
+        }
+        self.cursor_l += 1;
+    }
+
+
+    pub fn handle_backspace(&mut self) {
+        self.log_insert('\u{8}');
+        if self.cursor_c > 0 {
+            self.record_edit();
+            let line = self.buf().content.line(self.cursor_l);
+            let start = grapheme_to_char(&line, self.cursor_c - 1);
+            let end = grapheme_to_char(&line, self.cursor_c);
+            self.delete_text((self.cursor_l, start), (self.cursor_l, end));
+            self.cursor_c -= 1;
+        } else if self.cursor_l > 0 {
+            self.record_edit();
+            self.cursor_l -= 1;
+            self.cursor_c = self.line_cols(self.cursor_l);
+            let end = self.buf().content.line_len(self.cursor_l);
+            self.delete_text((self.cursor_l, end), (self.cursor_l + 1, 0));
+        }
+    }
+
+    pub fn handle_delete(&mut self) {
+        if self.cursor_l >= self.buf().content.line_count() {
+            return;
+        }
+
+        self.log_insert('\u{7f}');
+        if self.cursor_c < self.line_cols(self.cursor_l) {
+            self.record_edit();
+            let line = self.buf().content.line(self.cursor_l);
+            let start = grapheme_to_char(&line, self.cursor_c);
+            let end = grapheme_to_char(&line, self.cursor_c + 1);
+            self.delete_text((self.cursor_l, start), (self.cursor_l, end));
+        } else if self.cursor_l < self.buf().content.line_count() - 1 {
+            self.record_edit();
+            let end = self.buf().content.line_len(self.cursor_l);
+            self.delete_text((self.cursor_l, end), (self.cursor_l + 1, 0));
+        }
+    }
+    
+    pub fn handle_tab(&mut self) {
+        if self.cursor_l < self.buf().content.line_count() {
+            self.record_edit();
+            let idx = self.cursor_char();
+            let indent_unit = self.indent_unit();
+            self.insert_text(self.cursor_l, idx, &indent_unit);
+            self.cursor_c += grapheme_count(&indent_unit);
+            self.log_insert('\t');
+        }
+    }
+
+    pub fn closing_pair(&self, c: char) -> Option<char> {
+        self.config.auto_pairs(self.filetype())
+            .iter()
+            .find(|(open, _)| *open == c)
+            .map(|(_, close)| *close)
+    }
+
+    pub fn auto_close(&mut self, c: char) {
+        let Some(close) = self.closing_pair(c) else { return };
+
+        if self.cursor_l < self.buf().content.line_count() {
+            self.record_edit();
+            let idx = self.cursor_char();
+            self.insert_text(self.cursor_l, idx, &format!("{}{}", c, close));
+            self.set_cursor_char(idx + 1);
+            self.log_insert(c);
+        }
+    }
+
+    pub fn type_char(&mut self, c: char) {
+        if self.closing_pair(c).is_some() {
+            self.auto_close(c);
+        } else {
+            self.insert_char(c);
+        }
+    }
+
+    // lines go below the cursor line (above with P), text right after the
+    // cursor (at it with P) and blocks column by column from there
+    pub fn paste(&mut self, before: bool) {
+        let name = self.register.take();
+        let Some(register) = self.registers.get(name) else {
+            if let Some(name) = name {
+                self.status_message = format!("Register {} is empty", name);
+            }
+            return;
+        };
+
+        self.record_edit();
+        let text = register.text;
+        let cols = self.line_cols(self.cursor_l);
+        let col = if before { self.cursor_c } else { (self.cursor_c + 1).min(cols) };
+
+        match register.kind {
+            RegisterKind::Line => {
+                if before {
+                    self.insert_text(self.cursor_l, 0, &format!("{}\n", text));
+                } else {
+                    let end = self.buf().content.line_len(self.cursor_l);
+                    self.insert_text(self.cursor_l, end, &format!("\n{}", text));
+                    self.cursor_l += 1;
+                }
+                self.cursor_c = normal::first_non_blank(&self.buf().content.line(self.cursor_l));
+            },
+            RegisterKind::Char => {
+                let (_, idx) = self.char_pos((self.cursor_l, col));
+                self.insert_text(self.cursor_l, idx, &text);
+                if text.contains('\n') {
+                    self.set_cursor_char(idx);
+                } else {
+                    self.set_cursor_char(idx + text.chars().count().saturating_sub(1));
+                }
+            },
+            RegisterKind::Block => {
+                for (i, piece) in text.split('\n').enumerate() {
+                    let line = self.cursor_l + i;
+                    if line == self.buf().content.line_count() {
+                        let end = self.buf().content.line_len(line - 1);
+                        self.insert_text(line - 1, end, "\n");
+                    }
+
+                    // lines shorter than the block column get padded
+                    let line_cols = self.line_cols(line);
+                    if line_cols < col {
+                        let end = self.buf().content.line_len(line);
+                        self.insert_text(line, end, &" ".repeat(col - line_cols));
+                    }
+
+                    let (_, at) = self.char_pos((line, col));
+                    self.insert_text(line, at, piece);
+                }
+                self.cursor_c = col;
+            },
+        }
+
+        self.adjust_scroll();
+        self.status_message = "pasted".to_string();
+    }
+
+    // a register picked with "x only lasts for the next yank, delete or paste
+    fn store_register(&mut self, register: Register, delete: bool) {
+        let name = self.register.take();
+        let result = if delete {
+            self.registers.delete(name, register)
+        } else {
+            self.registers.yank(name, register)
+        };
+
+        if let Err(err) = result {
+            self.status_message = err;
+        }
+    }
+
+    // Right: opens a file, unfolds a folder or steps into an open one
+    pub fn open_selected(&mut self) {
+        let Some(entry) = self.tree.entries.get(self.file_cursor) else { return };
+        let path = entry.path.clone();
+
+        if !entry.is_dir {
+            let name = entry.name.clone();
+            self.open_file_from_command(path.to_str().unwrap());
+            self.status_message = format!("Arquivo aberto: {}", name);
+        } else if entry.expanded {
+            let depth = entry.depth;
+            if self.tree.entries.get(self.file_cursor + 1).is_some_and(|next| next.depth > depth) {
+                self.move_down_files();
+            }
+        } else {
+            self.tree.set_expanded(&path, true);
+            self.refresh_sidebar();
+        }
+    }
+
+    // Left: folds an open folder or goes to the folder above. from the top
+    // level the root moves up one folder
+    pub fn go_back(&mut self) {
+        if let Some(entry) = self.tree.entries.get(self.file_cursor) && entry.is_dir && entry.expanded {
+            let path = entry.path.clone();
+            self.tree.set_expanded(&path, false);
+            self.refresh_sidebar();
+        } else if let Some(parent) = self.tree.parent(self.file_cursor) {
+            self.file_cursor = parent;
+            self.adjust_sidebar_scroll();
+        } else if let Some(parent) = self.current_dir.parent() {
+            let old_root = self.current_dir.clone();
+            self.current_dir = parent.to_path_buf();
+            self.tree.set_expanded(&old_root, true);
+            self.select_in_sidebar(&old_root);
+            self.status_message = format!("Diretório: {}", self.current_dir.display());
+        }
+    }
+
+    pub fn move_right(&mut self) {
+        if self.cursor_c < self.line_cols(self.cursor_l) {
+            self.cursor_c += 1;
+            self.adjust_scroll();
+        } else if self.cursor_l < self.buf().content.line_count() - 1 {
+            self.cursor_l += 1;
+            self.cursor_c = 0;
+            self.adjust_scroll();
+        }
+    }
+
+    pub fn move_left(&mut self) {
+        if self.cursor_c > 0 {
+            self.cursor_c -= 1;
+            self.adjust_scroll();
+        } else if self.cursor_l > 0 {
+            self.cursor_l -= 1;
+            self.cursor_c = self.line_cols(self.cursor_l);
+            self.adjust_scroll();
+        }
+    }
+
+    fn line_cols(&self, line: usize) -> usize {
+        grapheme_count(&self.buf().content.line(line))
+    }
+
+    fn cursor_char(&self) -> usize {
+        grapheme_to_char(&self.buf().content.line(self.cursor_l), self.cursor_c)
+    }
+
+    fn set_cursor_char(&mut self, idx: usize) {
+        self.cursor_c = char_to_grapheme(&self.buf().content.line(self.cursor_l), idx);
+    }
+
+    fn cursor_x(&self) -> u16 {
+        screen_col(&self.buf().content.line(self.cursor_l), self.cursor_c) as u16
+    }
+
+    pub fn move_up_files(&mut self) {
+        if self.file_cursor > 0 {
+            self.file_cursor -= 1;
+            self.adjust_sidebar_scroll();
+        }
+    }
+
+    pub fn move_down_files(&mut self) {
+        if self.file_cursor + 1 < self.tree.entries.len() {
+            self.file_cursor += 1;
+            self.adjust_sidebar_scroll();
+        }
+    }
+
+    pub fn move_up(&mut self) {
+        if self.cursor_l > 0 {
+            self.cursor_l -= 1;
+            self.cursor_c = std::cmp::min(self.cursor_c, self.line_cols(self.cursor_l));
+            self.adjust_scroll();
+        }
+    }
+
+    pub fn move_down(&mut self) {
+        if self.cursor_l < self.buf().content.line_count() - 1 {
+            self.cursor_l += 1;
+            self.cursor_c = std::cmp::min(self.cursor_c, self.line_cols(self.cursor_l));
+            self.adjust_scroll();
+        }
+    }
+
+    fn insert_text(&mut self, line: usize, col: usize, text: &str) {
+        let buffer = self.buf_mut();
+        buffer.content.insert(line, col, text);
+        buffer.highlighter.invalidate(line);
+        buffer.diff.invalidate();
+        buffer.synced = false;
+        buffer.swapped = false;
+    }
+
+    fn delete_text(&mut self, start: (usize, usize), end: (usize, usize)) {
+        let buffer = self.buf_mut();
+        buffer.content.delete(start, end);
+        buffer.highlighter.invalidate(start.0);
+        buffer.diff.invalidate();
+        buffer.synced = false;
+        buffer.swapped = false;
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            content: self.buf().content.clone(),
+            cursor_l: self.cursor_l,
+            cursor_c: self.cursor_c,
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.buf_mut().content = snapshot.content;
+        self.buf_mut().highlighter.invalidate(0);
+        self.buf_mut().diff.invalidate();
+        self.buf_mut().synced = false;
+        self.buf_mut().swapped = false;
+        self.cursor_l = snapshot.cursor_l.min(self.buf().content.line_count() - 1);
+        self.cursor_c = snapshot.cursor_c.min(self.line_cols(self.cursor_l));
+        self.adjust_scroll();
+    }
+
+    fn record_edit(&mut self) {
+        if !self.buf().history.in_group() {
+            let snapshot = self.snapshot();
+            self.buf_mut().history.push(snapshot);
+        }
+
+        if matches!(self.mode, Mode::Insert) {
+            self.buf_mut().history.open_group();
+        }
+        self.buf_mut().modified = true;
+    }
+
+    pub fn undo(&mut self) {
+        let current = self.snapshot();
+        match self.buf_mut().history.undo(current) {
+            Some(previous) => {
+                self.restore(previous);
+                self.buf_mut().modified = true;
+                self.status_message = "undo".to_string();
+            },
+            None => self.status_message = "Already at oldest change".to_string(),
+        }
+    }
+
+    pub fn redo(&mut self) {
+        let current = self.snapshot();
+        match self.buf_mut().history.redo(current) {
+            Some(next) => {
+                self.restore(next);
+                self.buf_mut().modified = true;
+                self.status_message = "redo".to_string();
+            },
+            None => self.status_message = "Already at newest change".to_string(),
+        }
+    }
+
+    // the `:`, `/` or `?` line being typed. its keys skip the keymap
+    pub fn in_command_line(&self) -> bool {
+        matches!(self.mode, Mode::Command) && !self.command.is_empty()
+    }
+
+    fn map_mode(&self) -> MapMode {
+        match self.mode {
+            Mode::Command => MapMode::Normal,
+            Mode::Insert => MapMode::Insert,
+            Mode::Visual => MapMode::Visual,
+        }
+    }
+
+    pub fn resolve_key(&mut self, key: Key) -> Vec<Input> {
+        self.pending_input.push(key);
+        self.keymap.resolve(self.map_mode(), &mut self.pending_input, false)
+    }
+
+    // how long to wait for the rest of a key sequence
+    pub fn input_timeout(&self) -> Option<Duration> {
+        (!self.pending_input.is_empty()).then(|| Duration::from_millis(self.config.key_timeout))
+    }
+
+    pub fn flush_input(&mut self) -> Vec<Input> {
+        self.keymap.resolve(self.map_mode(), &mut self.pending_input, true)
+    }
+
+    pub fn run_action(&mut self, name: &str) {
+        match name {
+            "escape" => self.escape(),
+            "undo" => self.undo(),
+            "redo" => self.redo(),
+            "file.save" => {
+                self.write_buffer(None, false);
+            },
+            "insert.newline" => self.handle_enter(),
+            "insert.backspace" => self.handle_backspace(),
+            "insert.delete" => self.handle_delete(),
+            "insert.tab" => self.handle_tab(),
+            "cursor.left" => self.move_left(),
+            "cursor.right" => self.move_right(),
+            "cursor.up" => self.move_up(),
+            "cursor.down" => self.move_down(),
+            "files.up" => self.move_up_files(),
+            "files.down" => self.move_down_files(),
+            "files.open" => self.open_selected(),
+            "files.back" => self.go_back(),
+            "files.find" => self.open_finder(""),
+            "files.create" => self.file_action("create"),
+            "files.rename" => self.file_action("rename"),
+            "files.delete" => self.file_action("delete"),
+            "files.copy" => self.file_action("copy"),
+            "files.move" => self.file_action("move"),
+            "files.hidden" => self.toggle_hidden(),
+            "sidebar.toggle" => self.toggle_sidebar(),
+            "sidebar.wider" => self.resize_sidebar(2),
+            "sidebar.narrower" => self.resize_sidebar(-2),
+            "window.next" => self.next_window(),
+            "window.left" => self.focus_toward('h'),
+            "window.down" => self.focus_toward('j'),
+            "window.up" => self.focus_toward('k'),
+            "window.right" => self.focus_toward('l'),
+            "window.split" => self.split_window(Direction::Horizontal),
+            "window.vsplit" => self.split_window(Direction::Vertical),
+            "window.close" => self.close_window(),
+            "buffer.next" => self.next_buffer(),
+            "buffer.prev" => self.prev_buffer(),
+            "visual.char" => self.start_visual(VisualKind::Char),
+            "visual.line" => self.start_visual(VisualKind::Line),
+            "visual.block" => self.start_visual(VisualKind::Block),
+            "search.next" => self.search_next(false),
+            "search.prev" => self.search_next(true),
+            "lsp.hover" => self.lsp_hover(),
+            "lsp.definition" => self.lsp_definition(),
+            "lsp.complete" => self.lsp_complete(),
+            _ => self.status_message = format!("Unknown action: {}", name),
+        }
+    }
+
+    // keys without a binding: text in insert mode, the command grammar in
+    // command mode and the selection keys in visual mode
+    pub fn default_key(&mut self, key: Key) {
+        let KeyCode::Char(c) = key.code else { return };
+        if key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) {
+            return;
+        }
+
+        match self.mode {
+            Mode::Insert => {
+                self.type_char(c);
+                self.complete_on_trigger(c);
+            },
+            Mode::Visual => self.visual_key(c),
+            Mode::Command if self.pending_keys.is_empty() && matches!(c, ':' | '/' | '?') => {
+                self.command.push(c);
+                if c != ':' {
+                    self.update_search();
+                }
+            },
+            Mode::Command => self.normal_key(c),
+        }
+    }
+
+    // keys typed on the `:`, `/` or `?` line. true means quit
+    pub fn command_line_key(&mut self, key: Key) -> bool {
+        if !matches!(key.code, KeyCode::Tab | KeyCode::BackTab) {
+            self.completion = None;
+        }
+
+        match key.code {
+            KeyCode::Enter => return self.run_command(),
+            KeyCode::Esc => {
+                self.command_history.reset();
+                self.escape();
+            },
+            KeyCode::Backspace => {
+                self.command.pop();
+                self.command_history.reset();
+                if self.command.starts_with('/') || self.command.starts_with('?') {
+                    self.update_search();
+                } else {
+                    self.cancel_search();
+                }
+            },
+            KeyCode::Up | KeyCode::Down if self.command.starts_with(':') => {
+                let line = if key.code == KeyCode::Up {
+                    self.command_history.older(&self.command[1..])
+                } else {
+                    self.command_history.newer()
+                };
+                if let Some(line) = line {
+                    self.command = format!(":{}", line);
+                }
+            },
+            KeyCode::Tab => self.complete_command(false),
+            KeyCode::BackTab => self.complete_command(true),
+            KeyCode::Char(c) => {
+                self.command.push(c);
+                self.command_history.reset();
+                if self.command.starts_with('/') || self.command.starts_with('?') {
+                    self.update_search();
+                }
+            },
+            _ => {}
+        }
+
+        false
+    }
+
+    // Tab cycles through what the `:` line can become, Shift-Tab goes back.
+    // the choices are listed in the status line
+    fn complete_command(&mut self, back: bool) {
+        if !self.command.starts_with(':') {
+            return;
+        }
+
+        let (candidates, idx) = match self.completion.take() {
+            Some((candidates, idx)) => {
+                let len = candidates.len();
+                let idx = if back { (idx + len - 1) % len } else { (idx + 1) % len };
+                (candidates, idx)
+            },
+            None => {
+                let buffers: Vec<String> = self.buffers.iter().map(|b| self.buffer_name(b)).collect();
+                let candidates = command::complete(&self.command[1..], &self.current_dir, &buffers);
+                if candidates.is_empty() {
+                    self.status_message = "No completion".to_string();
+                    return;
+                }
+                let idx = if back { candidates.len() - 1 } else { 0 };
+                (candidates, idx)
+            },
+        };
+
+        self.command = format!(":{}", candidates[idx]);
+        if candidates.len() == 1 {
+            return;
+        }
+
+        // just the last word, and the file name of paths
+        let names: Vec<String> = candidates.iter().enumerate().map(|(i, candidate)| {
+            let word = candidate.rsplit(char::is_whitespace).next().unwrap_or(candidate);
+            let name = &word[word.trim_end_matches('/').rfind('/').map_or(0, |i| i + 1)..];
+            if i == idx { format!("[{}]", name) } else { name.to_string() }
+        }).collect();
+        self.status_message = names.join("  ");
+        self.completion = Some((candidates, idx));
+    }
+
+    // Enter on the command line. true means quit
+    fn run_command(&mut self) -> bool {
+        if self.command.starts_with('/') || self.command.starts_with('?') {
+            self.finish_search();
+            self.command.clear();
+            return false;
+        }
+
+        let line = std::mem::take(&mut self.command);
+        let line = line.strip_prefix(':').unwrap_or(&line);
+        self.command_history.push(line);
+
+        match command::parse(line) {
+            Ok(parsed) => self.execute_command(parsed),
+            Err(err) => {
+                self.status_message = err;
+                false
+            },
+        }
+    }
+
+    fn execute_command(&mut self, parsed: Parsed) -> bool {
+        let ExCommand { cmd, name, bang, arg } = match parsed {
+            Parsed::Empty => return false,
+            Parsed::Substitute(command) => {
+                self.substitute(&command);
+                return false;
+            },
+            Parsed::GoToLine(n) => {
+                let line = n.saturating_sub(1).min(self.buf().content.line_count() - 1);
+                let col = normal::first_non_blank(&self.buf().content.line(line));
+                self.jump_to((line, col));
+                return false;
+            },
+            Parsed::Command(command) => command,
+        };
+
+        match cmd {
+            Cmd::Edit if arg.is_empty() => self.reload_buffer(bang),
+            Cmd::Edit => {
+                self.open_file_from_command(&arg);
+                self.refresh_sidebar();
+            },
+            Cmd::Write => {
+                self.write_buffer((!arg.is_empty()).then_some(arg.as_str()), bang);
+            },
+            Cmd::WriteQuit => return self.write_buffer(None, bang) && self.quit(false, bang),
+            Cmd::Quit => return self.quit(false, bang),
+            Cmd::QuitAll => return self.quit(true, bang),
+            Cmd::Close => self.close_window(),
+            Cmd::Split | Cmd::VSplit => {
+                self.split_window(if cmd == Cmd::Split { Direction::Horizontal } else { Direction::Vertical });
+                if !arg.is_empty() {
+                    self.open_file_from_command(&arg);
+                }
+            },
+            Cmd::BufferNext => self.next_buffer(),
+            Cmd::BufferPrev => self.prev_buffer(),
+            Cmd::Buffers => self.list_buffers(),
+            Cmd::BufferDelete => {
+                if !arg.is_empty() {
+                    match self.find_buffer(&arg) {
+                        Ok(idx) => self.switch_buffer(idx),
+                        Err(err) => {
+                            self.status_message = err;
+                            return false;
+                        },
+                    }
+                }
+                self.delete_buffer(bang);
+            },
+            Cmd::Buffer => match self.find_buffer(&arg) {
+                Ok(idx) => self.switch_buffer(idx),
+                Err(err) => self.status_message = err,
+            },
+            Cmd::Set => self.set_option(&arg),
+            Cmd::NoHighlight => self.search = None,
+            Cmd::Map | Cmd::NMap | Cmd::IMap | Cmd::VMap => self.map_command(&name, &arg),
+            Cmd::Find => self.open_finder(&arg),
+            Cmd::Create => self.create_path(&arg),
+            Cmd::Rename => self.rename_selected(&arg),
+            Cmd::Remove => match self.tree.entries.get(self.file_cursor) {
+                Some(entry) => {
+                    let path = entry.path.clone();
+                    self.delete_path(&path, bang);
+                },
+                None => self.status_message = "No file selected".to_string(),
+            },
+            Cmd::Copy => self.transfer_selected(&arg, true),
+            Cmd::Move => self.transfer_selected(&arg, false),
+            Cmd::Cd => self.change_root(&arg),
+            Cmd::Grep => self.grep(&arg),
+            Cmd::QuickfixNext => self.quickfix_step(true),
+            Cmd::QuickfixPrev => self.quickfix_step(false),
+            Cmd::QuickfixList if self.quickfix.hits.is_empty() => self.status_message = "No grep results".to_string(),
+            Cmd::QuickfixList => self.quickfix.open = true,
+            Cmd::Help => match arg.as_str() {
+                "" | "commands" => self.pager = Some((command::help_lines(), 0)),
+                "keys" => self.show_keys(&[MapMode::Normal, MapMode::Insert, MapMode::Visual]),
+                topic => self.status_message = format!("No help for {}", topic),
+            },
+        }
+
+        false
+    }
+
+    // `:w` and `:w <file>`. errors go to the status line, true when the
+    // buffer got saved. without `!` a file another program changed is left
+    // alone
+    fn write_buffer(&mut self, path: Option<&str>, force: bool) -> bool {
+        if path.is_none() && !force && self.buf_mut().changed_on_disk() {
+            self.status_message = format!("{} changed on disk since it was read (add ! to overwrite)", self.buffer_name(self.buf()));
+            return false;
+        }
+
+        let result = match path {
+            Some(path) => self.save_as(path, force),
+            None => self.save(force),
+        };
+
+        match result {
+            Ok(()) => {
+                self.refresh_sidebar();
+                self.lsp_saved();
+                !self.buf().modified
+            },
+            Err(err) => {
+                self.status_message = format!("Could not save: {}", err);
+                false
+            },
+        }
+    }
+
+    // `:q` closes the window when there are others, the last one (or `:qa`)
+    // quits unless a buffer has unsaved changes. true when the editor quits
+    fn quit(&mut self, all: bool, force: bool) -> bool {
+        if !all && self.windows.len() > 1 {
+            self.close_window();
+            return false;
+        }
+
+        match self.modified_buffer() {
+            Some(idx) if !force => {
+                self.status_message = format!("No write since last change for buffer {} (add ! to override)", idx + 1);
+                false
+            },
+            _ => true,
+        }
+    }
+
+    // `:e` reads the file again, `:e!` also throws the changes away
+    fn reload_buffer(&mut self, force: bool) {
+        if self.buf().is_empty_file() {
+            self.status_message = "Argument required for :edit".to_string();
+            return;
+        }
+        if self.buf().modified && !force {
+            self.status_message = "No write since last change (add ! to override)".to_string();
+            return;
+        }
+
+        self.read_again(self.current);
+        self.status_message = format!("Reloaded: {}", self.buffer_name(self.buf()));
+    }
+
+    // puts what's on disk now in a buffer, keeping the cursors where they
+    // were. the old text stays one undo away
+    fn read_again(&mut self, idx: usize) {
+        let cursor = if idx == self.current {
+            (self.cursor_l, self.cursor_c)
+        } else {
+            (self.buffers[idx].cursor_l, self.buffers[idx].cursor_c)
+        };
+        let path = self.buffers[idx].file_path.clone();
+        let mut old = std::mem::replace(&mut self.buffers[idx], Buffer::new(&path));
+        old.remove_swap();
+        old.history.close_group();
+        old.history.push(Snapshot { content: old.content, cursor_l: cursor.0, cursor_c: cursor.1 });
+
+        let buffer = &mut self.buffers[idx];
+        let last = buffer.content.line_count() - 1;
+        buffer.history = old.history;
+        buffer.cursor_l = old.cursor_l.min(last);
+        buffer.cursor_c = old.cursor_c.min(grapheme_count(&buffer.content.line(buffer.cursor_l)));
+        buffer.scroll_offset = old.scroll_offset.min(last);
+
+        for window in self.windows.iter_mut().filter(|w| w.buffer == idx) {
+            window.cursor_l = window.cursor_l.min(last);
+        }
+        if idx == self.current {
+            self.jump_to((self.cursor_l, self.cursor_c));
+        }
+    }
+
+    // reloads the buffers whose file another program changed, or asks first
+    // when they have changes of their own. true when the screen needs a redraw
+    pub fn check_files(&mut self) -> bool {
+        if self.prompt.is_some() || self.last_file_check.elapsed() < FILE_CHECK {
+            return false;
+        }
+        self.last_file_check = Instant::now();
+
+        let mut redraw = false;
+        for idx in 0..self.buffers.len() {
+            if !self.buffers[idx].changed_on_disk() {
+                continue;
+            }
+            let name = self.buffer_name(&self.buffers[idx]);
+            redraw = true;
+
+            if self.buffers[idx].modified {
+                self.status_message = format!("{} changed on disk: (r)eload and lose your changes, or (k)eep them", name);
+                self.prompt = Some(Prompt::Conflict(idx));
+                break;
+            }
+            self.read_again(idx);
+            self.status_message = format!("{} changed on disk, reloaded", name);
+        }
+        redraw
+    }
+
+    // `:b 2`, `:b main` or `:b src/ma`: a buffer number or part of its name
+    fn find_buffer(&self, arg: &str) -> Result<usize, String> {
+        if let Ok(n) = arg.parse::<usize>() {
+            return match n {
+                1.. if n <= self.buffers.len() => Ok(n - 1),
+                _ => Err(format!("Buffer {} does not exist", n)),
+            };
+        }
+
+        let names: Vec<String> = self.buffers.iter().map(|b| self.buffer_name(b)).collect();
+        if let Some(idx) = names.iter().position(|name| name == arg) {
+            return Ok(idx);
+        }
+
+        let matches: Vec<usize> = (0..names.len()).filter(|i| names[*i].contains(arg)).collect();
+        match matches[..] {
+            [idx] => Ok(idx),
+            [] => Err(format!("No matching buffer for {}", arg)),
+            _ => Err(format!("More than one match for {}", arg)),
+        }
+    }
+
+    // `:map`, `:nmap`, `:imap` and `:vmap`. without arguments they list the
+    // bindings of their modes
+    pub fn map_command(&mut self, name: &str, args: &str) {
+        let modes: &[MapMode] = match name {
+            "map" => &[MapMode::Normal, MapMode::Visual],
+            "nmap" => &[MapMode::Normal],
+            "imap" => &[MapMode::Insert],
+            _ => &[MapMode::Visual],
+        };
+
+        let args = args.trim();
+        if args.is_empty() {
+            self.show_keys(modes);
+            return;
+        }
+
+        let Some((lhs, rhs)) = args.split_once(char::is_whitespace) else {
+            self.status_message = format!("Missing action for {}", args);
+            return;
+        };
+
+        self.status_message = match self.keymap.map(modes, lhs, rhs.trim()) {
+            Ok(()) => format!("{} -> {}", lhs, rhs.trim()),
+            Err(err) => err,
+        };
+    }
+
+    // `:help keys`
+    pub fn show_keys(&mut self, modes: &[MapMode]) {
+        let mut lines = Vec::new();
+
+        for mode in modes {
+            lines.push(format!("{} mode", mode.name()));
+            for mapping in self.keymap.bindings(*mode) {
+                let target = match &mapping.binding {
+                    keymap::Binding::Action(name) => {
+                        let description = keymap::ACTIONS.iter()
+                            .find(|(action, _)| action == name)
+                            .map_or("", |(_, description)| description);
+                        format!("{:<16} {}", name, description)
+                    },
+                    keymap::Binding::Keys(keys) => keymap::format_keys(keys),
+                };
+                lines.push(format!("    {:<16} {}", keymap::format_keys(&mapping.keys), target));
+            }
+            lines.push(String::new());
+        }
+
+        self.pager = Some((lines, 0));
+    }
+
+    // keys typed in command mode outside of a `:`, `/` or `?` line. they pile
+    // up in pending_keys until they make a whole command
+    pub fn normal_key(&mut self, c: char) {
+        self.pending_keys.push(c);
+
+        match normal::parse(&self.pending_keys) {
+            Parse::Incomplete => {},
+            Parse::Invalid => self.pending_keys.clear(),
+            Parse::Complete(action) => {
+                self.pending_keys.clear();
+                self.execute(action);
+            },
+        }
+    }
+
+    pub fn execute(&mut self, action: Action) {
+        self.register = action.register;
+        if action.command.is_change() {
+            self.last_change = Some(action);
+            self.last_insert.clear();
+        }
+
+        match action.command {
+            Command::Move(motion) => {
+                let pos = (self.cursor_l, self.cursor_c);
+                if let Some(target) = normal::motion_target(&self.buf().content, pos, motion, action.count) {
+                    self.jump_to(target);
+                }
+            },
+            Command::Operate(op, target) => self.operate(op, target, action.count),
+            Command::Insert => self.start_insert(),
+            Command::Append => {
+                self.cursor_c = (self.cursor_c + 1).min(self.line_cols(self.cursor_l));
+                self.start_insert();
+            },
+            Command::InsertLineStart => {
+                self.cursor_c = normal::first_non_blank(&self.buf().content.line(self.cursor_l));
+                self.start_insert();
+            },
+            Command::AppendLineEnd => {
+                self.cursor_c = self.line_cols(self.cursor_l);
+                self.start_insert();
+            },
+            Command::OpenBelow | Command::OpenAbove => {
+                self.start_insert();
+                self.record_edit();
+                let line = self.buf().content.line(self.cursor_l);
+                let indent: String = line.chars().take_while(|c| c.is_whitespace()).collect();
+
+                if action.command == Command::OpenBelow {
+                    let end = self.buf().content.line_len(self.cursor_l);
+                    self.insert_text(self.cursor_l, end, &format!("\n{}", indent));
+                    self.cursor_l += 1;
+                } else {
+                    self.insert_text(self.cursor_l, 0, &format!("{}\n", indent));
+                }
+                self.cursor_c = grapheme_count(&indent);
+                self.adjust_scroll();
+            },
+            Command::Visual => self.start_visual(VisualKind::Char),
+            Command::VisualLine => self.start_visual(VisualKind::Line),
+            Command::ToggleCase => {
+                let times = action.times();
+                self.operate(Operator::ToggleCase, Target::Motion(normal::Motion::Right), action.count);
+                self.cursor_c = (self.cursor_c + times).min(self.line_cols(self.cursor_l));
+            },
+            Command::Paste | Command::PasteBefore => {
+                for _ in 0..action.times() {
+                    self.register = action.register;
+                    self.paste(action.command == Command::PasteBefore);
+                }
+            },
+            Command::Undo => {
+                for _ in 0..action.times() {
+                    self.undo();
+                }
+            },
+            Command::Repeat => self.repeat(action.count),
+            Command::SearchNext | Command::SearchPrev => {
+                for _ in 0..action.times() {
+                    self.search_next(action.command == Command::SearchPrev);
+                }
+            },
+            Command::NextHunk | Command::PrevHunk => {
+                self.jump_to_hunk(action.command == Command::NextHunk, action.times());
+            },
+            Command::Hover => self.lsp_hover(),
+            Command::Definition => self.lsp_definition(),
+        }
+    }
+
+    // ]c / [c, to the first line of the count-th hunk after or before the cursor
+    fn jump_to_hunk(&mut self, forward: bool, count: usize) {
+        let buffer = self.buf_mut();
+        buffer.diff.refresh(&buffer.content);
+
+        let last = buffer.content.line_count() - 1;
+        // a deletion is marked on the line above it
+        let lines: Vec<usize> = buffer.diff.hunks.iter()
+            .map(|hunk| match hunk.kind {
+                HunkKind::Deleted => hunk.start.saturating_sub(1),
+                _ => hunk.start,
+            }.min(last))
+            .collect();
+
+        let target = if forward {
+            lines.iter().filter(|l| **l > self.cursor_l).nth(count - 1)
+        } else {
+            lines.iter().rev().filter(|l| **l < self.cursor_l).nth(count - 1)
+        };
+
+        match target {
+            Some(&line) => {
+                self.cursor_l = line;
+                self.cursor_c = normal::first_non_blank(&self.buf().content.line(line));
+                self.adjust_scroll();
+            },
+            None => self.status_message = "No more hunks".to_string(),
+        }
+    }
+
+    // leaves insert, visual or a half typed search and goes back to command mode
+    pub fn escape(&mut self) {
+        if self.search_origin.is_some() {
+            self.cancel_search();
+        }
+        if matches!(self.mode, Mode::Visual) {
+            self.leave_visual();
+        }
+        self.finish_block_insert();
+        self.mode = Mode::Command;
+        self.command.clear();
+        self.pending_keys.clear();
+        self.register = None;
+        self.buf_mut().history.close_group();
+
+        if let Some(log) = self.insert_log.take() {
+            self.last_insert = log;
+        }
+    }
+
+    // what gets typed until Esc is kept so `.` can type it again
+    fn start_insert(&mut self) {
+        self.mode = Mode::Insert;
+        self.visual_start = None;
+        self.insert_log = Some(String::new());
+    }
+
+    fn log_insert(&mut self, c: char) {
+        if let Some(log) = &mut self.insert_log {
+            log.push(c);
+        }
+    }
+
+    fn repeat(&mut self, count: Option<usize>) {
+        let Some(mut action) = self.last_change else {
+            return;
+        };
+        if count.is_some() {
+            action.count = count;
+        }
+
+        let typed = self.last_insert.clone();
+        self.execute(action);
+
+        if matches!(self.mode, Mode::Insert) {
+            for c in typed.chars() {
+                match c {
+                    '\n' => self.handle_enter(),
+                    '\u{8}' => self.handle_backspace(),
+                    '\u{7f}' => self.handle_delete(),
+                    '\t' => self.handle_tab(),
+                    c => self.type_char(c),
+                }
+            }
+            self.escape();
+        }
+    }
+
+    fn operate(&mut self, op: Operator, target: Target, count: Option<usize>) {
+        let pos = (self.cursor_l, self.cursor_c);
+        let last_line = self.buf().content.line_count() - 1;
+
+        // linewise ranges go from start.0 to end.0, the others from start up
+        // to end (exclusive)
+        let range = match target {
+            Target::Line => {
+                let end = (pos.0 + count.unwrap_or(1) - 1).min(last_line);
+                Some(((pos.0, 0), (end, 0), true))
+            },
+            Target::Object(object) => {
+                normal::object_range(&self.buf().content, pos, object).map(|(start, end)| (start, end, false))
+            },
+            Target::Motion(motion) => {
+                // cw stops at the end of the word, like ce
+                let on_word = self.buf().content.line(pos.0)
+                    .chars()
+                    .nth(self.cursor_char())
+                    .is_some_and(|c| !c.is_whitespace());
+                let motion = if op == Operator::Change && motion == normal::Motion::WordForward && on_word {
+                    normal::Motion::WordEnd
+                } else {
+                    motion
+                };
+
+                normal::motion_target(&self.buf().content, pos, motion, count).map(|target| {
+                    let (start, mut end) = if target < pos { (target, pos) } else { (pos, target) };
+                    if motion.inclusive() {
+                        end.1 += 1;
+                    }
+                    // dw on the last word of a line keeps the line break
+                    if motion == normal::Motion::WordForward
+                        && end.0 > start.0
+                        && end.1 <= normal::first_non_blank(&self.buf().content.line(end.0))
+                    {
+                        end = (end.0 - 1, self.line_cols(end.0 - 1));
+                    }
+                    (start, end, motion.linewise())
+                })
+            },
+        };
+
+        if let Some((start, end, linewise)) = range {
+            self.apply_operator(op, start, end, linewise);
+        }
+    }
+
+    // runs `op` over a range from a motion, a text object or a visual
+    // selection
+    fn apply_operator(&mut self, op: Operator, start: (usize, usize), end: (usize, usize), linewise: bool) {
+        if !linewise && start == end && op != Operator::Change {
+            return;
+        }
+
+        let (from, to) = if linewise {
+            ((start.0, 0), (end.0, self.buf().content.line_len(end.0)))
+        } else {
+            (self.char_pos(start), self.char_pos(end))
+        };
+        let text = self.buf().content.slice(from, to);
+        let kind = if linewise { RegisterKind::Line } else { RegisterKind::Char };
+
+        match op {
+            Operator::Yank => {
+                self.status_message = "copied".to_string();
+                self.store_register(Register::new(text, kind), false);
+                self.jump_to(if linewise { (start.0, self.cursor_c) } else { start });
+            },
+            Operator::Delete | Operator::Change => {
+                if op == Operator::Change {
+                    self.start_insert();
+                }
+                self.record_edit();
+                self.store_register(Register::new(text, kind), true);
+
+                if linewise && op == Operator::Change {
+                    let line = self.buf().content.line(start.0);
+                    let indent: String = line.chars().take_while(|c| c.is_whitespace()).collect();
+                    let end_len = self.buf().content.line_len(end.0);
+                    self.delete_text((start.0, 0), (end.0, end_len));
+                    self.insert_text(start.0, 0, &indent);
+                    self.jump_to((start.0, grapheme_count(&indent)));
+                } else if linewise {
+                    self.delete_lines(start.0, end.0);
+                    let line = start.0.min(self.buf().content.line_count() - 1);
+                    let col = normal::first_non_blank(&self.buf().content.line(line));
+                    self.jump_to((line, col));
+                } else {
+                    self.delete_text(from, to);
+                    self.jump_to(start);
+                }
+            },
+            Operator::ToggleCase => {
+                self.record_edit();
+                let toggled = toggle_case(&text);
+                self.delete_text(from, to);
+                self.insert_text(from.0, from.1, &toggled);
+                self.jump_to(start);
+            },
+            Operator::Indent | Operator::Dedent => {
+                self.record_edit();
+                let unit = self.indent_unit();
+
+                for line in start.0..=end.0 {
+                    let text = self.buf().content.line(line);
+                    if op == Operator::Indent {
+                        if !text.is_empty() {
+                            self.insert_text(line, 0, &unit);
+                        }
+                    } else {
+                        let width = if text.starts_with('\t') {
+                            1
+                        } else {
+                            text.chars().take(unit.len()).take_while(|c| *c == ' ').count()
+                        };
+                        self.delete_text((line, 0), (line, width));
+                    }
+                }
+
+                let col = normal::first_non_blank(&self.buf().content.line(start.0));
+                self.jump_to((start.0, col));
+            },
+        }
+    }
+
+    // v, V and Ctrl-v. the same key again leaves visual mode, another one
+    // changes the kind and keeps the selection
+    pub fn start_visual(&mut self, kind: VisualKind) {
+        if matches!(self.mode, Mode::Visual) {
+            if self.visual_kind == kind {
+                self.leave_visual();
+            } else {
+                self.visual_kind = kind;
+            }
+            return;
+        }
+
+        self.mode = Mode::Visual;
+        self.visual_kind = kind;
+        self.visual_start = Some((self.cursor_l, self.cursor_c));
+    }
+
+    // the selected lines are kept for '<,'>
+    fn leave_visual(&mut self) {
+        if let Some((line, _)) = self.visual_start.take() {
+            self.last_visual = Some((line.min(self.cursor_l), line.max(self.cursor_l)));
+        }
+        self.mode = Mode::Command;
+    }
+
+    pub fn visual_key(&mut self, c: char) {
+        if self.pending_keys == "\"" {
+            self.pending_keys.clear();
+            if registers::is_register(c) {
+                self.register = Some(c);
+            }
+            return;
+        }
+
+        if self.pending_keys.is_empty() {
+            let op = match c {
+                'd' | 'x' => Some(Operator::Delete),
+                'c' | 's' => Some(Operator::Change),
+                'y' => Some(Operator::Yank),
+                '>' => Some(Operator::Indent),
+                '<' => Some(Operator::Dedent),
+                '~' => Some(Operator::ToggleCase),
+                _ => None,
+            };
+            if let Some(op) = op {
+                self.visual_operate(op);
+                return;
+            }
+
+            match c {
+                'v' => return self.start_visual(VisualKind::Char),
+                'V' => return self.start_visual(VisualKind::Line),
+                ':' => return self.command_from_visual(),
+'"' => {
+                    self.pending_keys.push(c);
+                    return;
+                },
+                // jump to the other end of the selection
+                'o' => {
+                    if let Some(anchor) = self.visual_start.replace((self.cursor_l, self.cursor_c)) {
+                        self.jump_to(anchor);
+                    }
+                    return;
+                },
+                _ => {},
+            }
+        }
+
+        self.pending_keys.push(c);
+
+        match normal::parse(&self.pending_keys) {
+            Parse::Incomplete => {},
+            Parse::Complete(Action { count, command: Command::Move(motion), .. }) => {
+                self.pending_keys.clear();
+                let pos = (self.cursor_l, self.cursor_c);
+                if let Some(target) = normal::motion_target(&self.buf().content, pos, motion, count) {
+                    self.jump_to(target);
+                }
+            },
+            _ => self.pending_keys.clear(),
+        }
+    }
+
+    fn visual_operate(&mut self, op: Operator) {
+        let Some(anchor) = self.visual_start else { return };
+        let cursor = (self.cursor_l, self.cursor_c);
+        let (start, end) = if anchor <= cursor { (anchor, cursor) } else { (cursor, anchor) };
+        let kind = self.visual_kind;
+
+        self.leave_visual();
+        // `.` only repeats changes made from command mode
+        self.last_change = None;
+
+        match kind {
+            VisualKind::Line => self.apply_operator(op, start, end, true),
+            VisualKind::Char => {
+                // past the last char the line break is selected too
+                let end = if end.1 >= self.line_cols(end.0) && end.0 + 1 < self.buf().content.line_count() {
+                    (end.0 + 1, 0)
+                } else {
+                    (end.0, (end.1 + 1).min(self.line_cols(end.0)))
+                };
+                self.apply_operator(op, start, end, false);
+            },
+            VisualKind::Block if matches!(op, Operator::Indent | Operator::Dedent) => {
+                self.apply_operator(op, start, end, true);
+            },
+            VisualKind::Block => {
+                let left = anchor.1.min(cursor.1);
+                let right = anchor.1.max(cursor.1) + 1;
+                self.block_operate(op, start.0, end.0, left, right);
+            },
+        }
+    }
+
+    // a block works on the same columns of every line, lines that end before
+    // the block starts are left alone
+    fn block_operate(&mut self, op: Operator, top: usize, bottom: usize, left: usize, right: usize) {
+        if op != Operator::Yank {
+            if op == Operator::Change {
+                self.start_insert();
+            }
+            self.record_edit();
+        }
+
+        let mut pieces = Vec::new();
+        let mut reached = Vec::new();
+        for line in top..=bottom {
+            let cols = self.line_cols(line);
+            if cols > left && line > top {
+                reached.push(line);
+            }
+            let from = self.char_pos((line, left.min(cols)));
+            let to = self.char_pos((line, right.min(cols)));
+            let text = self.buf().content.slice(from, to);
+
+            match op {
+                Operator::Delete | Operator::Change => self.delete_text(from, to),
+                Operator::ToggleCase => {
+                    self.delete_text(from, to);
+                    self.insert_text(line, from.1, &toggle_case(&text));
+                },
+                _ => {},
+            }
+            pieces.push(text);
+        }
+
+        if op == Operator::Yank {
+            self.status_message = "copied".to_string();
+        }
+        if matches!(op, Operator::Yank | Operator::Delete | Operator::Change) {
+            let register = Register::new(pieces.join("\n"), RegisterKind::Block);
+            self.store_register(register, op != Operator::Yank);
+        }
+        if op == Operator::Change {
+            self.block_insert = Some((top, left, reached));
+        }
+        self.jump_to((top, left));
+    }
+
+    // what was typed after `c` on a block goes on every line of the block
+    fn finish_block_insert(&mut self) {
+        let Some((top, left, lines)) = self.block_insert.take() else { return };
+        if self.cursor_l != top || self.cursor_c <= left {
+            return;
+        }
+
+        let typed = self.buf().content.slice(self.char_pos((top, left)), self.char_pos((top, self.cursor_c)));
+        for line in lines {
+            let (_, at) = self.char_pos((line, left));
+            self.insert_text(line, at, &typed);
+        }
+    }
+
+    // grapheme columns of `line` inside the visual selection, and whether
+    // its line break is selected too
+    fn selected_cols(&self, line: usize) -> Option<(usize, usize, bool)> {
+        if !matches!(self.mode, Mode::Visual) {
+            return None;
+        }
+
+        let anchor = self.visual_start?;
+        let cursor = (self.cursor_l, self.cursor_c);
+        let (start, end) = if anchor <= cursor { (anchor, cursor) } else { (cursor, anchor) };
+        if line < start.0 || line > end.0 {
+            return None;
+        }
+
+        let cols = self.line_cols(line);
+        match self.visual_kind {
+            VisualKind::Line => Some((0, cols, true)),
+            VisualKind::Block => {
+                let left = anchor.1.min(cursor.1);
+                let right = anchor.1.max(cursor.1) + 1;
+                Some((left.min(cols), right.min(cols), false))
+            },
+            VisualKind::Char => {
+                let from = if line == start.0 { start.1.min(cols) } else { 0 };
+                if line == end.0 && end.1 < cols {
+                    Some((from, end.1 + 1, false))
+                } else {
+                    Some((from, cols, true))
+                }
+            },
+        }
+    }
+
+    // removes whole lines, newline included
+    fn delete_lines(&mut self, first: usize, last: usize) {
+        let content = &self.buf().content;
+
+        if last + 1 < content.line_count() {
+            self.delete_text((first, 0), (last + 1, 0));
+        } else if first > 0 {
+            let start = content.line_len(first - 1);
+            let end = content.line_len(last);
+            self.delete_text((first - 1, start), (last, end));
+        } else {
+            let end = content.line_len(last);
+            self.delete_text((0, 0), (last, end));
+        }
+    }
+
+    // (line, grapheme column) to the (line, char index) the text buffer uses
+    fn char_pos(&self, (line, col): (usize, usize)) -> (usize, usize) {
+        (line, grapheme_to_char(&self.buf().content.line(line), col))
+    }
+
+    // reads the tree again, keeping the same entry selected while it's there
+    pub fn refresh_sidebar(&mut self) {
+        let selected = self.tree.entries.get(self.file_cursor).map(|entry| entry.path.clone());
+        self.tree.refresh(&self.current_dir);
+        self.git_status = RepoStatus::load(&self.current_dir);
+
+        if let Some(idx) = selected.and_then(|path| self.tree.position(&path)) {
+            self.file_cursor = idx;
+        }
+        self.file_cursor = self.file_cursor.min(self.tree.entries.len().saturating_sub(1));
+        self.adjust_sidebar_scroll();
+    }
+
+    // selects path in the sidebar, unfolding the folders above it
+    fn select_in_sidebar(&mut self, path: &Path) {
+        self.tree.reveal(&self.current_dir, path);
+        self.tree.refresh(&self.current_dir);
+        self.git_status = RepoStatus::load(&self.current_dir);
+        if let Some(idx) = self.tree.position(path) {
+            self.file_cursor = idx;
+        }
+        self.file_cursor = self.file_cursor.min(self.tree.entries.len().saturating_sub(1));
+        self.adjust_sidebar_scroll();
+    }
+
+    // a path typed in a file command, relative to the sidebar root
+    fn sidebar_path(&self, arg: &str) -> PathBuf {
+        let path = PathBuf::from(arg);
+        if path.is_relative() { self.current_dir.join(path) } else { path }
+    }
+
+    // where a new file goes: in the selected folder, or next to the
+    // selected file
+    fn sidebar_dir(&self) -> PathBuf {
+        match self.tree.entries.get(self.file_cursor) {
+            Some(entry) if entry.is_dir => entry.path.clone(),
+            Some(entry) => entry.path.parent().map_or_else(|| self.current_dir.clone(), Path::to_path_buf),
+            None => self.current_dir.clone(),
+        }
+    }
+
+    // the file actions put the command on the `:` line to be finished
+    fn file_action(&mut self, name: &str) {
+        let selected = self.tree.entries.get(self.file_cursor).map(|entry| entry.path.clone());
+        let relative = |path: &Path| relative_path(&self.current_dir, &path.to_string_lossy());
+
+        self.command = match (name, selected) {
+            ("create", _) => {
+                let dir = relative(&self.sidebar_dir());
+                if dir.is_empty() { ":create ".to_string() } else { format!(":create {}/", dir) }
+            },
+            ("delete", Some(path)) => {
+                self.delete_path(&path, false);
+                return;
+            },
+            ("rename", Some(path)) => format!(":rename {}", path.file_name().unwrap_or_default().to_string_lossy()),
+            (_, Some(path)) => format!(":{} {}", name, relative(&path)),
+            (_, None) => {
+                self.status_message = "No file selected".to_string();
+                return;
+            },
+        };
+        self.mode = Mode::Command;
+    }
+
+    // `:create path`, a folder when it ends with /
+    fn create_path(&mut self, arg: &str) {
+        let path = self.sidebar_path(arg);
+        if path.exists() {
+            self.status_message = format!("Already exists: {}", arg);
+            return;
+        }
+
+        let result = if arg.ends_with('/') {
+            fs::create_dir_all(&path)
+        } else {
+            path.parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| File::create_new(&path).map(|_| ()))
+        };
+
+        match result {
+            Ok(()) => {
+                self.select_in_sidebar(&path);
+                self.status_message = format!("Created: {}", arg);
+            },
+            Err(err) => self.status_message = format!("Could not create {}: {}", arg, err),
+        }
+    }
+
+    // `:rename name` for the selected entry, in the same folder
+    fn rename_selected(&mut self, name: &str) {
+        let Some(entry) = self.tree.entries.get(self.file_cursor) else {
+            self.status_message = "No file selected".to_string();
+            return;
+        };
+        if name.contains('/') {
+            self.status_message = format!("Not a file name: {} (use :move)", name);
+            return;
+        }
+
+        let from = entry.path.clone();
+        self.move_path(&from, &from.with_file_name(name));
+    }
+
+    // `:move dest` and `:copy dest`. into dest when it's a folder
+    fn transfer_selected(&mut self, dest: &str, copy: bool) {
+        let Some(entry) = self.tree.entries.get(self.file_cursor) else {
+            self.status_message = "No file selected".to_string();
+            return;
+        };
+
+        let from = entry.path.clone();
+        let mut to = self.sidebar_path(dest);
+        if to.is_dir() && let Some(name) = from.file_name() {
+            to = to.join(name);
+        }
+
+        if !copy {
+            self.move_path(&from, &to);
+            return;
+        }
+        if to.exists() {
+            self.status_message = format!("Already exists: {}", to.display());
+            return;
+        }
+
+        match file_tree::copy_recursive(&from, &to) {
+            Ok(()) => {
+                self.select_in_sidebar(&to);
+                self.status_message = format!("Copied to {}", relative_path(&self.current_dir, &to.to_string_lossy()));
+            },
+            Err(err) => self.status_message = format!("Could not copy {}: {}", from.display(), err),
+        }
+    }
+
+    // renames on disk and points the open buffers at the new place
+    fn move_path(&mut self, from: &Path, to: &Path) {
+        if to.exists() {
+            self.status_message = format!("Already exists: {}", to.display());
+            return;
+        }
+
+        let from_abs = from.canonicalize().unwrap_or_else(|_| from.to_path_buf());
+        let moved: Vec<(usize, PathBuf)> = self.buffers.iter().enumerate()
+            .filter(|(_, buffer)| !buffer.is_empty_file())
+            .filter_map(|(i, buffer)| {
+                let path = Path::new(&buffer.file_path).canonicalize().ok()?;
+                Some((i, path.strip_prefix(&from_abs).ok()?.to_path_buf()))
+            })
+            .collect();
+
+        if let Err(err) = fs::rename(from, to) {
+            self.status_message = format!("Could not move {}: {}", from.display(), err);
+            return;
+        }
+
+        for (i, rest) in moved {
+            let path = if rest.as_os_str().is_empty() { to.to_path_buf() } else { to.join(rest) };
+            let buffer = &mut self.buffers[i];
+            buffer.remove_swap();
+            buffer.file_path = path.to_string_lossy().into_owned();
+            buffer.highlighter = Highlighter::new(&buffer.file_path);
+            buffer.diff = Diff::load(&buffer.file_path);
+            buffer.swap_file = swap::path(&buffer.file_path);
+            buffer.swapped = false;
+        }
+
+        self.tree.rename(from, to);
+        self.select_in_sidebar(to);
+        self.status_message = format!("Moved to {}", relative_path(&self.current_dir, &to.to_string_lossy()));
+    }
+
+    // asks first unless forced with `:remove!`
+    fn delete_path(&mut self, path: &Path, force: bool) {
+        if force {
+            let result = if path.is_dir() { fs::remove_dir_all(path) } else { fs::remove_file(path) };
+            match result {
+                Ok(()) => {
+                    self.refresh_sidebar();
+                    self.status_message = format!("Deleted: {}", relative_path(&self.current_dir, &path.to_string_lossy()));
+                },
+                Err(err) => self.status_message = format!("Could not delete {}: {}", path.display(), err),
+            }
+        } else {
+            self.status_message = format!("Delete {}? (y/n)", relative_path(&self.current_dir, &path.to_string_lossy()));
+            self.prompt = Some(Prompt::Delete(path.to_path_buf()));
+        }
+    }
+
+    pub fn prompt_key(&mut self, c: char) {
+        match self.prompt.take() {
+            Some(Prompt::Delete(path)) if c == 'y' => self.delete_path(&path, true),
+            Some(Prompt::Delete(_)) => self.status_message = "Cancelled".to_string(),
+            Some(Prompt::Recover(path)) if c == 'r' => self.recover(&path),
+            Some(Prompt::Recover(path)) if c == 'd' => {
+                let _ = fs::remove_file(path);
+                self.status_message = "Swap file deleted".to_string();
+            },
+            Some(Prompt::Recover(_)) => self.status_message = "Swap file kept".to_string(),
+            Some(Prompt::Conflict(idx)) if c == 'r' => {
+                self.read_again(idx);
+                self.status_message = format!("Reloaded: {}", self.buffer_name(&self.buffers[idx]));
+            },
+            // the new file counts as read, so it's not asked again
+            Some(Prompt::Conflict(idx)) => {
+                let buffer = &mut self.buffers[idx];
+                buffer.disk = DiskState::read(Path::new(&buffer.file_path));
+                self.status_message = "Kept your changes, :w overwrites the file".to_string();
+            },
+            None => {},
+        }
+    }
+
+    // looks for a swap file of the current buffer, left by an editor that
+    // crashed or written by one that has the file open right now
+    fn check_swap(&mut self) {
+        let Some(swap_path) = self.buf().swap_file.clone() else { return };
+        let Some(swap) = Swap::read(&swap_path) else { return };
+        let name = self.buffer_name(self.buf());
+
+        if swap.in_use(self.pid_alive) {
+            self.buf_mut().swap_file = None;
+            self.status_message = format!("{} is open in another editor (pid {}), no swap file here", name, swap.pid);
+        } else if swap.text == self.buf().content.text() {
+            let _ = fs::remove_file(&swap_path);
+        } else {
+            self.status_message = format!("Unsaved changes to {} from {}: (r)ecover, (d)elete or (i)gnore", name, swap.age());
+            self.prompt = Some(Prompt::Recover(swap_path));
+        }
+    }
+
+    // puts the text of a swap file in the buffer as one undo step
+    fn recover(&mut self, swap_path: &Path) {
+        let Some(swap) = Swap::read(swap_path) else {
+            self.status_message = format!("Could not read {}", swap_path.display());
+            return;
+        };
+
+        self.buf_mut().history.close_group();
+        self.record_edit();
+        self.restore(Snapshot {
+            content: TextBuffer::from_text(&swap.text),
+            cursor_l: swap.cursor.0,
+            cursor_c: swap.cursor.1,
+        });
+        self.buf_mut().history.close_group();
+        // the text is swapped again from here on, under this editor
+        let _ = fs::remove_file(swap_path);
+        self.status_message = "Recovered, :w to keep the changes".to_string();
+    }
+
+    // writes the swap files of the buffers edited since, at most once every
+    // SWAP_DELAY. true while some are still waiting for it
+    pub fn write_swaps(&mut self) -> bool {
+        if self.buffers.iter().all(|buffer| buffer.swapped) {
+            return false;
+        }
+        if self.last_swap.elapsed() < SWAP_DELAY {
+            return true;
+        }
+        self.last_swap = Instant::now();
+        self.save_view();
+
+        for buffer in self.buffers.iter_mut().filter(|buffer| !buffer.swapped) {
+            buffer.swapped = true;
+            let Some(swap_file) = &buffer.swap_file else { continue };
+
+            // saved or undone back to what's on disk
+            if !buffer.modified {
+                swap::remove(swap_file);
+                continue;
+            }
+
+            if let Err(err) = swap::write(swap_file, (buffer.cursor_l, buffer.cursor_c), &buffer.content.text()) {
+                self.status_message = format!("Could not write swap file: {}", err);
+                buffer.swap_file = None;
+            }
+        }
+        false
+    }
+
+    // the gutter of a shown buffer needs a redraw now that typing paused, or
+    // will once it does
+    pub fn diffs_due(&self) -> bool {
+        self.windows.iter().any(|window| self.buffers[window.buffer].diff.is_due())
+    }
+
+    pub fn diffs_stale(&self) -> bool {
+        self.windows.iter().any(|window| self.buffers[window.buffer].diff.is_stale())
+    }
+
+    // a clean quit leaves no swap files behind
+    pub fn remove_swaps(&self) {
+        for buffer in &self.buffers {
+            buffer.remove_swap();
+        }
+    }
+
+    // `:cd path`, or the selected folder without one
+    fn change_root(&mut self, arg: &str) {
+        let path = match arg {
+            "" => match self.tree.entries.get(self.file_cursor) {
+                Some(entry) if entry.is_dir => entry.path.clone(),
+                _ => {
+                    self.status_message = "Not a folder".to_string();
+                    return;
+                },
+            },
+            arg => self.sidebar_path(arg),
+        };
+
+        match path.canonicalize() {
+            Ok(path) if path.is_dir() => {
+                self.current_dir = path;
+                self.file_cursor = 0;
+                self.refresh_sidebar();
+                self.status_message = format!("Diretório: {}", self.current_dir.display());
+            },
+            _ => self.status_message = format!("Not a folder: {}", arg),
+        }
+    }
+
+    pub fn toggle_hidden(&mut self) {
+        self.config.show_hidden = !self.config.show_hidden;
+        self.tree.show_hidden = self.config.show_hidden;
+        self.refresh_sidebar();
+        self.status_message = format!("show_hidden={}", self.config.show_hidden);
+    }
+
+
+    pub fn save(&mut self, force: bool) -> io::Result<()> {
+        if self.buf().is_empty_file() {
+            self.status_message = "Usage :w <file_path>".to_string();
+            return Ok(());
+        }
+
+        let buffer = self.buf_mut();
+        buffer::write_file(Path::new(&buffer.file_path), &buffer.content, buffer.format, force)?;
+        buffer.modified = false;
+        buffer.disk = DiskState::read(Path::new(&buffer.file_path));
+        buffer.remove_swap();
+        // HEAD may have moved since the file was opened
+        buffer.diff = Diff::load(&buffer.file_path);
+        self.status_message = "File Saved".to_string();
+        Ok(())
+    }
+
+    pub fn save_as(&mut self, new_path: &str, force: bool) -> io::Result<()> {
+        let mut path = PathBuf::from(new_path);
+        if path.is_relative() {
+            path = self.current_dir.join(path);
+        }
+
+        let buffer = self.buf_mut();
+        buffer::write_file(&path, &buffer.content, buffer.format, force)?;
+        buffer.remove_swap();
+
+        buffer.file_path = path.to_str().unwrap().to_string();
+        buffer.swap_file = swap::path(&buffer.file_path);
+        buffer.disk = DiskState::read(&path);
+        buffer.highlighter = Highlighter::new(&buffer.file_path);
+        buffer.diff = Diff::load(&buffer.file_path);
+        buffer.modified = false;
+        self.status_message = format!("Arquivo salvo como: {}", self.buf().file_path);
+        Ok(())
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn severity_color(severity: Severity) -> style::Color {
+    match severity {
+        Severity::Error => style::Color::Red,
+        Severity::Warning => style::Color::Yellow,
+        Severity::Info => style::Color::Blue,
+        Severity::Hint => style::Color::Cyan,
+    }
+}
+
+fn toggle_case(text: &str) -> String {
+    text.chars()
+        .flat_map(|c| {
+            if c.is_lowercase() {
+                c.to_uppercase().collect::<Vec<_>>()
+            } else {
+                c.to_lowercase().collect::<Vec<_>>()
+            }
+        })
+        .collect()
+}
+
+fn truncate_string(s: &str, max_width: usize) -> String {
+    if display_width(s) <= max_width {
         s.to_string()
     } else if max_width > 1 {
-        let truncated: String = s.chars().take(max_width - 1).collect();
+        let mut truncated = String::new();
+        for c in s.chars() {
+            if display_width(&truncated) + display_width(c.encode_utf8(&mut [0; 4])) > max_width - 1 {
+                break;
+            }
+            truncated.push(c);
+        }
         format!("{}…", truncated)
     } else {
         "…".to_string()
@@ -566,44 +3536,26 @@ fn truncate_string(s: &str, max_width: usize) -> String {
 }
 
 fn file_icon(file_name: &str) -> &str {
-    if file_name.ends_with(".rs") {
-        "🦀"
-    } else if file_name.ends_with(".go") {
-        "🐹"
-    } else if file_name.ends_with(".c") {
-        "C"
-    } else if file_name.ends_with(".cpp") {
-        "C++"
-    } else if file_name.ends_with(".h") {
-        "H"
-    } else if file_name.ends_with(".py") {
-        "🐍"
-    } else if file_name.ends_with(".r") {
-        "𝐑"
-    } else if file_name.ends_with(".js") {
-        "JS"
-    } else if file_name.ends_with(".ts") {
-        "TS"
-    } else if file_name.ends_with(".html") {
-        "🌐"
-    } else if file_name.ends_with(".css") {
-        "🎨"
-    } else if file_name.ends_with(".md") {
-        ""
-    } else if file_name.ends_with(".json") {
-        "{}"
-    } else if file_name.ends_with(".toml") || file_name.ends_with(".yaml") || file_name.ends_with(".conf") || file_name.ends_with(".config") || file_name.starts_with(".") {
-        "⚙️"
-    } else if file_name.ends_with(".sh") {
-        ">_"
-    } else if file_name.ends_with(".txt") {
-        ""
-    } else if file_name.ends_with(".sql") {
-        ""
-    } else if file_name.ends_with(".java") {
-        "☕"
-    } else {
-        "📄"
+    match filetype::detect(file_name) {
+        "rust" => "🦀",
+        "go" => "🐹",
+        "c" => "C",
+        "cpp" => "C++",
+        "header" => "H",
+        "python" => "🐍",
+        "r" => "𝐑",
+        "javascript" => "JS",
+        "typescript" => "TS",
+        "html" => "🌐",
+        "css" => "🎨",
+        "markdown" => "",
+        "json" => "{}",
+        "toml" | "yaml" | "conf" => "⚙️",
+        "sh" => ">_",
+        "text" => "",
+        "sql" => "",
+        "java" => "☕",
+        _ => "📄",
     }
 }
 
@@ -634,3 +3586,216 @@ fn relative_path(base: &PathBuf, target: &str) -> String {
         target_path.display().to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard::StubProvider;
+    use crate::test_util::TempDir;
+
+    // an unnamed buffer holding `text`, without the user's config, key
+    // bindings or clipboard
+    pub fn editor(text: &str) -> Editor {
+        let registers = Registers::new(Box::<StubProvider>::default());
+        let mut editor = Editor::with_config(Config::default(), Keymap::new(), registers, Buffer::new("."), PathBuf::from("."));
+        editor.buf_mut().content = TextBuffer::from_text(text);
+        editor
+    }
+
+    // keys as main would send them: \x1b is Esc, \n Enter, \x08 Backspace,
+    // \x7f Delete and \t Tab
+    pub fn typing(editor: &mut Editor, keys: &str) {
+        for c in keys.chars() {
+            let key = match c {
+                '\x1b' => Key::new(KeyCode::Esc, KeyModifiers::NONE),
+                '\n' => Key::new(KeyCode::Enter, KeyModifiers::NONE),
+                '\x08' => Key::new(KeyCode::Backspace, KeyModifiers::NONE),
+                '\x7f' => Key::new(KeyCode::Delete, KeyModifiers::NONE),
+                '\t' => Key::new(KeyCode::Tab, KeyModifiers::NONE),
+                c => Key::char(c),
+            };
+            let inputs = if editor.in_command_line() { vec![Input::Key(key)] } else { editor.resolve_key(key) };
+            run(editor, inputs);
+        }
+        let inputs = editor.flush_input();
+        run(editor, inputs);
+    }
+
+    fn run(editor: &mut Editor, inputs: Vec<Input>) {
+        for input in inputs {
+            match input {
+                Input::Action(name) => editor.run_action(name),
+                Input::Key(key) if editor.in_command_line() => {
+                    editor.command_line_key(key);
+                },
+                Input::Key(key) => editor.default_key(key),
+            }
+        }
+    }
+
+    pub fn text(editor: &Editor) -> String {
+        editor.buf().content.text()
+    }
+
+    // `keys` from `pos` turn `before` into `after`, u brings `before` back
+    // and Ctrl-r `after` again
+    fn round_trip(before: &str, pos: (usize, usize), keys: &str, after: &str) {
+        let mut editor = editor(before);
+        editor.jump_to(pos);
+        typing(&mut editor, keys);
+        assert_eq!(text(&editor), after, "{:?}", keys);
+
+        typing(&mut editor, "u");
+        assert_eq!(text(&editor), before, "undo {:?}", keys);
+
+        editor.redo();
+        assert_eq!(text(&editor), after, "redo {:?}", keys);
+    }
+
+    #[test]
+    fn insert_mode_edits_undo_and_redo() {
+        round_trip("ab", (0, 1), "ixy\x1b", "axyb");
+        round_trip("ab", (0, 1), "i\n\x1b", "a\nb");
+        round_trip("ab\ncd", (1, 0), "i\x08\x1b", "abcd");
+        round_trip("ab", (0, 2), "i\x08\x1b", "a");
+        round_trip("ab\ncd", (0, 2), "i\x7f\x1b", "abcd");
+        round_trip("ab", (0, 0), "i\x7f\x1b", "b");
+        round_trip("ab", (0, 0), "i\t\x1b", "    ab");
+        round_trip("", (0, 0), "i(\x1b", "()");
+    }
+
+    #[test]
+    fn command_mode_edits_undo_and_redo() {
+        round_trip("one\ntwo", (0, 0), "dd", "two");
+        round_trip("one two", (0, 0), "dw", "two");
+        round_trip("one two", (0, 0), "x", "ne two");
+        round_trip("one two", (0, 0), "cwsix\x1b", "six two");
+        round_trip("one\ntwo", (0, 0), "yyp", "one\none\ntwo");
+        round_trip("one", (0, 0), ">>", "    one");
+        round_trip("one", (0, 0), "~", "One");
+        round_trip("one", (0, 0), "oxy\x1b", "one\nxy");
+    }
+
+    #[test]
+    fn edits_next_to_multi_byte_graphemes() {
+        let edit = |col, keys| {
+            let mut editor = editor("c\u{327}ã👨\u{200d}👩\u{200d}👧日");
+            editor.jump_to((0, col));
+            typing(&mut editor, keys);
+            text(&editor)
+        };
+
+        assert_eq!(edit(2, "ix\x1b"), "c\u{327}ãx👨\u{200d}👩\u{200d}👧日");
+        assert_eq!(edit(3, "i\x08\x08\x1b"), "c\u{327}日");
+        assert_eq!(edit(2, "i\x7f\x7f\x1b"), "c\u{327}ã");
+        assert_eq!(edit(3, "a\nz\x1b"), "c\u{327}ã👨\u{200d}👩\u{200d}👧日\nz");
+    }
+
+    #[test]
+    fn command_mode_moves_over_whole_graphemes() {
+        let mut editor = editor("c\u{327}ã日本");
+        typing(&mut editor, "lx");
+        assert_eq!(text(&editor), "c\u{327}日本");
+        typing(&mut editor, "$x");
+        assert_eq!(text(&editor), "c\u{327}日");
+    }
+
+    #[test]
+    fn one_insert_session_is_one_undo_step() {
+        let mut editor = editor("");
+        typing(&mut editor, "iab\ncd\x08e\x1b");
+        assert_eq!(text(&editor), "ab\nce");
+
+        typing(&mut editor, "u");
+        assert_eq!(text(&editor), "");
+        typing(&mut editor, "u");
+        assert_eq!(editor.status_message, "Already at oldest change");
+    }
+
+    // a swap for a file in a temp dir, left by the editor with pid 4000000
+    fn crashed_swap(dir: &TempDir, text: &str) -> (Editor, PathBuf) {
+        let path = dir.join("notes.txt");
+        fs::write(&path, "on disk\n").unwrap();
+        let swap_path = dir.join("notes.txt.swp");
+        swap::write(&swap_path, (1, 3), text).unwrap();
+        let data = fs::read_to_string(&swap_path).unwrap();
+        fs::write(&swap_path, data.replace(&format!("pid {}", std::process::id()), "pid 4000000")).unwrap();
+
+        let mut editor = editor("");
+        editor.buffers[0] = Buffer::new(path.to_str().unwrap());
+        editor.buffers[0].swap_file = Some(swap_path.clone());
+        (editor, swap_path)
+    }
+
+    #[test]
+    fn a_crashed_editors_swap_is_recovered_as_one_undo_step() {
+        let dir = TempDir::new("swap-recover");
+        let (mut editor, swap_path) = crashed_swap(&dir, "on disk\nunsaved");
+        editor.pid_alive = |_| false;
+        editor.check_swap();
+        assert!(matches!(&editor.prompt, Some(Prompt::Recover(path)) if *path == swap_path));
+
+        editor.prompt_key('r');
+        assert_eq!(text(&editor), "on disk\nunsaved");
+        assert_eq!((editor.cursor_l, editor.cursor_c), (1, 3));
+        assert!(editor.buf().modified);
+        assert!(!swap_path.exists());
+
+        typing(&mut editor, "u");
+        assert_eq!(text(&editor), "on disk");
+        editor.redo();
+        assert_eq!(text(&editor), "on disk\nunsaved");
+    }
+
+    #[test]
+    fn a_running_editors_swap_is_left_alone() {
+        let dir = TempDir::new("swap-running");
+        let (mut editor, swap_path) = crashed_swap(&dir, "on disk\nunsaved");
+        editor.pid_alive = |_| true;
+        editor.check_swap();
+        assert!(editor.prompt.is_none());
+        assert!(editor.buf().swap_file.is_none());
+        assert!(swap_path.exists());
+        assert_eq!(text(&editor), "on disk");
+
+        // and one with the text already on disk just goes away
+        let (mut editor, swap_path) = crashed_swap(&dir, "on disk");
+        editor.pid_alive = |_| false;
+        editor.check_swap();
+        assert!(editor.prompt.is_none());
+        assert!(!swap_path.exists());
+    }
+
+
+    // a `:` command, true when it quits
+    fn ex(editor: &mut Editor, line: &str) -> bool {
+        editor.execute_command(command::parse(line).unwrap())
+    }
+
+    #[test]
+    fn write_quit_works_like_quit_after_writing() {
+        let dir = TempDir::new("wq");
+        let path = dir.join("a.txt");
+        let mut editor = editor("");
+        editor.buffers[0] = Buffer::new(path.to_str().unwrap());
+        editor.buffers[0].content = TextBuffer::from_text("saved");
+        editor.buffers[0].modified = true;
+        let mut other = Buffer::new(".");
+        other.modified = true;
+        editor.buffers.push(other);
+
+        // another window is just closed
+        ex(&mut editor, "split");
+        assert_eq!(editor.windows.len(), 2);
+        assert!(!ex(&mut editor, "wq"));
+        assert_eq!(editor.windows.len(), 1);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "saved\n");
+
+        // the last one waits for the other buffer, unless forced
+        assert!(!ex(&mut editor, "wq"));
+        assert_eq!(editor.status_message, "No write since last change for buffer 2 (add ! to override)");
+        assert!(!ex(&mut editor, "q"));
+        assert!(ex(&mut editor, "wq!"));
+        assert!(ex(&mut editor, "qa!"));
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// one row of the sidebar
+pub struct Entry {
+    pub path: PathBuf,
+    pub name: String,
+    pub depth: usize,
+    pub is_dir: bool,
+    pub expanded: bool,
+}
+
+// the sidebar tree. only the open folders are read, and `entries` is the
+// flattened list of rows as they are drawn
+#[derive(Default)]
+pub struct FileTree {
+    pub entries: Vec<Entry>,
+    expanded: HashSet<PathBuf>,
+    pub show_hidden: bool,
+}
+
+impl FileTree {
+    pub fn new(root: &Path, show_hidden: bool) -> Self {
+        let mut tree = FileTree { entries: Vec::new(), expanded: HashSet::new(), show_hidden };
+        tree.refresh(root);
+        tree
+    }
+
+    // reads the folders again, keeping which ones are open
+    pub fn refresh(&mut self, root: &Path) {
+        self.expanded.retain(|path| path.is_dir());
+        self.entries.clear();
+        self.read_level(root, 0);
+    }
+
+    fn read_level(&mut self, dir: &Path, depth: usize) {
+        for (name, is_dir) in read_dir_sorted(dir, self.show_hidden) {
+            let path = dir.join(&name);
+            let expanded = is_dir && self.expanded.contains(&path);
+
+            self.entries.push(Entry { path: path.clone(), name, depth, is_dir, expanded });
+            if expanded {
+                self.read_level(&path, depth + 1);
+            }
+        }
+    }
+
+    pub fn set_expanded(&mut self, path: &Path, expanded: bool) {
+        if expanded {
+            self.expanded.insert(path.to_path_buf());
+        } else {
+            self.expanded.remove(path);
+        }
+    }
+
+    // opens every folder between root and path so it gets a row
+    pub fn reveal(&mut self, root: &Path, path: &Path) {
+        let mut dir = path.parent();
+        while let Some(parent) = dir && parent.starts_with(root) && parent != root {
+            self.expanded.insert(parent.to_path_buf());
+            dir = parent.parent();
+        }
+    }
+
+    pub fn position(&self, path: &Path) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.path == path)
+    }
+
+    // the row of the folder holding the entry at idx
+    pub fn parent(&self, idx: usize) -> Option<usize> {
+        let depth = self.entries[idx].depth;
+        (0..idx).rev().find(|i| self.entries[*i].depth + 1 == depth)
+    }
+
+    // a folder moved or renamed keeps its open subfolders open
+    pub fn rename(&mut self, from: &Path, to: &Path) {
+        let moved: Vec<PathBuf> = self.expanded.iter().filter(|path| path.starts_with(from)).cloned().collect();
+        for path in moved {
+            self.expanded.remove(&path);
+            if let Ok(rest) = path.strip_prefix(from) {
+                self.expanded.insert(to.join(rest));
+            }
+        }
+    }
+}
+
+// folders first, then files, each sorted by name
+pub fn read_dir_sorted(path: &Path, show_hidden: bool) -> Vec<(String, bool)> {
+    let Ok(entries) = fs::read_dir(path) else { return Vec::new() };
+
+    let mut entries: Vec<(String, bool)> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| (entry.file_name().to_string_lossy().into_owned(), entry.path().is_dir()))
+        .filter(|(name, _)| show_hidden || !name.starts_with('.'))
+        .collect();
+
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    entries
+}
+
+pub fn copy_recursive(from: &Path, to: &Path) -> io::Result<()> {
+    if from.is_dir() {
+        if to.starts_with(from) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "can't copy a folder into itself"));
+        }
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        fs::copy(from, to).map(|_| ())
+    }
+}
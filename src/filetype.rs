@@ -0,0 +1,54 @@
+use std::path::Path;
+
+// file type of a path, looked up by extension. shared by the icons in the
+// file browser and the syntax highlighter
+pub fn detect(path: &str) -> &'static str {
+    let name = Path::new(path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(path);
+
+    if name.ends_with(".rs") {
+        "rust"
+    } else if name.ends_with(".go") {
+        "go"
+    } else if name.ends_with(".c") {
+        "c"
+    } else if name.ends_with(".cpp") {
+        "cpp"
+    } else if name.ends_with(".h") {
+        "header"
+    } else if name.ends_with(".py") {
+        "python"
+    } else if name.ends_with(".r") {
+        "r"
+    } else if name.ends_with(".js") {
+        "javascript"
+    } else if name.ends_with(".ts") {
+        "typescript"
+    } else if name.ends_with(".html") {
+        "html"
+    } else if name.ends_with(".css") {
+        "css"
+    } else if name.ends_with(".md") {
+        "markdown"
+    } else if name.ends_with(".json") {
+        "json"
+    } else if name.ends_with(".toml") {
+        "toml"
+    } else if name.ends_with(".yaml") {
+        "yaml"
+    } else if name.ends_with(".conf") || name.ends_with(".config") || name.starts_with(".") {
+        "conf"
+    } else if name.ends_with(".sh") {
+        "sh"
+    } else if name.ends_with(".txt") {
+        "text"
+    } else if name.ends_with(".sql") {
+        "sql"
+    } else if name.ends_with(".java") {
+        "java"
+    } else {
+        ""
+    }
+}
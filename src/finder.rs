@@ -0,0 +1,173 @@
+use std::path::Path;
+use ignore::WalkBuilder;
+
+// big trees are cut here so opening the finder stays quick
+const MAX_FILES: usize = 100_000;
+
+// the Ctrl-p / `:find` overlay: every file under the project, narrowed down
+// by a fuzzy query
+pub struct Finder {
+    pub query: String,
+    files: Vec<String>,
+    // (index in files, matched char indices), best match first
+    pub matches: Vec<(usize, Vec<usize>)>,
+    pub selected: usize,
+    pub scroll: usize,
+}
+
+impl Finder {
+    pub fn new(root: &Path, query: &str) -> Self {
+        let mut finder = Finder {
+            query: query.to_string(),
+            files: walk(root),
+            matches: Vec::new(),
+            selected: 0,
+            scroll: 0,
+        };
+        finder.refilter();
+        finder
+    }
+
+    pub fn file(&self, idx: usize) -> &str {
+        &self.files[idx]
+    }
+
+    pub fn total(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.query.push(c);
+        self.refilter();
+    }
+
+    pub fn pop(&mut self) {
+        self.query.pop();
+        self.refilter();
+    }
+
+    fn refilter(&mut self) {
+        let mut scored: Vec<(i64, usize, Vec<usize>)> = self.files.iter().enumerate()
+            .filter_map(|(idx, file)| fuzzy_match(&self.query, file).map(|(score, positions)| (score, idx, positions)))
+            .collect();
+
+        // shorter paths first when the score is the same
+        scored.sort_by(|a, b| b.0.cmp(&a.0)
+            .then_with(|| self.files[a.1].len().cmp(&self.files[b.1].len()))
+            .then_with(|| self.files[a.1].cmp(&self.files[b.1])));
+
+        self.matches = scored.into_iter().map(|(_, idx, positions)| (idx, positions)).collect();
+        self.selected = 0;
+        self.scroll = 0;
+    }
+
+    pub fn move_selection(&mut self, down: bool) {
+        if self.matches.is_empty() {
+            return;
+        }
+        self.selected = if down {
+            (self.selected + 1) % self.matches.len()
+        } else {
+            (self.selected + self.matches.len() - 1) % self.matches.len()
+        };
+    }
+
+    pub fn keep_visible(&mut self, height: usize) {
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if height > 0 && self.selected >= self.scroll + height {
+            self.scroll = self.selected + 1 - height;
+        }
+    }
+
+    pub fn selected_path(&self) -> Option<&str> {
+        self.matches.get(self.selected).map(|(idx, _)| self.files[*idx].as_str())
+    }
+}
+
+// files under root relative to it, skipping hidden ones and whatever
+// .gitignore / .ignore leave out
+pub fn walk(root: &Path) -> Vec<String> {
+    let mut files: Vec<String> = WalkBuilder::new(root)
+        .require_git(false)
+        .build()
+        .flatten()
+        .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+        .filter_map(|entry| {
+            let path = entry.path().strip_prefix(root).ok()?;
+            Some(path.to_string_lossy().replace('\\', "/"))
+        })
+        .take(MAX_FILES)
+        .collect();
+
+    files.sort();
+    files
+}
+
+// Some((score, matched char indices)) when every char of the query shows up
+// in the candidate in order. an uppercase letter makes the query match case
+pub fn fuzzy_match(query: &str, candidate: &str) -> Option<(i64, Vec<usize>)> {
+    let exact = query.chars().any(char::is_uppercase);
+    let fold = |c: char| if exact { c } else { c.to_lowercase().next().unwrap_or(c) };
+
+    let query: Vec<char> = query.chars().filter(|c| !c.is_whitespace()).map(fold).collect();
+    let chars: Vec<char> = candidate.chars().collect();
+    if query.is_empty() {
+        return Some((0, Vec::new()));
+    }
+
+    // the first place the whole query fits, then back from its end to the
+    // tightest run of matches that still ends there
+    let mut next = 0;
+    let mut end = None;
+    for (i, c) in chars.iter().enumerate() {
+        if fold(*c) == query[next] {
+            next += 1;
+            if next == query.len() {
+                end = Some(i);
+                break;
+            }
+        }
+    }
+    let end = end?;
+
+    let mut positions = vec![0; query.len()];
+    let mut left = query.len();
+    for i in (0..=end).rev() {
+        if fold(chars[i]) == query[left - 1] {
+            left -= 1;
+            positions[left] = i;
+            if left == 0 {
+                break;
+            }
+        }
+    }
+
+    let name_start = chars.iter().rposition(|c| *c == '/').map_or(0, |i| i + 1);
+    let mut score = 0i64;
+
+    for (n, &i) in positions.iter().enumerate() {
+        score += 16;
+
+        if n > 0 && positions[n - 1] + 1 == i {
+            score += 12;
+        } else if n > 0 {
+            score -= (i - positions[n - 1] - 1).min(8) as i64;
+        }
+
+        // the start of a word: after a separator or a camelCase hump
+        let prev = i.checked_sub(1).map(|p| chars[p]);
+        if matches!(prev, None | Some('/' | '_' | '-' | '.' | ' '))
+            || (prev.is_some_and(char::is_lowercase) && chars[i].is_uppercase())
+        {
+            score += 10;
+        }
+
+        // matches in the file name count for more than in its folders
+        if i >= name_start {
+            score += 4;
+        }
+    }
+
+    Some((score, positions))
+}
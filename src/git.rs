@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use ropey::RopeSlice;
+use crate::text_buffer::TextBuffer;
+
+// how long typing has to pause before the hunks are worked out again
+const REFRESH_DELAY: Duration = Duration::from_millis(300);
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum FileStatus {
+    Ignored,
+    Untracked,
+    Added,
+    Modified,
+}
+
+// `git status` of the repo around a folder. folders get the strongest status
+// of what's inside them
+pub struct RepoStatus {
+    files: HashMap<PathBuf, FileStatus>,
+    dirs: HashMap<PathBuf, FileStatus>,
+}
+
+impl RepoStatus {
+    // None outside of a repo or without git installed
+    pub fn load(dir: &Path) -> Option<Self> {
+        let top = git(dir, &["rev-parse", "--show-toplevel"])?;
+        let top = PathBuf::from(String::from_utf8_lossy(&top).trim_end());
+        let output = git(dir, &["status", "--porcelain=v1", "-z", "--ignored", "--untracked-files=normal"])?;
+
+        let mut status = RepoStatus { files: HashMap::new(), dirs: HashMap::new() };
+        let mut entries = output.split(|b| *b == 0);
+
+        while let Some(entry) = entries.next() {
+            if entry.len() < 4 {
+                continue;
+            }
+            let (x, y) = (entry[0], entry[1]);
+            let path = top.join(String::from_utf8_lossy(&entry[3..]).trim_end_matches('/'));
+
+            // renames are followed by the old name
+            if x == b'R' || x == b'C' {
+                entries.next();
+            }
+
+            let file_status = match (x, y) {
+                (b'?', b'?') => FileStatus::Untracked,
+                (b'!', b'!') => FileStatus::Ignored,
+                (b'A', _) => FileStatus::Added,
+                _ => FileStatus::Modified,
+            };
+
+            if file_status != FileStatus::Ignored {
+                for dir in path.ancestors().skip(1).take_while(|dir| dir.starts_with(&top) && *dir != top) {
+                    let current = status.dirs.entry(dir.to_path_buf()).or_insert(file_status);
+                    *current = (*current).max(file_status);
+                }
+            }
+            status.files.insert(path, file_status);
+        }
+
+        Some(status)
+    }
+
+    pub fn get(&self, path: &Path) -> Option<FileStatus> {
+        if let Some(status) = self.files.get(path).or_else(|| self.dirs.get(path)) {
+            return Some(*status);
+        }
+
+        // everything in an untracked or ignored folder is listed as the folder
+        path.ancestors().skip(1)
+            .find_map(|dir| self.files.get(dir))
+            .filter(|status| matches!(status, FileStatus::Untracked | FileStatus::Ignored))
+            .copied()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum HunkKind {
+    Added,
+    Changed,
+    Deleted,
+}
+
+// lines of the buffer that differ from HEAD. a deletion has no lines and
+// sits where the removed lines used to be
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Hunk {
+    pub start: usize,
+    pub len: usize,
+    pub kind: HunkKind,
+}
+
+// the HEAD version of a buffer's file and the hunks against it. after an
+// edit the old hunks stay up until typing pauses, so big files aren't
+// diffed on every key
+pub struct Diff {
+    base: Option<Vec<String>>,
+    pub hunks: Vec<Hunk>,
+    stale: bool,
+    // None right after loading, when there's nothing to wait for
+    edited: Option<Instant>,
+}
+
+impl Diff {
+    pub fn load(file_path: &str) -> Self {
+        let base = head_lines(Path::new(file_path));
+        Diff { stale: base.is_some(), base, hunks: Vec::new(), edited: None }
+    }
+
+    pub fn invalidate(&mut self) {
+        if self.base.is_some() {
+            self.stale = true;
+            self.edited = Some(Instant::now());
+        }
+    }
+
+    // the hunks are out of date and typing has paused long enough
+    pub fn is_due(&self) -> bool {
+        self.stale && self.edited.is_none_or(|edited| edited.elapsed() >= REFRESH_DELAY)
+    }
+
+    // out of date, whether or not it's due yet
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    pub fn refresh_when_due(&mut self, content: &TextBuffer) {
+        if self.is_due() {
+            self.refresh(content);
+        }
+    }
+
+    pub fn refresh(&mut self, content: &TextBuffer) {
+        if !self.stale {
+            return;
+        }
+        self.stale = false;
+
+        self.hunks = match &self.base {
+            Some(base) => {
+                let lines: Vec<RopeSlice> = (0..content.line_count()).map(|l| content.line_slice(l)).collect();
+                diff_lines(base, &lines)
+            },
+            None => Vec::new(),
+        };
+    }
+
+    // the gutter mark of a line: its hunk, and for deletions whether the
+    // lines were removed above the first line instead of below this one
+    pub fn marker(&self, line: usize) -> Option<(HunkKind, bool)> {
+        self.hunks.iter().find_map(|hunk| match hunk.kind {
+            HunkKind::Deleted if hunk.start == 0 => (line == 0).then_some((hunk.kind, true)),
+            HunkKind::Deleted => (line + 1 == hunk.start).then_some((hunk.kind, false)),
+            _ => (hunk.start..hunk.start + hunk.len).contains(&line).then_some((hunk.kind, false)),
+        })
+    }
+}
+
+fn git(dir: &Path, args: &[&str]) -> Option<Vec<u8>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    output.status.success().then_some(output.stdout)
+}
+
+fn head_lines(path: &Path) -> Option<Vec<String>> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let name = path.file_name()?.to_string_lossy();
+    let bytes = git(dir, &["show", &format!("HEAD:./{}", name)])?;
+
+    let mut text = String::from_utf8_lossy(&bytes).replace("\r\n", "\n");
+    if text.ends_with('\n') {
+        text.pop();
+    }
+    Some(text.split('\n').map(String::from).collect())
+}
+
+// hunks turning `old` into `new`, found with Myers' diff on the lines left
+// after the common start and end are cut off
+pub fn diff_lines<A: PartialEq<B>, B>(old: &[A], new: &[B]) -> Vec<Hunk> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let old = &old[prefix..old.len() - suffix];
+    let new = &new[prefix..new.len() - suffix];
+
+    let mut hunks = Vec::new();
+    let (mut x, mut y) = (0, 0);
+
+    // lines between two common ones make up one hunk
+    let mut push = |x0: usize, y0: usize, x1: usize, y1: usize| {
+        let (deleted, added) = (x1 - x0, y1 - y0);
+        let kind = match (deleted, added) {
+            (0, 0) => return,
+            (0, _) => HunkKind::Added,
+            (_, 0) => HunkKind::Deleted,
+            _ => HunkKind::Changed,
+        };
+        hunks.push(Hunk { start: prefix + y0, len: added, kind });
+    };
+
+    // past MAX_EDITS the whole middle counts as one change
+    let common = common_lines(old, new).unwrap_or_default();
+    for (mx, my) in common {
+        push(x, y, mx, my);
+        x = mx + 1;
+        y = my + 1;
+    }
+    push(x, y, old.len(), new.len());
+
+    hunks
+}
+
+const MAX_EDITS: isize = 2000;
+
+// (old index, new index) of the lines both sides keep, in order. None when
+// the files are too far apart to be worth it
+fn common_lines<A: PartialEq<B>, B>(old: &[A], new: &[B]) -> Option<Vec<(usize, usize)>> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let offset = n + m + 1;
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    // v[-d..=d] as it was before each round
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'search: for d in 0..=(n + m) {
+        if d > MAX_EDITS {
+            return None;
+        }
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+
+        for k in (-d..=d).step_by(2) {
+            let at = |k: isize| v[(k + offset) as usize];
+            let mut x = if k == -d || (k != d && at(k - 1) < at(k + 1)) { at(k + 1) } else { at(k - 1) + 1 };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[(k + offset) as usize] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    // walk back through the rounds, keeping the diagonal steps
+    let mut common = Vec::new();
+    let (mut x, mut y) = (n, m);
+
+    for (d, saved) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let (prev_x, prev_y) = if d == 0 {
+            (0, 0)
+        } else {
+            let at = |k: isize| saved[(k + d) as usize];
+            let k = x - y;
+            let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) { k + 1 } else { k - 1 };
+            (at(prev_k), at(prev_k) - prev_k)
+        };
+
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            common.push((x as usize, y as usize));
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+
+    common.reverse();
+    Some(common)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::test_util::TempDir;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split('\n').map(String::from).collect()
+    }
+
+    fn hunk(start: usize, len: usize, kind: HunkKind) -> Hunk {
+        Hunk { start, len, kind }
+    }
+
+    #[test]
+    fn hunks_for_simple_edits() {
+        let old = lines("a\nb\nc\nd");
+        assert_eq!(diff_lines(&old, &old), []);
+        assert_eq!(diff_lines(&old, &lines("a\nb\nx\ny\nc\nd")), [hunk(2, 2, HunkKind::Added)]);
+        assert_eq!(diff_lines(&old, &lines("a\nd")), [hunk(1, 0, HunkKind::Deleted)]);
+        assert_eq!(diff_lines(&old, &lines("b\nc\nd")), [hunk(0, 0, HunkKind::Deleted)]);
+        assert_eq!(diff_lines(&old, &lines("a\nB\nc\nd")), [hunk(1, 1, HunkKind::Changed)]);
+        assert_eq!(
+            diff_lines(&old, &lines("x\na\nc\nD")),
+            [hunk(0, 1, HunkKind::Added), hunk(2, 0, HunkKind::Deleted), hunk(3, 1, HunkKind::Changed)],
+        );
+        assert_eq!(diff_lines(&lines(""), &lines("a\nb")), [hunk(0, 2, HunkKind::Changed)]);
+    }
+
+    #[test]
+    fn rope_lines_compare_with_strings() {
+        let content = TextBuffer::from_text("a\nB\nc\nd\n");
+        let slices: Vec<RopeSlice> = (0..content.line_count()).map(|l| content.line_slice(l)).collect();
+        assert_eq!(diff_lines(&lines("a\nb\nc\nd\n"), &slices), [hunk(1, 1, HunkKind::Changed)]);
+    }
+
+    // length of the longest common subsequence, the slow way
+    fn lcs(old: &[String], new: &[String]) -> usize {
+        let mut table = vec![vec![0; new.len() + 1]; old.len() + 1];
+        for i in (0..old.len()).rev() {
+            for j in (0..new.len()).rev() {
+                table[i][j] = if old[i] == new[j] { table[i + 1][j + 1] + 1 } else { table[i + 1][j].max(table[i][j + 1]) };
+            }
+        }
+        table[0][0]
+    }
+
+    #[test]
+    fn hunks_keep_a_longest_common_subsequence() {
+        let mut seed = 7u32;
+        let mut random = |n: u32| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 16) % n
+        };
+
+        for _ in 0..300 {
+            let old: Vec<String> = (0..random(30)).map(|_| random(4).to_string()).collect();
+            let new: Vec<String> = (0..random(30)).map(|_| random(4).to_string()).collect();
+            let hunks = diff_lines(&old, &new);
+
+            // the lines outside of hunks are kept ones: in order in the old
+            // file too, and as many as can be
+            let kept: Vec<&String> = (0..new.len())
+                .filter(|l| !hunks.iter().any(|h| (h.start..h.start + h.len).contains(l)))
+                .map(|l| &new[l])
+                .collect();
+            let mut old_lines = old.iter();
+            assert!(kept.iter().all(|line| old_lines.any(|old| old == *line)), "{:?} -> {:?}", old, new);
+            assert_eq!(kept.len(), lcs(&old, &new), "{:?} -> {:?}", old, new);
+
+            for pair in hunks.windows(2) {
+                assert!(pair[0].start + pair[0].len <= pair[1].start);
+            }
+        }
+    }
+
+    fn git_in(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com", "-c", "commit.gpgsign=false"])
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    fn repo() -> TempDir {
+        let dir = TempDir::new("git");
+        git_in(dir.path(), &["init", "-q"]);
+        fs::write(dir.join("tracked.txt"), "a\nb\nc\n").unwrap();
+        fs::write(dir.join("clean.txt"), "clean\n").unwrap();
+        fs::write(dir.join(".gitignore"), "*.log\n").unwrap();
+        git_in(dir.path(), &["add", "."]);
+        git_in(dir.path(), &["commit", "-q", "-m", "first"]);
+        dir
+    }
+
+    #[test]
+    fn diff_against_head() {
+        let dir = repo();
+        let path = dir.join("tracked.txt");
+        let mut diff = Diff::load(path.to_str().unwrap());
+        assert!(diff.is_due());
+
+        let content = TextBuffer::from_text("a\nB\nc\nd");
+        diff.refresh(&content);
+        assert_eq!(diff.hunks, [hunk(1, 1, HunkKind::Changed), hunk(3, 1, HunkKind::Added)]);
+        assert_eq!(diff.marker(1), Some((HunkKind::Changed, false)));
+        assert_eq!(diff.marker(0), None);
+
+        let untracked = Diff::load(dir.join("new.txt").to_str().unwrap());
+        assert!(!untracked.is_stale());
+    }
+
+    #[test]
+    fn edits_wait_for_typing_to_pause() {
+        let dir = repo();
+        let mut diff = Diff::load(dir.join("tracked.txt").to_str().unwrap());
+        diff.refresh(&TextBuffer::from_text("a\nb\nc"));
+        assert_eq!(diff.hunks, []);
+
+        diff.invalidate();
+        let content = TextBuffer::from_text("a\nb");
+        diff.refresh_when_due(&content);
+        assert!(diff.is_stale() && !diff.is_due());
+        assert_eq!(diff.hunks, []);
+
+        std::thread::sleep(REFRESH_DELAY);
+        assert!(diff.is_due());
+        diff.refresh_when_due(&content);
+        assert_eq!(diff.hunks, [hunk(2, 0, HunkKind::Deleted)]);
+        assert!(!diff.is_stale());
+    }
+
+    #[test]
+    fn status_of_files_and_folders() {
+        let dir = repo();
+        fs::write(dir.join("tracked.txt"), "changed\n").unwrap();
+        fs::write(dir.join("staged.txt"), "new\n").unwrap();
+        git_in(dir.path(), &["add", "staged.txt"]);
+        fs::create_dir_all(dir.join("sub/deep")).unwrap();
+        fs::write(dir.join("sub/deep/loose.txt"), "x\n").unwrap();
+        fs::write(dir.join("debug.log"), "x\n").unwrap();
+
+        let status = RepoStatus::load(dir.path()).unwrap();
+        assert_eq!(status.get(&dir.join("tracked.txt")), Some(FileStatus::Modified));
+        assert_eq!(status.get(&dir.join("staged.txt")), Some(FileStatus::Added));
+        assert_eq!(status.get(&dir.join("debug.log")), Some(FileStatus::Ignored));
+        assert_eq!(status.get(&dir.join("clean.txt")), None);
+
+        // git lists the untracked folder, not what's in it
+        assert_eq!(status.get(&dir.join("sub")), Some(FileStatus::Untracked));
+        assert_eq!(status.get(&dir.join("sub/deep/loose.txt")), Some(FileStatus::Untracked));
+    }
+
+    #[test]
+    fn folders_take_the_strongest_status_inside() {
+        let dir = repo();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/a.txt"), "a\n").unwrap();
+        fs::write(dir.join("src/b.txt"), "b\n").unwrap();
+        git_in(dir.path(), &["add", "src"]);
+        git_in(dir.path(), &["commit", "-q", "-m", "src"]);
+        fs::write(dir.join("src/a.txt"), "changed\n").unwrap();
+        fs::write(dir.join("src/c.txt"), "c\n").unwrap();
+
+        let status = RepoStatus::load(dir.path()).unwrap();
+        assert_eq!(status.get(&dir.join("src")), Some(FileStatus::Modified));
+        assert_eq!(status.get(&dir.join("src/c.txt")), Some(FileStatus::Untracked));
+        assert_eq!(status.get(&dir.join("src/b.txt")), None);
+    }
+
+    #[test]
+    fn no_status_outside_a_repo() {
+        let dir = TempDir::new("no-repo");
+        assert!(RepoStatus::load(dir.path()).is_none());
+    }
+}
@@ -0,0 +1,86 @@
+use std::fs;
+use std::path::Path;
+use regex::Regex;
+use crate::finder;
+use crate::unicode::char_to_grapheme;
+
+// files with a NUL byte in their first few KB are taken as binary
+const BINARY_CHECK: usize = 8192;
+
+// one match of a :grep, the entries of the quickfix list
+pub struct Hit {
+    pub path: String,
+    pub line: usize,
+    pub col: usize,
+    pub text: String,
+}
+
+// the hits of the last :grep and the one :cn / :cp are at. `open` shows
+// them as a list over the text
+#[derive(Default)]
+pub struct Quickfix {
+    pub hits: Vec<Hit>,
+    pub current: usize,
+    pub open: bool,
+    pub scroll: usize,
+}
+
+// `pattern [path]`. a pattern with spaces goes in quotes: "fn main"
+pub fn parse_args(args: &str) -> Result<(String, Option<String>), String> {
+    let args = args.trim();
+
+    let (pattern, rest) = match args.chars().next() {
+        Some(quote @ ('"' | '\'')) => {
+            let end = args[1..].find(quote).ok_or_else(|| format!("Missing closing {}", quote))?;
+            (&args[1..end + 1], &args[end + 2..])
+        },
+        _ => args.split_once(char::is_whitespace).unwrap_or((args, "")),
+    };
+
+    if pattern.is_empty() {
+        return Err("Missing pattern".to_string());
+    }
+
+    let rest = rest.trim();
+    Ok((pattern.to_string(), (!rest.is_empty()).then(|| rest.to_string())))
+}
+
+// every line matching `pattern` in the files under `path` (a folder or a
+// single file), skipping binaries and whatever .gitignore leaves out
+pub fn grep(pattern: &str, path: &Path) -> Result<Vec<Hit>, String> {
+    let regex = Regex::new(pattern).map_err(|_| format!("Invalid pattern: {}", pattern))?;
+
+    let files: Vec<String> = if path.is_file() {
+        vec![path.to_string_lossy().into_owned()]
+    } else if path.is_dir() {
+        finder::walk(path).into_iter()
+            .map(|file| path.join(file).to_string_lossy().into_owned())
+            .collect()
+    } else {
+        return Err(format!("No such file or folder: {}", path.display()));
+    };
+
+    let mut hits = Vec::new();
+
+    for file in files {
+        let Ok(bytes) = fs::read(&file) else { continue };
+        if bytes[..bytes.len().min(BINARY_CHECK)].contains(&0) {
+            continue;
+        }
+
+        let text = String::from_utf8_lossy(&bytes);
+        for (l, line) in text.lines().enumerate() {
+            if let Some(m) = regex.find(line) {
+                let idx = line[..m.start()].chars().count();
+                hits.push(Hit {
+                    path: file.clone(),
+                    line: l,
+                    col: char_to_grapheme(line, idx),
+                    text: line.trim().to_string(),
+                });
+            }
+        }
+    }
+
+    Ok(hits)
+}
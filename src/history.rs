@@ -0,0 +1,109 @@
+use crate::text_buffer::TextBuffer;
+
+pub struct Snapshot {
+    pub content: TextBuffer,
+    pub cursor_l: usize,
+    pub cursor_c: usize,
+}
+
+pub struct History {
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    group_open: bool,
+}
+
+impl History {
+    pub fn new() -> Self {
+        History {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            group_open: false,
+        }
+    }
+
+    // saves the state before an edit. while a group is open (one insert mode
+    // session) the following edits are folded into the same undo step
+    pub fn push(&mut self, snapshot: Snapshot) {
+        self.undo.push(snapshot);
+        self.redo.clear();
+    }
+
+    pub fn in_group(&self) -> bool {
+        self.group_open
+    }
+
+    pub fn open_group(&mut self) {
+        self.group_open = true;
+    }
+
+    pub fn close_group(&mut self) {
+        self.group_open = false;
+    }
+
+    pub fn undo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        self.group_open = false;
+        let previous = self.undo.pop()?;
+        self.redo.push(current);
+        Some(previous)
+    }
+
+    pub fn redo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        self.group_open = false;
+        let next = self.redo.pop()?;
+        self.undo.push(current);
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(text: &str) -> Snapshot {
+        Snapshot { content: TextBuffer::from_text(text), cursor_l: 0, cursor_c: text.len() }
+    }
+
+    fn text(snapshot: &Snapshot) -> String {
+        snapshot.content.text()
+    }
+
+    #[test]
+    fn undo_and_redo_walk_back_and_forth() {
+        let mut history = History::new();
+        history.push(snapshot("a"));
+        history.push(snapshot("ab"));
+
+        let previous = history.undo(snapshot("abc")).unwrap();
+        assert_eq!(text(&previous), "ab");
+        let previous = history.undo(previous).unwrap();
+        assert_eq!(text(&previous), "a");
+        assert!(history.undo(snapshot("a")).is_none());
+
+        let next = history.redo(previous).unwrap();
+        assert_eq!(text(&next), "ab");
+        let next = history.redo(next).unwrap();
+        assert_eq!(text(&next), "abc");
+        assert!(history.redo(next).is_none());
+    }
+
+    #[test]
+    fn a_new_edit_clears_redo() {
+        let mut history = History::new();
+        history.push(snapshot("a"));
+        let previous = history.undo(snapshot("ab")).unwrap();
+
+        history.push(previous);
+        assert!(history.redo(snapshot("ax")).is_none());
+    }
+
+    #[test]
+    fn undo_closes_the_open_group() {
+        let mut history = History::new();
+        history.open_group();
+        assert!(history.in_group());
+
+        history.push(snapshot("a"));
+        history.undo(snapshot("ab"));
+        assert!(!history.in_group());
+    }
+}
@@ -0,0 +1,493 @@
+use crossterm::event::{KeyCode, KeyModifiers};
+
+// a key press with the modifiers that matter for bindings. shift is part of
+// the char itself ('A'), so it's only kept for the other keys (<S-Tab>)
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl Key {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut modifiers = modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        if let KeyCode::Char(_) = code {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Key { code, modifiers }
+    }
+
+    pub fn char(c: char) -> Self {
+        Key::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MapMode {
+    Normal,
+    Insert,
+    Visual,
+}
+
+impl MapMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            MapMode::Normal => "normal",
+            MapMode::Insert => "insert",
+            MapMode::Visual => "visual",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Binding {
+    Action(&'static str),
+    // typed as if they came from the keyboard
+    Keys(Vec<Key>),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Mapping {
+    pub keys: Vec<Key>,
+    pub binding: Binding,
+}
+
+// what the editor has to do after a key: run an action or handle a key
+// that has no binding
+#[derive(Clone, PartialEq, Debug)]
+pub enum Input {
+    Action(&'static str),
+    Key(Key),
+}
+
+// every action a key can be bound to
+pub const ACTIONS: &[(&str, &str)] = &[
+    ("escape", "back to command mode"),
+    ("undo", "undo last change"),
+    ("redo", "redo last undone change"),
+    ("file.save", "save the file"),
+    ("insert.newline", "break the line"),
+    ("insert.backspace", "delete before the cursor"),
+    ("insert.delete", "delete under the cursor"),
+    ("insert.tab", "insert an indent"),
+    ("cursor.left", "move left"),
+    ("cursor.right", "move right"),
+    ("cursor.up", "move up"),
+    ("cursor.down", "move down"),
+    ("files.up", "previous file in the sidebar"),
+    ("files.down", "next file in the sidebar"),
+    ("files.open", "open the file or folder"),
+    ("files.back", "go to the parent folder"),
+    ("files.find", "fuzzy find a file in the project"),
+    ("files.create", "create a file or folder"),
+    ("files.rename", "rename the selected file"),
+    ("files.delete", "delete the selected file"),
+    ("files.copy", "copy the selected file"),
+    ("files.move", "move the selected file"),
+    ("files.hidden", "show or hide hidden files"),
+    ("sidebar.toggle", "show or hide the sidebar"),
+    ("sidebar.wider", "make the sidebar wider"),
+    ("sidebar.narrower", "make the sidebar narrower"),
+    ("window.next", "focus the next window"),
+    ("window.left", "focus the window on the left"),
+    ("window.down", "focus the window below"),
+    ("window.up", "focus the window above"),
+    ("window.right", "focus the window on the right"),
+    ("window.split", "split the window"),
+    ("window.vsplit", "split the window vertically"),
+    ("window.close", "close the window"),
+    ("buffer.next", "next buffer"),
+    ("buffer.prev", "previous buffer"),
+    ("visual.char", "visual mode by char"),
+    ("visual.line", "visual mode by line"),
+    ("visual.block", "visual mode by block"),
+    ("search.next", "next match"),
+    ("search.prev", "previous match"),
+    ("lsp.hover", "show what's under the cursor"),
+    ("lsp.definition", "go to the definition"),
+    ("lsp.complete", "complete the word"),
+];
+
+// modes (n, i, v), keys, action
+const DEFAULTS: &[(&str, &str, &str)] = &[
+    ("niv", "<Esc>", "escape"),
+    ("n", "<C-r>", "redo"),
+    ("nv", "<C-v>", "visual.block"),
+    ("niv", "<C-w>w", "window.next"),
+    ("niv", "<C-w><C-w>", "window.next"),
+    ("niv", "<C-w>h", "window.left"),
+    ("niv", "<C-w>j", "window.down"),
+    ("niv", "<C-w>k", "window.up"),
+    ("niv", "<C-w>l", "window.right"),
+    ("niv", "<C-w><Left>", "window.left"),
+    ("niv", "<C-w><Down>", "window.down"),
+    ("niv", "<C-w><Up>", "window.up"),
+    ("niv", "<C-w><Right>", "window.right"),
+    ("niv", "<C-w>s", "window.split"),
+    ("niv", "<C-w>v", "window.vsplit"),
+    ("niv", "<C-w>c", "window.close"),
+    ("niv", "<C-w>q", "window.close"),
+    ("n", "<C-p>", "files.find"),
+    ("n", "<A-a>", "files.create"),
+    ("n", "<A-r>", "files.rename"),
+    ("n", "<A-d>", "files.delete"),
+    ("n", "<A-c>", "files.copy"),
+    ("n", "<A-m>", "files.move"),
+    ("n", "<A-h>", "files.hidden"),
+    ("n", "<C-b>", "sidebar.toggle"),
+    ("n", "<A-.>", "sidebar.wider"),
+    ("n", "<A-,>", "sidebar.narrower"),
+    ("i", "<C-Space>", "lsp.complete"),
+    ("n", "<Up>", "files.up"),
+    ("n", "<Down>", "files.down"),
+    ("n", "<Right>", "files.open"),
+    ("n", "<Left>", "files.back"),
+    ("iv", "<Up>", "cursor.up"),
+    ("iv", "<Down>", "cursor.down"),
+    ("iv", "<Left>", "cursor.left"),
+    ("iv", "<Right>", "cursor.right"),
+    ("i", "<CR>", "insert.newline"),
+    ("i", "<BS>", "insert.backspace"),
+    ("i", "<Del>", "insert.delete"),
+    ("i", "<Tab>", "insert.tab"),
+];
+
+const MAX_DEPTH: usize = 10;
+
+pub struct Keymap {
+    normal: Vec<Mapping>,
+    insert: Vec<Mapping>,
+    visual: Vec<Mapping>,
+}
+
+impl Keymap {
+    pub fn new() -> Self {
+        let mut keymap = Keymap { normal: Vec::new(), insert: Vec::new(), visual: Vec::new() };
+
+        for (modes, keys, action) in DEFAULTS {
+            let keys = parse_keys(keys).unwrap();
+            for mode in modes.chars() {
+                let mode = match mode {
+                    'n' => MapMode::Normal,
+                    'i' => MapMode::Insert,
+                    _ => MapMode::Visual,
+                };
+                keymap.bind(mode, keys.clone(), Binding::Action(action));
+            }
+        }
+
+        keymap
+    }
+
+    pub fn bindings(&self, mode: MapMode) -> &[Mapping] {
+        match mode {
+            MapMode::Normal => &self.normal,
+            MapMode::Insert => &self.insert,
+            MapMode::Visual => &self.visual,
+        }
+    }
+
+    fn bindings_mut(&mut self, mode: MapMode) -> &mut Vec<Mapping> {
+        match mode {
+            MapMode::Normal => &mut self.normal,
+            MapMode::Insert => &mut self.insert,
+            MapMode::Visual => &mut self.visual,
+        }
+    }
+
+    pub fn bind(&mut self, mode: MapMode, keys: Vec<Key>, binding: Binding) {
+        let bindings = self.bindings_mut(mode);
+        match bindings.iter_mut().find(|m| m.keys == keys) {
+            Some(mapping) => mapping.binding = binding,
+            None => bindings.push(Mapping { keys, binding }),
+        }
+    }
+
+    // `:nmap <C-s> file.save` or `:imap jk <Esc>`. the right side is an
+    // action name or else the keys to type
+    pub fn map(&mut self, modes: &[MapMode], lhs: &str, rhs: &str) -> Result<(), String> {
+        let keys = parse_keys(lhs)?;
+        if keys.is_empty() {
+            return Err("Missing keys to map".to_string());
+        }
+
+        let binding = match ACTIONS.iter().find(|(name, _)| *name == rhs) {
+            Some((name, _)) => Binding::Action(name),
+            None => {
+                let rhs_keys = parse_keys(rhs)?;
+                if rhs_keys.is_empty() {
+                    return Err(format!("Missing action for {}", lhs));
+                }
+                Binding::Keys(rhs_keys)
+            },
+        };
+
+        for mode in modes {
+            self.bind(*mode, keys.clone(), binding.clone());
+        }
+        Ok(())
+    }
+
+    // turns the keys typed so far into inputs. keys that start a longer
+    // binding stay in `pending` until the rest comes in or the timeout runs
+    // out, then `flush` handles them with whatever matches
+    pub fn resolve(&self, mode: MapMode, pending: &mut Vec<Key>, flush: bool) -> Vec<Input> {
+        self.resolve_depth(mode, pending, flush, 0)
+    }
+
+    fn resolve_depth(&self, mode: MapMode, pending: &mut Vec<Key>, flush: bool, depth: usize) -> Vec<Input> {
+        let bindings = self.bindings(mode);
+        let mut inputs = Vec::new();
+
+        while !pending.is_empty() {
+            let longer = bindings.iter().any(|m| m.keys.len() > pending.len() && m.keys.starts_with(pending));
+            if longer && !flush {
+                break;
+            }
+
+            // the longest binding the pending keys start with
+            let found = bindings.iter()
+                .filter(|m| pending.starts_with(&m.keys))
+                .max_by_key(|m| m.keys.len());
+
+            match found {
+                Some(mapping) => {
+                    match &mapping.binding {
+                        Binding::Action(name) => inputs.push(Input::Action(name)),
+                        // `:nmap a ab` would never end, so nested keys stop
+                        // being looked up after a few levels
+                        Binding::Keys(keys) if depth >= MAX_DEPTH => inputs.extend(keys.iter().map(|k| Input::Key(*k))),
+                        Binding::Keys(keys) => inputs.extend(self.resolve_depth(mode, &mut keys.clone(), true, depth + 1)),
+                    }
+                    pending.drain(..mapping.keys.len());
+                },
+                None => inputs.push(Input::Key(pending.remove(0))),
+            }
+        }
+
+        inputs
+    }
+}
+
+const NAMES: &[(&str, KeyCode)] = &[
+    ("Esc", KeyCode::Esc),
+    ("CR", KeyCode::Enter),
+    ("Enter", KeyCode::Enter),
+    ("Tab", KeyCode::Tab),
+    ("BS", KeyCode::Backspace),
+    ("Del", KeyCode::Delete),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("Space", KeyCode::Char(' ')),
+    ("lt", KeyCode::Char('<')),
+];
+
+// vim notation: plain chars, <Esc>, <C-w>, <A-x>, <S-Tab>, <C-w>h ...
+pub fn parse_keys(notation: &str) -> Result<Vec<Key>, String> {
+    let mut keys = Vec::new();
+    let mut rest = notation;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '<' && let Some(end) = rest.find('>') && end > 1 {
+            keys.push(parse_special(&rest[1..end]).ok_or_else(|| format!("Invalid key: {}", &rest[..=end]))?);
+            rest = &rest[end + 1..];
+        } else {
+            keys.push(Key::char(c));
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    Ok(keys)
+}
+
+fn parse_special(name: &str) -> Option<Key> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = name;
+
+    while rest.len() > 2 && rest.as_bytes()[1] == b'-' {
+        modifiers |= match rest.as_bytes()[0].to_ascii_uppercase() {
+            b'C' => KeyModifiers::CONTROL,
+            b'A' | b'M' => KeyModifiers::ALT,
+            b'S' => KeyModifiers::SHIFT,
+            _ => return None,
+        };
+        rest = &rest[2..];
+    }
+
+    let mut chars = rest.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) if modifiers.contains(KeyModifiers::CONTROL) => KeyCode::Char(c.to_ascii_lowercase()),
+        (Some(c), None) => KeyCode::Char(c),
+        _ => {
+            if let Some(n) = rest.strip_prefix(['F', 'f']).and_then(|n| n.parse::<u8>().ok()) {
+                KeyCode::F(n)
+            } else {
+                NAMES.iter().find(|(key, _)| key.eq_ignore_ascii_case(rest))?.1
+            }
+        },
+    };
+
+    Some(Key::new(code, modifiers))
+}
+
+pub fn format_keys(keys: &[Key]) -> String {
+    keys.iter().map(format_key).collect()
+}
+
+fn format_key(key: &Key) -> String {
+    let name = match key.code {
+        KeyCode::Char('<') => "lt".to_string(),
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char(c) if key.modifiers.is_empty() => return c.to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::F(n) => format!("F{}", n),
+        code => NAMES.iter()
+            .find(|(_, c)| *c == code)
+            .map_or_else(|| format!("{:?}", code), |(name, _)| name.to_string()),
+    };
+
+    let mut prefix = String::new();
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        prefix.push_str("C-");
+    }
+    if key.modifiers.contains(KeyModifiers::ALT) {
+        prefix.push_str("A-");
+    }
+    if key.modifiers.contains(KeyModifiers::SHIFT) {
+        prefix.push_str("S-");
+    }
+
+    format!("<{}{}>", prefix, name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> Key {
+        Key::new(code, modifiers)
+    }
+
+    fn keys(notation: &str) -> Vec<Key> {
+        parse_keys(notation).unwrap()
+    }
+
+    fn ctrl_w() -> Key {
+        key(KeyCode::Char('w'), KeyModifiers::CONTROL)
+    }
+
+    #[test]
+    fn notation() {
+        assert_eq!(keys("<C-w><C-w>"), [ctrl_w(), ctrl_w()]);
+        assert_eq!(keys("<C-W>h"), [ctrl_w(), Key::char('h')]);
+        assert_eq!(keys("<A-.>"), [key(KeyCode::Char('.'), KeyModifiers::ALT)]);
+        assert_eq!(keys("<M-x>"), [key(KeyCode::Char('x'), KeyModifiers::ALT)]);
+        assert_eq!(keys("<S-Tab>"), [key(KeyCode::Tab, KeyModifiers::SHIFT)]);
+        assert_eq!(keys("<C-S-Left>"), [key(KeyCode::Left, KeyModifiers::CONTROL | KeyModifiers::SHIFT)]);
+        assert_eq!(keys("<lt>"), [Key::char('<')]);
+        assert_eq!(keys("<cr><F12><Space>"), [
+            key(KeyCode::Enter, KeyModifiers::NONE),
+            key(KeyCode::F(12), KeyModifiers::NONE),
+            Key::char(' '),
+        ]);
+        // a < that doesn't start a name is just a char
+        assert_eq!(keys("a<b"), [Key::char('a'), Key::char('<'), Key::char('b')]);
+        assert_eq!(keys("<>"), [Key::char('<'), Key::char('>')]);
+        assert_eq!(keys("jk"), [Key::char('j'), Key::char('k')]);
+        assert_eq!(keys(""), []);
+
+        assert_eq!(parse_keys("<Foo>"), Err("Invalid key: <Foo>".to_string()));
+        assert_eq!(parse_keys("a<X-a>b"), Err("Invalid key: <X-a>".to_string()));
+        assert_eq!(parse_keys("<C-Bogus>"), Err("Invalid key: <C-Bogus>".to_string()));
+    }
+
+    #[test]
+    fn notation_round_trips() {
+        for notation in ["<C-w>h", "<lt>", "<S-Tab>", "<A-.>", "<Space>", "<F5>", "<C-A-x>", "jk<Esc>"] {
+            assert_eq!(format_keys(&keys(notation)), notation);
+        }
+    }
+
+    #[test]
+    fn prefixes_wait_for_the_timeout() {
+        let mut keymap = Keymap::new();
+        keymap.map(&[MapMode::Insert], "jk", "<Esc>").unwrap();
+
+        // j might start jk
+        let mut pending = keys("j");
+        assert_eq!(keymap.resolve(MapMode::Insert, &mut pending, false), []);
+        assert_eq!(pending, keys("j"));
+
+        let mut done = keys("jk");
+        assert_eq!(keymap.resolve(MapMode::Insert, &mut done, false), [Input::Action("escape")]);
+        assert!(done.is_empty());
+
+        // the timeout types the j after all
+        assert_eq!(keymap.resolve(MapMode::Insert, &mut pending, true), [Input::Key(Key::char('j'))]);
+        assert!(pending.is_empty());
+
+        let mut other = keys("jx");
+        assert_eq!(keymap.resolve(MapMode::Insert, &mut other, false), [Input::Key(Key::char('j')), Input::Key(Key::char('x'))]);
+    }
+
+    #[test]
+    fn complete_bindings_that_start_longer_ones() {
+        let mut keymap = Keymap::new();
+        let mut pending = vec![ctrl_w()];
+        assert_eq!(keymap.resolve(MapMode::Normal, &mut pending, false), []);
+        assert_eq!(keymap.resolve(MapMode::Normal, &mut pending, true), [Input::Key(ctrl_w())]);
+
+        let mut pending = vec![ctrl_w(), ctrl_w()];
+        assert_eq!(keymap.resolve(MapMode::Normal, &mut pending, false), [Input::Action("window.next")]);
+
+        // g alone is bound, gx too: g waits, and on the timeout runs the
+        // shorter binding
+        keymap.map(&[MapMode::Normal], "g", "undo").unwrap();
+        keymap.map(&[MapMode::Normal], "gx", "redo").unwrap();
+        let mut pending = keys("g");
+        assert_eq!(keymap.resolve(MapMode::Normal, &mut pending, false), []);
+        assert_eq!(keymap.resolve(MapMode::Normal, &mut pending, true), [Input::Action("undo")]);
+        let mut pending = keys("gxg");
+        assert_eq!(keymap.resolve(MapMode::Normal, &mut pending, false), [Input::Action("redo")]);
+        assert_eq!(pending, keys("g"));
+    }
+
+    #[test]
+    fn keys_bindings_are_resolved_again() {
+        let mut keymap = Keymap::new();
+        keymap.map(&[MapMode::Normal], "Q", "<C-w>v").unwrap();
+        keymap.map(&[MapMode::Normal], "D", "dd").unwrap();
+        // would loop forever without the depth limit
+        keymap.map(&[MapMode::Normal], "a", "ab").unwrap();
+
+        assert_eq!(keymap.resolve(MapMode::Normal, &mut keys("Q"), false), [Input::Action("window.vsplit")]);
+        assert_eq!(keymap.resolve(MapMode::Normal, &mut keys("D"), false), [Input::Key(Key::char('d')), Input::Key(Key::char('d'))]);
+
+        let inputs = keymap.resolve(MapMode::Normal, &mut keys("a"), false);
+        assert_eq!(inputs.len(), MAX_DEPTH + 2);
+        assert_eq!(inputs[0], Input::Key(Key::char('a')));
+        assert!(inputs[1..].iter().all(|input| *input == Input::Key(Key::char('b'))));
+    }
+
+    #[test]
+    fn map_errors() {
+        let mut keymap = Keymap::new();
+        assert_eq!(keymap.map(&[MapMode::Normal], "", "undo"), Err("Missing keys to map".to_string()));
+        assert_eq!(keymap.map(&[MapMode::Normal], "x", ""), Err("Missing action for x".to_string()));
+        assert_eq!(keymap.map(&[MapMode::Normal], "<Bad>", "undo"), Err("Invalid key: <Bad>".to_string()));
+        assert_eq!(keymap.map(&[MapMode::Normal], "x", "<Nope>"), Err("Invalid key: <Nope>".to_string()));
+
+        // mapping again replaces the binding
+        keymap.map(&[MapMode::Normal, MapMode::Visual], "<C-r>", "undo").unwrap();
+        let redo: Vec<&Mapping> = keymap.bindings(MapMode::Normal).iter().filter(|m| m.keys == keys("<C-r>")).collect();
+        assert_eq!(redo.len(), 1);
+        assert_eq!(redo[0].binding, Binding::Action("undo"));
+        assert!(keymap.bindings(MapMode::Insert).iter().all(|m| m.keys != keys("<C-r>")));
+    }
+}
@@ -0,0 +1,880 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde_json::{Value, json};
+use crate::config::LspConfig;
+use crate::finder;
+
+// how long quitting waits for a server to exit before killing it
+const EXIT_WAIT: Duration = Duration::from_millis(200);
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+    Hint,
+}
+
+// positions are kept as the server sends them: (line, UTF-16 column)
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    pub severity: Severity,
+    pub message: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Location {
+    pub path: PathBuf,
+    pub line: usize,
+    pub character: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct CompletionItem {
+    pub label: String,
+    pub detail: String,
+    pub insert: String,
+    filter: String,
+}
+
+// what the servers sent back since the last poll
+pub enum Event {
+    Diagnostics(String, Vec<Diagnostic>),
+    Hover(Vec<String>),
+    Definition(Vec<Location>),
+    Completion(Vec<CompletionItem>),
+    Message(String),
+}
+
+#[derive(Clone, Copy)]
+enum Request {
+    Initialize,
+    Hover,
+    Definition,
+    Completion,
+    Shutdown,
+}
+
+// one running language server, talking JSON-RPC over its stdin / stdout.
+// a thread reads the replies so the editor never blocks on the server
+pub struct Client {
+    name: String,
+    child: Child,
+    stdin: ChildStdin,
+    messages: Receiver<Value>,
+    next_id: u64,
+    pending: HashMap<u64, Request>,
+    // notifications and requests wait here until initialize is answered
+    initialized: bool,
+    queued: Vec<Value>,
+    // open documents by uri, with their version
+    documents: HashMap<String, i64>,
+    exited: bool,
+    pub trigger_chars: Vec<char>,
+}
+
+impl Client {
+    pub fn start(config: &LspConfig, root: &Path) -> io::Result<Self> {
+        let mut child = Command::new(&config.command)
+            .args(&config.args)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let (sender, messages) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut reader = BufReader::new(stdout);
+            while let Ok(Some(message)) = read_message(&mut reader) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut client = Client {
+            name: config.command.clone(),
+            child,
+            stdin,
+            messages,
+            next_id: 0,
+            pending: HashMap::new(),
+            initialized: false,
+            queued: Vec::new(),
+            documents: HashMap::new(),
+            exited: false,
+            trigger_chars: Vec::new(),
+        };
+
+        let root_uri = path_uri(root);
+        client.request(Request::Initialize, "initialize", json!({
+            "processId": std::process::id(),
+            "rootUri": root_uri,
+            "workspaceFolders": [{ "uri": root_uri, "name": root.file_name().map(|n| n.to_string_lossy()) }],
+            "clientInfo": { "name": "reedit", "version": env!("CARGO_PKG_VERSION") },
+            "capabilities": {
+                "general": { "positionEncodings": ["utf-16"] },
+                "textDocument": {
+                    "synchronization": { "didSave": true },
+                    "publishDiagnostics": {},
+                    "hover": { "contentFormat": ["plaintext", "markdown"] },
+                    "definition": { "linkSupport": true },
+                    "completion": { "completionItem": { "snippetSupport": false } },
+                },
+            },
+        }));
+
+        Ok(client)
+    }
+
+    pub fn is_open(&self, uri: &str) -> bool {
+        self.documents.contains_key(uri)
+    }
+
+    pub fn did_open(&mut self, uri: &str, language: &str, text: &str) {
+        self.documents.insert(uri.to_string(), 0);
+        self.notify("textDocument/didOpen", json!({
+            "textDocument": { "uri": uri, "languageId": language_id(language), "version": 0, "text": text },
+        }));
+    }
+
+    // the whole text is sent every time, which every server takes
+    pub fn did_change(&mut self, uri: &str, text: &str) {
+        let Some(version) = self.documents.get_mut(uri) else { return };
+        *version += 1;
+        let version = *version;
+
+        self.notify("textDocument/didChange", json!({
+            "textDocument": { "uri": uri, "version": version },
+            "contentChanges": [{ "text": text }],
+        }));
+    }
+
+    pub fn did_save(&mut self, uri: &str) {
+        if self.is_open(uri) {
+            self.notify("textDocument/didSave", json!({ "textDocument": { "uri": uri } }));
+        }
+    }
+
+    // closes every document that isn't in `keep`
+    pub fn close_others(&mut self, keep: &HashSet<String>) {
+        let closed: Vec<String> = self.documents.keys().filter(|uri| !keep.contains(*uri)).cloned().collect();
+        for uri in closed {
+            self.documents.remove(&uri);
+            self.notify("textDocument/didClose", json!({ "textDocument": { "uri": uri } }));
+        }
+    }
+
+    pub fn hover(&mut self, uri: &str, position: (usize, usize)) {
+        self.request(Request::Hover, "textDocument/hover", position_params(uri, position));
+    }
+
+    pub fn definition(&mut self, uri: &str, position: (usize, usize)) {
+        self.request(Request::Definition, "textDocument/definition", position_params(uri, position));
+    }
+
+    pub fn completion(&mut self, uri: &str, position: (usize, usize)) {
+        self.request(Request::Completion, "textDocument/completion", position_params(uri, position));
+    }
+
+    fn request(&mut self, kind: Request, method: &str, params: Value) {
+        self.next_id += 1;
+        self.pending.insert(self.next_id, kind);
+        self.send(json!({ "jsonrpc": "2.0", "id": self.next_id, "method": method, "params": params }));
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    fn send(&mut self, message: Value) {
+        if !self.initialized && message["method"] != "initialize" {
+            self.queued.push(message);
+            return;
+        }
+        self.write(&message);
+    }
+
+    // a server that died just stops answering, it's noticed in poll
+    fn write(&mut self, message: &Value) {
+        let body = message.to_string();
+        let _ = write!(self.stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let _ = self.stdin.flush();
+    }
+
+    fn poll(&mut self, events: &mut Vec<Event>) {
+        loop {
+            match self.messages.try_recv() {
+                Ok(message) => self.handle(message, events),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if !self.exited {
+                        self.exited = true;
+                        events.push(Event::Message(format!("Language server {} exited", self.name)));
+                    }
+                    break;
+                },
+            }
+        }
+    }
+
+    fn handle(&mut self, message: Value, events: &mut Vec<Event>) {
+        let method = message["method"].as_str();
+        let id = message.get("id").cloned();
+
+        match (method, id) {
+            // a request from the server. none of them need more than an
+            // empty answer, but they all need one
+            (Some(method), Some(id)) => {
+                let result = if method == "workspace/configuration" {
+                    let items = message["params"]["items"].as_array().map_or(0, Vec::len);
+                    Value::Array(vec![Value::Null; items])
+                } else {
+                    Value::Null
+                };
+                self.write(&json!({ "jsonrpc": "2.0", "id": id, "result": result }));
+            },
+            (Some("textDocument/publishDiagnostics"), None) => {
+                let params = &message["params"];
+                let Some(uri) = params["uri"].as_str() else { return };
+                let diagnostics = params["diagnostics"].as_array()
+                    .map(|items| items.iter().filter_map(parse_diagnostic).collect())
+                    .unwrap_or_default();
+                events.push(Event::Diagnostics(uri.to_string(), diagnostics));
+            },
+            (Some("window/showMessage"), None) => {
+                // errors and warnings only, the rest is chatter
+                let params = &message["params"];
+                if params["type"].as_u64().is_some_and(|kind| kind <= 2)
+                    && let Some(text) = params["message"].as_str()
+                {
+                    events.push(Event::Message(format!("{}: {}", self.name, first_line(text))));
+                }
+            },
+            (Some(_), None) => {},
+            (None, Some(id)) => {
+                let Some(kind) = id.as_u64().and_then(|id| self.pending.remove(&id)) else { return };
+
+                if let Some(error) = message.get("error") {
+                    let text = error["message"].as_str().unwrap_or("request failed");
+                    events.push(Event::Message(format!("{}: {}", self.name, first_line(text))));
+                    return;
+                }
+                self.handle_result(kind, &message["result"], events);
+            },
+            (None, None) => {},
+        }
+    }
+
+    fn handle_result(&mut self, kind: Request, result: &Value, events: &mut Vec<Event>) {
+        match kind {
+            Request::Initialize => {
+                self.trigger_chars = result["capabilities"]["completionProvider"]["triggerCharacters"]
+                    .as_array()
+                    .map(|chars| chars.iter().filter_map(|c| c.as_str()?.chars().next()).collect())
+                    .unwrap_or_default();
+
+                self.initialized = true;
+                self.write(&json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }));
+                for message in std::mem::take(&mut self.queued) {
+                    self.write(&message);
+                }
+            },
+            Request::Hover => events.push(Event::Hover(hover_lines(&result["contents"]))),
+            Request::Definition => events.push(Event::Definition(parse_locations(result))),
+            Request::Completion => {
+                let items = result.as_array().or_else(|| result["items"].as_array());
+                let mut items: Vec<(String, CompletionItem)> = items
+                    .map(|items| items.iter().filter_map(parse_completion).collect())
+                    .unwrap_or_default();
+                items.sort_by(|a, b| a.0.cmp(&b.0));
+                events.push(Event::Completion(items.into_iter().map(|(_, item)| item).collect()));
+            },
+            Request::Shutdown => {},
+        }
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        if self.exited {
+            return;
+        }
+        self.initialized = true;
+        self.request(Request::Shutdown, "shutdown", Value::Null);
+        self.notify("exit", Value::Null);
+
+        let start = Instant::now();
+        while start.elapsed() < EXIT_WAIT {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// the servers by filetype. each one is started the first time a buffer of
+// its filetype shows up
+pub struct Lsp {
+    root: PathBuf,
+    clients: HashMap<String, Client>,
+    // servers that couldn't start aren't tried again
+    failed: HashSet<String>,
+    errors: Vec<String>,
+}
+
+impl Lsp {
+    pub fn new(root: &Path) -> Self {
+        Lsp { root: root.to_path_buf(), clients: HashMap::new(), failed: HashSet::new(), errors: Vec::new() }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.clients.values().any(|client| !client.exited)
+    }
+
+    pub fn start(&mut self, filetype: &str, config: &LspConfig) -> Option<&mut Client> {
+        if !self.clients.contains_key(filetype) && !self.failed.contains(filetype) {
+            match Client::start(config, &self.root) {
+                Ok(client) => {
+                    self.clients.insert(filetype.to_string(), client);
+                },
+                Err(err) => {
+                    self.failed.insert(filetype.to_string());
+                    self.errors.push(format!("Could not start {}: {}", config.command, err));
+                },
+            }
+        }
+        self.client(filetype)
+    }
+
+    pub fn client(&mut self, filetype: &str) -> Option<&mut Client> {
+        self.clients.get_mut(filetype).filter(|client| !client.exited)
+    }
+
+    pub fn close_others(&mut self, keep: &HashSet<String>) {
+        for client in self.clients.values_mut() {
+            client.close_others(keep);
+        }
+    }
+
+    pub fn poll(&mut self) -> Vec<Event> {
+        let mut events: Vec<Event> = self.errors.drain(..).map(Event::Message).collect();
+        for client in self.clients.values_mut() {
+            client.poll(&mut events);
+        }
+        events
+    }
+}
+
+// the Insert mode popup of completions. the word typed since `start` narrows
+// the items down as it grows
+pub struct CompletionMenu {
+    pub items: Vec<CompletionItem>,
+    pub line: usize,
+    // char index where the word being completed starts
+    pub start: usize,
+    // indices into items, best match first
+    pub matches: Vec<usize>,
+    pub selected: usize,
+}
+
+impl CompletionMenu {
+    pub fn new(items: Vec<CompletionItem>, line: usize, start: usize) -> Self {
+        CompletionMenu { items, line, start, matches: Vec::new(), selected: 0 }
+    }
+
+    pub fn refilter(&mut self, typed: &str) {
+        let mut scored: Vec<(i64, usize)> = self.items.iter().enumerate()
+            .filter_map(|(idx, item)| finder::fuzzy_match(typed, &item.filter).map(|(score, _)| (score, idx)))
+            .collect();
+        // the server's order breaks ties
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+        self.matches = scored.into_iter().map(|(_, idx)| idx).collect();
+        self.selected = self.selected.min(self.matches.len().saturating_sub(1));
+    }
+
+    pub fn move_selection(&mut self, down: bool) {
+        if self.matches.is_empty() {
+            return;
+        }
+        self.selected = if down {
+            (self.selected + 1) % self.matches.len()
+        } else {
+            (self.selected + self.matches.len() - 1) % self.matches.len()
+        };
+    }
+
+    pub fn selected_item(&self) -> Option<&CompletionItem> {
+        self.matches.get(self.selected).map(|idx| &self.items[*idx])
+    }
+}
+
+// Content-Length framed messages, None at the end of the stream
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let Some(length) = length else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"));
+    };
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    serde_json::from_slice(&body).map(Some).map_err(io::Error::other)
+}
+
+fn position_params(uri: &str, (line, character): (usize, usize)) -> Value {
+    json!({ "textDocument": { "uri": uri }, "position": { "line": line, "character": character } })
+}
+
+fn parse_position(value: &Value) -> Option<(usize, usize)> {
+    Some((value["line"].as_u64()? as usize, value["character"].as_u64()? as usize))
+}
+
+fn parse_diagnostic(value: &Value) -> Option<Diagnostic> {
+    let severity = match value["severity"].as_u64() {
+        Some(2) => Severity::Warning,
+        Some(3) => Severity::Info,
+        Some(4) => Severity::Hint,
+        _ => Severity::Error,
+    };
+
+    Some(Diagnostic {
+        start: parse_position(&value["range"]["start"])?,
+        end: parse_position(&value["range"]["end"])?,
+        severity,
+        message: value["message"].as_str()?.to_string(),
+    })
+}
+
+// null, a Location, or a list of Locations or LocationLinks
+fn parse_locations(value: &Value) -> Vec<Location> {
+    let items = match value {
+        Value::Array(items) => items.iter().collect(),
+        Value::Object(_) => vec![value],
+        _ => Vec::new(),
+    };
+
+    items.into_iter()
+        .filter_map(|item| {
+            let (uri, range) = match item.get("targetUri") {
+                Some(uri) => (uri, &item["targetSelectionRange"]),
+                None => (&item["uri"], &item["range"]),
+            };
+            let (line, character) = parse_position(&range["start"])?;
+            Some(Location { path: uri_path(uri.as_str()?)?, line, character })
+        })
+        .collect()
+}
+
+// (sort key, item)
+fn parse_completion(value: &Value) -> Option<(String, CompletionItem)> {
+    let label = value["label"].as_str()?.to_string();
+    let text = value["textEdit"]["newText"].as_str()
+        .or_else(|| value["insertText"].as_str())
+        .unwrap_or(&label);
+    let insert = if value["insertTextFormat"].as_u64() == Some(2) {
+        strip_snippet(text)
+    } else {
+        text.to_string()
+    };
+
+    let item = CompletionItem {
+        detail: value["detail"].as_str().map(first_line).unwrap_or_default().to_string(),
+        filter: value["filterText"].as_str().unwrap_or(&label).to_string(),
+        insert,
+        label,
+    };
+    let sort = value["sortText"].as_str().unwrap_or(&item.label).to_string();
+    Some((sort, item))
+}
+
+// MarkupContent, a MarkedString or a list of them, as plain lines. code
+// fences are dropped
+fn hover_lines(contents: &Value) -> Vec<String> {
+    let text = match contents {
+        Value::String(text) => text.clone(),
+        Value::Array(items) => items.iter().map(|item| hover_lines(item).join("\n")).collect::<Vec<_>>().join("\n\n"),
+        Value::Object(object) => object.get("value").and_then(Value::as_str).unwrap_or_default().to_string(),
+        _ => String::new(),
+    };
+
+    let mut lines: Vec<String> = text.lines()
+        .filter(|line| !line.trim_start().starts_with("```"))
+        .map(|line| line.replace('\t', "    "))
+        .collect();
+    while lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+// `${1:name}` keeps its placeholder, tab stops like `$0` go away
+fn strip_snippet(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars().peekable();
+    let mut depth = 0;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            '$' if chars.peek() == Some(&'{') => {
+                chars.next();
+                while chars.next_if(char::is_ascii_digit).is_some() {}
+                chars.next_if_eq(&':');
+                depth += 1;
+            },
+            '$' if chars.peek().is_some_and(char::is_ascii_digit) => {
+                while chars.next_if(char::is_ascii_digit).is_some() {}
+            },
+            '}' if depth > 0 => depth -= 1,
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or_default()
+}
+
+// LSP language ids where they differ from the filetype names
+fn language_id(filetype: &str) -> &str {
+    match filetype {
+        "sh" => "shellscript",
+        "header" => "c",
+        _ => filetype,
+    }
+}
+
+pub fn path_uri(path: &Path) -> String {
+    let path = path.canonicalize()
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf());
+
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+pub fn uri_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+
+    while i < encoded.len() {
+        if encoded[i] == b'%'
+            && let Some(hex) = encoded.get(i + 1..i + 3)
+            && let Ok(byte) = u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16)
+        {
+            bytes.push(byte);
+            i += 3;
+        } else {
+            bytes.push(encoded[i]);
+            i += 1;
+        }
+    }
+
+    Some(PathBuf::from(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+// columns in LSP count UTF-16 units, the text buffer counts chars
+pub fn utf16_col(line: &str, idx: usize) -> usize {
+    line.chars().take(idx).map(char::len_utf16).sum()
+}
+
+pub fn char_col(line: &str, utf16: usize) -> usize {
+    let mut units = 0;
+    for (idx, c) in line.chars().enumerate() {
+        if units >= utf16 {
+            return idx;
+        }
+        units += c.len_utf16();
+    }
+    line.chars().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Cursor;
+    use crate::test_util::TempDir;
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    fn frame(body: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    #[test]
+    fn messages_are_framed_by_content_length() {
+        let body = r#"{"id":1,"result":"héllo"}"#;
+        let stream = format!(
+            "{}content-length: 2\r\nContent-Type: application/vscode-jsonrpc\r\n\r\n{{}}",
+            frame(body),
+        );
+        let mut reader = Cursor::new(stream.into_bytes());
+
+        assert_eq!(read_message(&mut reader).unwrap(), Some(json!({ "id": 1, "result": "héllo" })));
+        assert_eq!(read_message(&mut reader).unwrap(), Some(json!({})));
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+
+        assert!(read_message(&mut Cursor::new(b"Content-Type: x\r\n\r\n{}".to_vec())).is_err());
+        assert!(read_message(&mut Cursor::new(b"Content-Length: 10\r\n\r\n{}".to_vec())).is_err());
+    }
+
+    #[test]
+    fn locations_and_location_links() {
+        let range = json!({ "start": { "line": 3, "character": 7 }, "end": { "line": 3, "character": 9 } });
+        let other = json!({ "start": { "line": 1, "character": 0 }, "end": { "line": 5, "character": 1 } });
+
+        let location = json!({ "uri": "file:///src/a%20b.rs", "range": range });
+        let link = json!({ "targetUri": "file:///src/c.rs", "targetRange": other, "targetSelectionRange": range });
+
+        let found = parse_locations(&location);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].path, Path::new("/src/a b.rs"));
+        assert_eq!((found[0].line, found[0].character), (3, 7));
+
+        let found = parse_locations(&json!([link, { "uri": "http://x", "range": range }]));
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].path, Path::new("/src/c.rs"));
+        assert_eq!((found[0].line, found[0].character), (3, 7));
+
+        assert!(parse_locations(&Value::Null).is_empty());
+    }
+
+    #[test]
+    fn snippets_keep_their_placeholders() {
+        assert_eq!(strip_snippet("format!(${1:fmt})$0"), "format!(fmt)");
+        assert_eq!(strip_snippet("fn ${1:name}(${2:args}) {\n\t$0\n}"), "fn name(args) {\n\t\n}");
+        assert_eq!(strip_snippet("${1:outer ${2:inner}} $10"), "outer inner ");
+        assert_eq!(strip_snippet("cost: \\$5 {}"), "cost: $5 {}");
+        assert_eq!(strip_snippet("plain"), "plain");
+    }
+
+    #[test]
+    fn utf16_columns() {
+        let line = "aé😀b";
+        assert_eq!((0..=4).map(|idx| utf16_col(line, idx)).collect::<Vec<_>>(), [0, 1, 2, 4, 5]);
+        assert_eq!([0, 1, 2, 4, 5].map(|col| char_col(line, col)), [0, 1, 2, 3, 4]);
+        // half a surrogate pair and past the end
+        assert_eq!(char_col(line, 3), 3);
+        assert_eq!(char_col(line, 40), 4);
+    }
+
+    #[test]
+    fn uris_round_trip() {
+        let dir = TempDir::new("lsp-uri");
+        let path = dir.join("a b%é.rs");
+        let uri = path_uri(&path);
+        assert!(uri.starts_with("file:///") && uri.ends_with("/a%20b%25%C3%A9.rs"));
+        assert_eq!(uri_path(&uri).unwrap(), path);
+    }
+
+    // a client whose server is cat, so what it writes comes back to it
+    fn echo_client() -> Client {
+        let config = LspConfig { command: "cat".to_string(), args: Vec::new() };
+        Client::start(&config, Path::new(".")).unwrap()
+    }
+
+    fn written(client: &Client) -> Value {
+        client.messages.recv_timeout(WAIT).unwrap()
+    }
+
+    #[test]
+    fn messages_wait_for_initialize() {
+        let mut client = echo_client();
+        assert_eq!(written(&client)["method"], "initialize");
+
+        client.did_open("file:///a.rs", "rust", "text");
+        client.hover("file:///a.rs", (0, 1));
+        assert!(client.messages.recv_timeout(Duration::from_millis(100)).is_err());
+
+        let mut events = Vec::new();
+        client.handle(json!({ "id": 1, "result": { "capabilities": {
+            "completionProvider": { "triggerCharacters": [".", "::"] },
+        } } }), &mut events);
+        assert!(events.is_empty());
+        assert_eq!(client.trigger_chars, ['.', ':']);
+
+        assert_eq!(written(&client)["method"], "initialized");
+        assert_eq!(written(&client)["method"], "textDocument/didOpen");
+        let hover = written(&client);
+        assert_eq!((&hover["method"], &hover["id"]), (&json!("textDocument/hover"), &json!(2)));
+
+        // and once initialized they go out right away
+        client.did_change("file:///a.rs", "more");
+        let change = written(&client);
+        assert_eq!(change["params"]["textDocument"]["version"], 1);
+    }
+
+    #[test]
+    fn server_messages_are_dispatched() {
+        let mut client = echo_client();
+        written(&client);
+        let mut events = Vec::new();
+        client.handle(json!({ "id": 1, "result": {} }), &mut events);
+        written(&client);
+
+        // requests from the server get an answer
+        client.handle(json!({ "id": "c", "method": "workspace/configuration", "params": { "items": [{}, {}] } }), &mut events);
+        assert_eq!(written(&client), json!({ "jsonrpc": "2.0", "id": "c", "result": [null, null] }));
+        client.handle(json!({ "id": 7, "method": "client/registerCapability", "params": {} }), &mut events);
+        assert_eq!(written(&client)["result"], Value::Null);
+        assert!(events.is_empty());
+
+        client.handle(json!({ "method": "window/showMessage", "params": { "type": 3, "message": "chatter" } }), &mut events);
+        client.handle(json!({ "method": "$/progress", "params": {} }), &mut events);
+        assert!(events.is_empty());
+        client.handle(json!({ "method": "window/showMessage", "params": { "type": 1, "message": "broke\nbadly" } }), &mut events);
+        assert!(matches!(events.pop(), Some(Event::Message(text)) if text == "cat: broke"));
+
+        client.handle(json!({ "method": "textDocument/publishDiagnostics", "params": { "uri": "file:///a.rs", "diagnostics": [
+            { "range": { "start": { "line": 0, "character": 1 }, "end": { "line": 0, "character": 2 } }, "severity": 2, "message": "w" },
+            { "range": { "start": { "line": 1, "character": 0 }, "end": { "line": 1, "character": 4 } }, "message": "e" },
+            { "message": "no range" },
+        ] } }), &mut events);
+        let Some(Event::Diagnostics(uri, diagnostics)) = events.pop() else { panic!("no diagnostics") };
+        assert_eq!(uri, "file:///a.rs");
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!((diagnostics[0].start, diagnostics[0].end, diagnostics[0].severity), ((0, 1), (0, 2), Severity::Warning));
+        assert_eq!(diagnostics[1].severity, Severity::Error);
+
+        // answers go by the id of the request
+        client.hover("file:///a.rs", (0, 0));
+        client.completion("file:///a.rs", (0, 0));
+        client.definition("file:///a.rs", (0, 0));
+        client.handle(json!({ "id": 3, "result": [
+            { "label": "b", "sortText": "2" },
+            { "label": "a", "sortText": "1", "insertText": "a($1)", "insertTextFormat": 2, "detail": "one\ntwo" },
+        ] }), &mut events);
+        let Some(Event::Completion(items)) = events.pop() else { panic!("no completion") };
+        assert_eq!(items.iter().map(|item| item.label.as_str()).collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!((items[0].insert.as_str(), items[0].detail.as_str()), ("a()", "one"));
+
+        client.handle(json!({ "id": 2, "result": { "contents": [
+            { "language": "rust", "value": "fn a()" },
+            "```\ncode\n```\ndocs\t!",
+        ] } }), &mut events);
+        let Some(Event::Hover(lines)) = events.pop() else { panic!("no hover") };
+        assert_eq!(lines, ["fn a()", "", "code", "docs    !"]);
+
+        client.handle(json!({ "id": 4, "error": { "code": -32601, "message": "no definition\nat all" } }), &mut events);
+        assert!(matches!(events.pop(), Some(Event::Message(text)) if text == "cat: no definition"));
+
+        // an answer nobody waits for anymore
+        client.handle(json!({ "id": 4, "result": null }), &mut events);
+        assert!(events.is_empty());
+    }
+
+    // cargo test builds the examples next to the deps folder the test runs
+    // from, but not when it's given a filter
+    fn mock_lsp() -> LspConfig {
+        let exe = std::env::current_exe().unwrap();
+        let command = exe.parent().unwrap().parent().unwrap().join("examples").join("mock_lsp");
+        if !command.exists() {
+            let built = Command::new(env!("CARGO"))
+                .args(["build", "--example", "mock_lsp"])
+                .current_dir(env!("CARGO_MANIFEST_DIR"))
+                .status()
+                .is_ok_and(|status| status.success());
+            assert!(built, "could not build the mock language server");
+        }
+        LspConfig { command: command.to_string_lossy().into_owned(), args: Vec::new() }
+    }
+
+    #[test]
+    fn talks_to_a_server() {
+        let dir = TempDir::new("lsp");
+        let path = dir.join("main.rs");
+        let text = "fn main() { bad(); }\n// é warn\nfn target() {}\n";
+        fs::write(&path, text).unwrap();
+        let uri = path_uri(&path);
+
+        let mut lsp = Lsp::new(dir.path());
+        let client = lsp.start("rust", &mock_lsp()).unwrap();
+        client.did_open(&uri, "rust", text);
+        client.hover(&uri, (0, 3));
+        client.definition(&uri, (0, 12));
+        client.completion(&uri, (0, 0));
+
+        let (mut diagnostics, mut hover, mut definition, mut completion, mut messages) = (None, None, None, None, Vec::new());
+        let start = Instant::now();
+        while start.elapsed() < WAIT && (diagnostics.is_none() || hover.is_none() || definition.is_none() || completion.is_none() || messages.is_empty()) {
+            for event in lsp.poll() {
+                match event {
+                    Event::Diagnostics(from, found) => diagnostics = Some((from, found)),
+                    Event::Hover(lines) => hover = Some(lines),
+                    Event::Definition(locations) => definition = Some(locations),
+                    Event::Completion(items) => completion = Some(items),
+                    Event::Message(text) => messages.push(text),
+                }
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        // nothing reached the server before it was initialized
+        assert_eq!(messages.len(), 1, "{:?}", messages);
+        assert!(messages[0].ends_with("configuration answered with 2 items"));
+        assert_eq!(lsp.client("rust").unwrap().trigger_chars, ['.', ':']);
+
+        let (from, diagnostics) = diagnostics.unwrap();
+        assert_eq!(from, uri);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!((diagnostics[0].start, diagnostics[0].severity), ((0, 12), Severity::Error));
+        assert_eq!(diagnostics[0].message, "bad word here\nsecond line");
+        assert_eq!((diagnostics[1].start, diagnostics[1].severity), ((1, 5), Severity::Warning));
+        assert_eq!(char_col("// é warn", diagnostics[1].start.1), 5);
+
+        assert_eq!(hover.unwrap(), ["fn hello() -> i32", "", "Says hello at 0:3"]);
+
+        let definition = definition.unwrap();
+        assert_eq!(definition.len(), 1);
+        assert_eq!(definition[0].path, path);
+        assert_eq!((definition[0].line, definition[0].character), (2, 3));
+
+        let completion = completion.unwrap();
+        let inserts: Vec<&str> = completion.iter().map(|item| item.insert.as_str()).collect();
+        assert_eq!(inserts, ["format!(fmt)", "hello_world", "help()"]);
+        assert_eq!(completion[1].detail, "fn()");
+    }
+}
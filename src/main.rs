@@ -1,10 +1,39 @@
+mod buffer;
+mod clipboard;
+mod command;
+mod config;
 mod editor;
+mod file_tree;
+mod filetype;
+mod finder;
+mod git;
+mod grep;
+mod history;
+mod keymap;
+mod lsp;
+mod normal;
+mod registers;
+mod search;
+mod substitute;
+mod swap;
+mod syntax;
+#[cfg(test)]
+mod test_util;
+mod text_buffer;
+mod unicode;
+mod window;
 
 use std::env;
 use std::io;
+use std::time::Duration;
 use crossterm::event::{self, Event, KeyCode, KeyEvent};
 use crossterm::terminal;
 use editor::Editor;
+use keymap::{Input, Key};
+
+// how often language servers and swap files are looked after while no key
+// comes in
+const POLL: Duration = Duration::from_millis(50);
 
 fn main() -> io::Result<()> {
     let args: Vec<String> = env::args().collect();
@@ -29,94 +58,113 @@ fn main() -> io::Result<()> {
     loop {
         editor.render();
         editor.draw_cursor();
+
+        // a half typed key sequence runs as it is once the timeout is over
+        if let Some(timeout) = editor.input_timeout() && !event::poll(timeout)? {
+            let inputs = editor.flush_input();
+            if dispatch(&mut editor, inputs) {
+                break;
+            }
+            continue;
+        }
+
+        if !wait_for_key(&mut editor)? {
+            continue;
+        }
+
         if let Event::Key(KeyEvent { code, modifiers, .. }) = event::read()? {
-            match (code, modifiers) {
-                (KeyCode::Esc, _) => {
-                    editor.mode = editor::Mode::Command;
-                    editor.visual_start = None;
-                },
-                (KeyCode::Char('i'), _) if matches!(editor.mode, editor::Mode::Command) && editor.command.is_empty() => {
-                    editor.mode = editor::Mode::Insert;
-                    editor.visual_start = None;
-                },
-                (KeyCode::Char('v'), _) if matches!(editor.mode, editor::Mode::Command) && editor.command.is_empty() => {
-                    editor.mode = editor::Mode::Visual;
-                    editor.visual_start = Some(editor.cursor_l);
-                },
-                (KeyCode::Char('y'), _) if matches!(editor.mode, editor::Mode::Visual) => {
-                    editor.copy_selection();
-                },
-                (KeyCode::Char('p'), _) if matches!(editor.mode, editor::Mode::Command) && editor.command.is_empty() => {
-                    editor.paste_lines();
-                },
-                (KeyCode::Enter, _) if matches!(editor.mode, editor::Mode::Command) => {
-                    if editor.command.starts_with(":e ") {
-                        let path_arg = editor.command[2..].trim().to_string();
-                        editor.open_file_from_command(&path_arg);
-                        editor.refresh_sidebar();
-                    } else if editor.command.starts_with(":w ") {
-                        let path_arg = editor.command[2..].trim().to_string();
-                        editor.save_as(&path_arg)?;
-                        editor.refresh_sidebar();
-                    } else if editor.command == ":w" {
-                        editor.save()?;
-                        editor.refresh_sidebar();
-                        editor.status_message = "File Saved".to_string();
-                    } else if editor.command == ":q" {
-                        break;
-                    } else if editor.command == ":wq" {
-                        editor.save()?;
-                        break;
-                    } else {
-                        editor.status_message = "Unknow command".to_string();
-                    }
-                
-                    editor.command.clear();
-                },
-                (KeyCode::Backspace, _) if matches!(editor.mode, editor::Mode::Command) => {
-                    editor.command.pop();
-                },
-                (KeyCode::Char(c), _) if matches!(editor.mode, editor::Mode::Command) => {
-                    editor.command.push(c);
-                },
-                (KeyCode::Up, _) if matches!(editor.mode, editor::Mode::Command) => {
-                    editor.move_up_files();
-                    editor.adjust_sidebar_scroll();
-                },
-                (KeyCode::Down, _) if matches!(editor.mode, editor::Mode::Command) => {
-                    editor.move_down_files();
-                    editor.adjust_sidebar_scroll();
-                },
-                (KeyCode::Right, _) if matches!(editor.mode, editor::Mode::Command) => {
-                   editor.open_selected(); 
-                },
-                (KeyCode::Left, _) if matches!(editor.mode, editor::Mode::Command) => {
-                   editor.go_back(); 
-                },
-                (KeyCode::Enter, _) => editor.handle_enter(),
-                (KeyCode::Backspace, _) => editor.handle_backspace(),
-                (KeyCode::Delete, _) => editor.handle_delete(),
-                (KeyCode::Left, _) => editor.move_left(),
-                (KeyCode::Right, _) => editor.move_right(),
-                (KeyCode::Up, _) => editor.move_up(),
-                (KeyCode::Down, _) => editor.move_down(),
-                (KeyCode::Char(c), _) if matches!(editor.mode, editor::Mode::Insert) => {
-                    match c {
-                        '(' | '[' | '{' | '"' | '\'' => editor.auto_close(c),
-                        _ => editor.insert_char(c),
-                    }
-                },
-                (KeyCode::Tab, _) => editor.handle_tab(), 
-                (_,_) => {}
+            let key = Key::new(code, modifiers);
+            editor.hover = None;
+
+            if editor.pager.is_some() {
+                editor.pager_key(key);
+                continue;
+            }
+
+            if editor.finder.is_some() {
+                editor.finder_key(key);
+                continue;
+            }
+
+            if editor.quickfix.open {
+                editor.quickfix_key(key);
+                continue;
+            }
+
+            if editor.prompt.is_some() {
+                match code {
+                    KeyCode::Char(c) => editor.prompt_key(c),
+                    KeyCode::Esc => editor.prompt_key('n'),
+                    _ => {}
+                }
+                continue;
+            }
+
+            if editor.pending_substitute.is_some() {
+                match code {
+                    KeyCode::Char(c) => editor.confirm_substitute(c),
+                    KeyCode::Esc => editor.confirm_substitute('q'),
+                    _ => {}
+                }
+                continue;
+            }
+
+            if editor.completion_menu.is_some() && editor.completion_menu_key(key) {
+                continue;
+            }
+
+            let inputs = if editor.in_command_line() {
+                vec![Input::Key(key)]
+            } else {
+                editor.resolve_key(key)
+            };
+            if dispatch(&mut editor, inputs) {
+                break;
             }
         }
     }
 
+    editor.remove_swaps();
     terminal::disable_raw_mode()?;
 
     Ok(())
 }
 
+// language servers answer, swap files get written, changed files are
+// reloaded and git hunks catch up in between keys. false when the screen
+// needs a redraw before any key came in
+fn wait_for_key(editor: &mut Editor) -> io::Result<bool> {
+    loop {
+        if editor.poll_lsp() || editor.check_files() || editor.diffs_due() {
+            return Ok(false);
+        }
+        let swaps_waiting = editor.write_swaps();
+        let busy = editor.lsp.is_running() || swaps_waiting || editor.diffs_stale();
+        let timeout = if busy { POLL } else { editor::FILE_CHECK };
+        if event::poll(timeout)? {
+            return Ok(true);
+        }
+    }
+}
+
+// runs the actions and keys the keymap gave back. true means quit
+fn dispatch(editor: &mut Editor, inputs: Vec<Input>) -> bool {
+    for input in inputs {
+        match input {
+            Input::Action(name) => editor.run_action(name),
+            Input::Key(key) if editor.in_command_line() => {
+                if editor.command_line_key(key) {
+                    return true;
+                }
+            },
+            Input::Key(key) => editor.default_key(key),
+        }
+    }
+    editor.update_completion();
+
+    false
+}
+
 fn command_list() {
     println!("++=====================================================================++");
     println!("||                    ReEdit - Terminal Text Editor                    ||");
@@ -129,13 +177,55 @@ fn command_list() {
     println!("|| Keyboard Commands:                                                  ||");
     println!("||    Esc                       - Enter command mode                   ||");
     println!("||    i                         - Enter insert mode                    ||");
-    println!("||    v                         - Enter visual mode                    ||");
+    println!("||    v | V | Ctrl-v            - Visual mode by char / line / block   ||");
+    println!("||    d c y > < ~ (Visual Mode) - Delete, change, yank, indent, case   ||");
+    println!("||    a A I o O                 - Append / insert / open a line        ||");
+    println!("||    h j k l w b e 0 $         - Move (with a count: 3w, 5j)          ||");
+    println!("||    gg | G | 12G              - First / last / given line            ||");
+    println!("||    f t F T <char> | %        - Find char / matching bracket         ||");
+    println!("||    d c y > < + motion        - Operators (dw, c$, y2j, >>, dd)      ||");
+    println!("||    iw aw i( a( i\" a\" ...     - Text objects (diw, ci(, ya\")         ||");
+    println!("||    x | D | C | ~             - Delete, change to end, toggle case   ||");
+    println!("||    p | P                     - Paste after / before the cursor      ||");
+    println!("||    \"a to \"z, \"A to \"Z        - Use / append to a register (\"ayy)    ||");
+    println!("||    \"0 \"1-\"9 \"/ \"+            - Yank, deletes, search, clipboard     ||");
+    println!("||    .                         - Repeat last change                   ||");
+    println!("||    u                         - Undo last change                     ||");
+    println!("||    Ctrl-r                    - Redo last undone change              ||");
     println!("||    :w                        - Save File                            ||");
+    println!("||    /text | ?text             - Search forward / backward            ||");
+    println!("||    n | N                     - Next / previous match                ||");
+    println!("||    ]c | [c                   - Next / previous changed hunk         ||");
+    println!("||    K | gd                    - Hover info / go to definition        ||");
+    println!("||    Ctrl-Space (Insert Mode)  - Complete (Tab to accept)             ||");
+    println!("||    :noh                      - Clear search highlight               ||");
+    println!("||    :[range]s/re/text/[gci]   - Substitute (range: %, n,m, '<,'>)    ||");
+    println!("||    :set <option>=<value>     - Change a setting                     ||");
+    println!("||    :map | :nmap | :imap      - Bind keys (:imap jk <Esc>)           ||");
+    println!("||    :help keys                - List key bindings                    ||");
+    println!("||    :help                     - List ex commands                     ||");
+    println!("||    Tab | Up/Down (on : line) - Complete / browse history            ||");
     println!("||    :q                        - Quit                                 ||");
+    println!("||    :q!                       - Quit discarding changes              ||");
     println!("||    :wq                       - Save and quit                        ||");
     println!("||    :e <file>                 - Edit new file                        ||");
+    println!("||    :e | :e!                  - Reload file / discard changes        ||");
+    println!("||    :bn | :bp                 - Next / previous buffer               ||");
+    println!("||    :b <n> | :b <name>        - Go to buffer by number or name       ||");
+    println!("||    Ctrl-p | :find [query]    - Fuzzy find a file in the project     ||");
+    println!("||    :grep <regex> [path]      - Search the project into a list       ||");
+    println!("||    :cn | :cp | :copen        - Next / previous hit, show the list   ||");
+    println!("||    :ls                       - List open buffers                    ||");
+    println!("||    :bd                       - Close buffer                         ||");
+    println!("||    :split | :vsplit          - Split window                         ||");
+    println!("||    :close                    - Close window                         ||");
+    println!("||    Ctrl-w w | Ctrl-w hjkl    - Move between windows                 ||");
     println!("||    arrows (Insert Mode)      - Navigate in file                     ||");
-    println!("||    arrows (Command Mode)     - Browse files                         ||");
-    println!("||    arrows (Visual Mode)      - Select lines                         ||");
+    println!("||    arrows (Command Mode)     - Browse / unfold the file tree        ||");
+    println!("||    Alt + a r d c m h         - New, rename, del, copy, move, hidden ||");
+    println!("||    :create :rename :remove   - File ops on the sidebar selection    ||");
+    println!("||    :copy | :move | :cd       - Copy / move it, change the tree root ||");
+    println!("||    Ctrl-b | Alt-. | Alt-,    - Toggle / widen / narrow the sidebar  ||");
+    println!("||    arrows (Visual Mode)      - Extend the selection                 ||");
     println!("++=====================================================================++");
 }
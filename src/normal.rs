@@ -0,0 +1,757 @@
+use unicode_segmentation::UnicodeSegmentation;
+use crate::registers;
+use crate::text_buffer::TextBuffer;
+
+// command mode grammar: [count] ["register] operator [count] (motion | text object),
+// [count] motion or a single key command. positions are (line, grapheme column)
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Motion {
+    Left,
+    Down,
+    Up,
+    Right,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    LineStart,
+    LineEnd,
+    FileStart,
+    FileEnd,
+    FindChar(char),
+    TillChar(char),
+    FindCharBack(char),
+    TillCharBack(char),
+    MatchPair,
+}
+
+impl Motion {
+    // j k gg G work on whole lines when used with an operator
+    pub fn linewise(self) -> bool {
+        matches!(self, Motion::Down | Motion::Up | Motion::FileStart | Motion::FileEnd)
+    }
+
+    // the char under the target is part of the range (de, df, d$, d%)
+    pub fn inclusive(self) -> bool {
+        matches!(self, Motion::WordEnd | Motion::LineEnd | Motion::FindChar(_) | Motion::TillChar(_) | Motion::MatchPair)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TextObject {
+    Word { around: bool },
+    Pair { open: char, close: char, around: bool },
+    Quote { quote: char, around: bool },
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+    Indent,
+    Dedent,
+    ToggleCase,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Target {
+    Motion(Motion),
+    Object(TextObject),
+    // the operator key pressed twice: dd, cc, yy, >>, <<
+    Line,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Command {
+    Move(Motion),
+    Operate(Operator, Target),
+    Insert,
+    Append,
+    InsertLineStart,
+    AppendLineEnd,
+    OpenBelow,
+    OpenAbove,
+    Visual,
+    VisualLine,
+    ToggleCase,
+    Paste,
+    PasteBefore,
+    Undo,
+    Repeat,
+    SearchNext,
+    SearchPrev,
+    NextHunk,
+    PrevHunk,
+    Hover,
+    Definition,
+}
+
+impl Command {
+    // what `.` repeats
+    pub fn is_change(self) -> bool {
+        match self {
+            Command::Operate(op, _) => op != Operator::Yank,
+            Command::Insert
+            | Command::Append
+            | Command::InsertLineStart
+            | Command::AppendLineEnd
+            | Command::OpenBelow
+            | Command::OpenAbove
+            | Command::ToggleCase
+            | Command::Paste
+            | Command::PasteBefore => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Action {
+    pub count: Option<usize>,
+    pub register: Option<char>,
+    pub command: Command,
+}
+
+impl Action {
+    pub fn times(&self) -> usize {
+        self.count.unwrap_or(1)
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Parse {
+    Incomplete,
+    Invalid,
+    Complete(Action),
+}
+
+pub fn parse(keys: &str) -> Parse {
+    let mut chars = keys.chars().peekable();
+
+    let mut count = read_count(&mut chars);
+    let mut register = None;
+
+    if chars.peek() == Some(&'"') {
+        chars.next();
+        match chars.next() {
+            Some(name) if registers::is_register(name) => register = Some(name),
+            Some(_) => return Parse::Invalid,
+            None => return Parse::Incomplete,
+        }
+        count = multiply(count, read_count(&mut chars));
+    }
+
+    let Some(c) = chars.next() else { return Parse::Incomplete };
+
+    let operator = match c {
+        'd' => Some(Operator::Delete),
+        'c' => Some(Operator::Change),
+        'y' => Some(Operator::Yank),
+        '>' => Some(Operator::Indent),
+        '<' => Some(Operator::Dedent),
+        _ => None,
+    };
+
+    let complete = |count, command| Parse::Complete(Action { count, register, command });
+
+    if let Some(op) = operator {
+        let count = multiply(count, read_count(&mut chars));
+
+        let Some(next) = chars.next() else { return Parse::Incomplete };
+
+        if next == c {
+            return complete(count, Command::Operate(op, Target::Line));
+        }
+
+        if next == 'i' || next == 'a' {
+            let Some(kind) = chars.next() else { return Parse::Incomplete };
+            return match text_object(kind, next == 'a') {
+                Some(object) => complete(count, Command::Operate(op, Target::Object(object))),
+                None => Parse::Invalid,
+            };
+        }
+
+        return match parse_motion(next, &mut chars) {
+            Parse::Complete(Action { command: Command::Move(motion), .. }) => {
+                complete(count, Command::Operate(op, Target::Motion(motion)))
+            },
+            other => other,
+        };
+    }
+
+    let command = match c {
+        'i' => Command::Insert,
+        'a' => Command::Append,
+        'I' => Command::InsertLineStart,
+        'A' => Command::AppendLineEnd,
+        'o' => Command::OpenBelow,
+        'O' => Command::OpenAbove,
+        'v' => Command::Visual,
+        'V' => Command::VisualLine,
+        '~' => Command::ToggleCase,
+        'p' => Command::Paste,
+        'P' => Command::PasteBefore,
+        'u' => Command::Undo,
+        '.' => Command::Repeat,
+        'n' => Command::SearchNext,
+        'N' => Command::SearchPrev,
+        'K' => Command::Hover,
+        'g' if chars.peek() == Some(&'d') => {
+            chars.next();
+            Command::Definition
+        },
+        // ]c and [c, the next / previous changed hunk
+        ']' | '[' => match chars.next() {
+            Some('c') if c == ']' => Command::NextHunk,
+            Some('c') => Command::PrevHunk,
+            Some(_) => return Parse::Invalid,
+            None => return Parse::Incomplete,
+        },
+        'x' => Command::Operate(Operator::Delete, Target::Motion(Motion::Right)),
+        'X' => Command::Operate(Operator::Delete, Target::Motion(Motion::Left)),
+        'D' => Command::Operate(Operator::Delete, Target::Motion(Motion::LineEnd)),
+        'C' => Command::Operate(Operator::Change, Target::Motion(Motion::LineEnd)),
+        _ => {
+            return match parse_motion(c, &mut chars) {
+                Parse::Complete(action) => complete(count, action.command),
+                other => other,
+            };
+        },
+    };
+
+    complete(count, command)
+}
+
+// 2d3w deletes six words
+fn multiply(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.saturating_mul(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+fn read_count(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<usize> {
+    let mut count: Option<usize> = None;
+
+    while let Some(&c) = chars.peek() {
+        // a leading 0 is the motion to the start of the line
+        if !c.is_ascii_digit() || (c == '0' && count.is_none()) {
+            break;
+        }
+        count = Some(count.unwrap_or(0).saturating_mul(10).saturating_add(c as usize - '0' as usize));
+        chars.next();
+    }
+
+    count
+}
+
+fn parse_motion(c: char, chars: &mut std::iter::Peekable<std::str::Chars>) -> Parse {
+    let motion = match c {
+        'h' => Motion::Left,
+        'j' => Motion::Down,
+        'k' => Motion::Up,
+        'l' => Motion::Right,
+        'w' => Motion::WordForward,
+        'b' => Motion::WordBackward,
+        'e' => Motion::WordEnd,
+        '0' => Motion::LineStart,
+        '$' => Motion::LineEnd,
+        'G' => Motion::FileEnd,
+        '%' => Motion::MatchPair,
+        'g' => match chars.next() {
+            Some('g') => Motion::FileStart,
+            Some(_) => return Parse::Invalid,
+            None => return Parse::Incomplete,
+        },
+        'f' | 't' | 'F' | 'T' => {
+            let Some(target) = chars.next() else { return Parse::Incomplete };
+            match c {
+                'f' => Motion::FindChar(target),
+                't' => Motion::TillChar(target),
+                'F' => Motion::FindCharBack(target),
+                _ => Motion::TillCharBack(target),
+            }
+        },
+        _ => return Parse::Invalid,
+    };
+
+    Parse::Complete(Action { count: None, register: None, command: Command::Move(motion) })
+}
+
+fn text_object(kind: char, around: bool) -> Option<TextObject> {
+    let pair = |open, close| Some(TextObject::Pair { open, close, around });
+
+    match kind {
+        'w' => Some(TextObject::Word { around }),
+        '(' | ')' | 'b' => pair('(', ')'),
+        '[' | ']' => pair('[', ']'),
+        '{' | '}' | 'B' => pair('{', '}'),
+        '<' | '>' => pair('<', '>'),
+        '"' | '\'' | '`' => Some(TextObject::Quote { quote: kind, around }),
+        _ => None,
+    }
+}
+
+fn graphemes(line: &str) -> Vec<&str> {
+    line.graphemes(true).collect()
+}
+
+// 0 blank, 1 keyword chars, 2 punctuation
+fn class(g: &str) -> u8 {
+    match g.chars().next() {
+        Some(c) if c.is_whitespace() => 0,
+        Some(c) if c.is_alphanumeric() || c == '_' => 1,
+        Some(_) => 2,
+        None => 0,
+    }
+}
+
+pub fn first_non_blank(line: &str) -> usize {
+    graphemes(line).iter().take_while(|g| class(g) == 0).count()
+}
+
+// where the cursor ends after `motion` repeated `count` times. None when the
+// motion cannot move (f without a match, % without a bracket)
+pub fn motion_target(content: &TextBuffer, pos: (usize, usize), motion: Motion, count: Option<usize>) -> Option<(usize, usize)> {
+    let times = count.unwrap_or(1);
+    let last_line = content.line_count() - 1;
+    let (line, col) = pos;
+    let line_len = |l: usize| graphemes(&content.line(l)).len();
+
+    let target = match motion {
+        Motion::Left => (line, col.saturating_sub(times)),
+        Motion::Right => (line, (col + times).min(line_len(line))),
+        Motion::Up => {
+            let l = line.saturating_sub(times);
+            (l, col.min(line_len(l)))
+        },
+        Motion::Down => {
+            let l = (line + times).min(last_line);
+            (l, col.min(line_len(l)))
+        },
+        Motion::LineStart => (line, 0),
+        Motion::LineEnd => {
+            let l = (line + times - 1).min(last_line);
+            (l, line_len(l).saturating_sub(1))
+        },
+        Motion::FileStart | Motion::FileEnd => {
+            let l = match count {
+                Some(n) => n.saturating_sub(1).min(last_line),
+                None if motion == Motion::FileStart => 0,
+                None => last_line,
+            };
+            (l, first_non_blank(&content.line(l)))
+        },
+        Motion::WordForward => (0..times).fold(pos, |p, _| word_forward(content, p)),
+        Motion::WordBackward => (0..times).fold(pos, |p, _| word_backward(content, p)),
+        Motion::WordEnd => (0..times).fold(pos, |p, _| word_end(content, p)),
+        Motion::FindChar(c) | Motion::TillChar(c) | Motion::FindCharBack(c) | Motion::TillCharBack(c) => {
+            let text = content.line(line);
+            let gs = graphemes(&text);
+            let target = c.to_string();
+            let forward = matches!(motion, Motion::FindChar(_) | Motion::TillChar(_));
+            let till = matches!(motion, Motion::TillChar(_) | Motion::TillCharBack(_));
+
+            let mut found = col;
+            for _ in 0..times {
+                let next = if forward {
+                    (found + 1 + till as usize..gs.len()).find(|i| gs[*i] == target)
+                } else {
+                    (0..found.saturating_sub(till as usize)).rev().find(|i| gs[*i] == target)
+                };
+                found = next?;
+            }
+
+            match (forward, till) {
+                (true, true) => (line, found - 1),
+                (false, true) => (line, found + 1),
+                _ => (line, found),
+            }
+        },
+        Motion::MatchPair => match_pair(content, pos)?,
+    };
+
+    Some(target)
+}
+
+// walks one grapheme at a time over the whole file. the end of a line counts
+// as a blank so words never continue on the next line
+fn step(content: &TextBuffer, (line, col): (usize, usize), forward: bool) -> Option<(usize, usize)> {
+    if forward {
+        if col < graphemes(&content.line(line)).len() {
+            Some((line, col + 1))
+        } else if line + 1 < content.line_count() {
+            Some((line + 1, 0))
+        } else {
+            None
+        }
+    } else if col > 0 {
+        Some((line, col - 1))
+    } else if line > 0 {
+        Some((line - 1, graphemes(&content.line(line - 1)).len()))
+    } else {
+        None
+    }
+}
+
+fn class_at(content: &TextBuffer, (line, col): (usize, usize)) -> u8 {
+    graphemes(&content.line(line)).get(col).map_or(0, |g| class(g))
+}
+
+// an empty line is a word of its own for w and b
+fn empty_line(content: &TextBuffer, (line, col): (usize, usize)) -> bool {
+    col == 0 && content.line_len(line) == 0
+}
+
+fn word_forward(content: &TextBuffer, pos: (usize, usize)) -> (usize, usize) {
+    let start = class_at(content, pos);
+    let mut p = pos;
+
+    if start != 0 {
+        while let Some(next) = step(content, p, true) {
+            p = next;
+            if class_at(content, p) != start || p.0 != pos.0 {
+                break;
+            }
+        }
+    }
+
+    while class_at(content, p) == 0 && !(empty_line(content, p) && p != pos) {
+        match step(content, p, true) {
+            Some(next) => p = next,
+            None => break,
+        }
+    }
+
+    p
+}
+
+fn word_backward(content: &TextBuffer, pos: (usize, usize)) -> (usize, usize) {
+    let Some(mut p) = step(content, pos, false) else { return pos };
+
+    while class_at(content, p) == 0 && !empty_line(content, p) {
+        match step(content, p, false) {
+            Some(prev) => p = prev,
+            None => return p,
+        }
+    }
+
+    let class = class_at(content, p);
+    while class != 0
+        && let Some(prev) = step(content, p, false)
+        && prev.0 == p.0
+        && class_at(content, prev) == class
+    {
+        p = prev;
+    }
+
+    p
+}
+
+fn word_end(content: &TextBuffer, pos: (usize, usize)) -> (usize, usize) {
+    let Some(mut p) = step(content, pos, true) else { return pos };
+
+    while class_at(content, p) == 0 {
+        match step(content, p, true) {
+            Some(next) => p = next,
+            None => return pos,
+        }
+    }
+
+    let class = class_at(content, p);
+    while let Some(next) = step(content, p, true) {
+        if next.0 != p.0 || class_at(content, next) != class {
+            break;
+        }
+        p = next;
+    }
+
+    p
+}
+
+const PAIRS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+// % jumps from the first bracket at or after the cursor to its partner
+fn match_pair(content: &TextBuffer, (line, col): (usize, usize)) -> Option<(usize, usize)> {
+    let text = content.line(line);
+    let gs = graphemes(&text);
+
+    let (start, bracket) = (col..gs.len()).find_map(|i| {
+        let c = gs[i].chars().next()?;
+        PAIRS.iter().any(|(o, cl)| *o == c || *cl == c).then_some((i, c))
+    })?;
+
+    let (open, close) = *PAIRS.iter().find(|(o, c)| *o == bracket || *c == bracket)?;
+    let forward = bracket == open;
+    find_unmatched(content, (line, start), open, close, forward)
+}
+
+// the bracket that closes (or opens) the block around `from`, skipping
+// nested pairs on the way. each line is split into graphemes once
+fn find_unmatched(content: &TextBuffer, from: (usize, usize), open: char, close: char, forward: bool) -> Option<(usize, usize)> {
+    let (want, other) = if forward { (close, open) } else { (open, close) };
+    let mut depth = 0;
+    let mut matches = |g: &str| {
+        let c = g.chars().next();
+        if c == Some(other) {
+            depth += 1;
+        } else if c == Some(want) {
+            if depth == 0 {
+                return true;
+            }
+            depth -= 1;
+        }
+        false
+    };
+
+    // only the part past `from` on its own line
+    let (mut line, mut col) = (from.0, Some(from.1));
+    loop {
+        let text = content.line(line);
+        let gs = graphemes(&text);
+        let found = if forward {
+            (col.map_or(0, |c| c + 1)..gs.len()).find(|i| matches(gs[*i]))
+        } else {
+            (0..col.unwrap_or(gs.len()).min(gs.len())).rev().find(|i| matches(gs[*i]))
+        };
+        if let Some(found) = found {
+            return Some((line, found));
+        }
+
+        col = None;
+        line = if forward { line + 1 } else { line.checked_sub(1)? };
+        if line >= content.line_count() {
+            return None;
+        }
+    }
+}
+
+// start and end (exclusive) of a text object around the cursor
+pub fn object_range(content: &TextBuffer, pos: (usize, usize), object: TextObject) -> Option<((usize, usize), (usize, usize))> {
+    let (line, col) = pos;
+    let text = content.line(line);
+    let gs = graphemes(&text);
+
+    match object {
+        TextObject::Word { around } => {
+            if gs.is_empty() {
+                return None;
+            }
+            let col = col.min(gs.len() - 1);
+            let class = class(gs[col]);
+
+            let mut start = col;
+            while start > 0 && self::class(gs[start - 1]) == class {
+                start -= 1;
+            }
+            let mut end = col + 1;
+            while end < gs.len() && self::class(gs[end]) == class {
+                end += 1;
+            }
+
+            if around && class != 0 {
+                let trailing = gs[end..].iter().take_while(|g| self::class(g) == 0).count();
+                if trailing > 0 {
+                    end += trailing;
+                } else {
+                    start -= gs[..start].iter().rev().take_while(|g| self::class(g) == 0).count();
+                }
+            }
+
+            Some(((line, start), (line, end)))
+        },
+        TextObject::Pair { open, close, around } => {
+            let at = gs.get(col).and_then(|g| g.chars().next());
+            let start = if at == Some(open) {
+                pos
+            } else {
+                find_unmatched(content, pos, open, close, false)?
+            };
+            let end = if at == Some(close) {
+                pos
+            } else {
+                find_unmatched(content, start, open, close, true)?
+            };
+
+            if around {
+                Some((start, (end.0, end.1 + 1)))
+            } else {
+                let inner_start = step(content, start, true)?;
+                Some((inner_start.min(end), end))
+            }
+        },
+        TextObject::Quote { quote, around } => {
+            let quote = quote.to_string();
+            let positions: Vec<usize> = (0..gs.len()).filter(|i| gs[*i] == quote).collect();
+
+            // the quoted string the cursor is in or the first one after it
+            let (start, end) = positions.chunks(2)
+                .filter(|pair| pair.len() == 2)
+                .map(|pair| (pair[0], pair[1]))
+                .find(|(_, end)| *end >= col)?;
+
+            if around {
+                Some(((line, start), (line, end + 1)))
+            } else {
+                Some(((line, start + 1), (line, end)))
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action(count: Option<usize>, register: Option<char>, command: Command) -> Parse {
+        Parse::Complete(Action { count, register, command })
+    }
+
+    fn operate(op: Operator, motion: Motion) -> Command {
+        Command::Operate(op, Target::Motion(motion))
+    }
+
+    #[test]
+    fn counts_multiply() {
+        assert_eq!(parse("3w"), action(Some(3), None, Command::Move(Motion::WordForward)));
+        assert_eq!(parse("2d3w"), action(Some(6), None, operate(Operator::Delete, Motion::WordForward)));
+        assert_eq!(parse("d3w"), action(Some(3), None, operate(Operator::Delete, Motion::WordForward)));
+        assert_eq!(parse("10j"), action(Some(10), None, Command::Move(Motion::Down)));
+        assert_eq!(parse("0"), action(None, None, Command::Move(Motion::LineStart)));
+        assert_eq!(parse("5G"), action(Some(5), None, Command::Move(Motion::FileEnd)));
+    }
+
+    #[test]
+    fn registers_go_before_the_operator() {
+        let yank_lines = Command::Operate(Operator::Yank, Target::Line);
+        assert_eq!(parse("\"a2yy"), action(Some(2), Some('a'), yank_lines));
+        assert_eq!(parse("2\"a3yy"), action(Some(6), Some('a'), yank_lines));
+        assert_eq!(parse("\"+p"), action(None, Some('+'), Command::Paste));
+        assert_eq!(parse("\"Ayw"), action(None, Some('A'), operate(Operator::Yank, Motion::WordForward)));
+    }
+
+    #[test]
+    fn operators_take_motions_or_repeat_for_lines() {
+        assert_eq!(parse("dd"), action(None, None, Command::Operate(Operator::Delete, Target::Line)));
+        assert_eq!(parse(">>"), action(None, None, Command::Operate(Operator::Indent, Target::Line)));
+        assert_eq!(parse("<<"), action(None, None, Command::Operate(Operator::Dedent, Target::Line)));
+        assert_eq!(parse("c$"), action(None, None, operate(Operator::Change, Motion::LineEnd)));
+        assert_eq!(parse("dgg"), action(None, None, operate(Operator::Delete, Motion::FileStart)));
+        assert_eq!(parse("dtx"), action(None, None, operate(Operator::Delete, Motion::TillChar('x'))));
+        assert_eq!(parse("yF,"), action(None, None, operate(Operator::Yank, Motion::FindCharBack(','))));
+        assert_eq!(parse("x"), action(None, None, operate(Operator::Delete, Motion::Right)));
+        assert_eq!(parse("D"), action(None, None, operate(Operator::Delete, Motion::LineEnd)));
+    }
+
+    #[test]
+    fn text_objects() {
+        let object = |op, object| action(None, None, Command::Operate(op, Target::Object(object)));
+        assert_eq!(parse("ciw"), object(Operator::Change, TextObject::Word { around: false }));
+        assert_eq!(parse("da("), object(Operator::Delete, TextObject::Pair { open: '(', close: ')', around: true }));
+        assert_eq!(parse("yib"), object(Operator::Yank, TextObject::Pair { open: '(', close: ')', around: false }));
+        assert_eq!(parse("diB"), object(Operator::Delete, TextObject::Pair { open: '{', close: '}', around: false }));
+        assert_eq!(parse("ci\""), object(Operator::Change, TextObject::Quote { quote: '"', around: false }));
+    }
+
+    #[test]
+    fn single_key_commands() {
+        assert_eq!(parse("u"), action(None, None, Command::Undo));
+        assert_eq!(parse("3."), action(Some(3), None, Command::Repeat));
+        assert_eq!(parse("gd"), action(None, None, Command::Definition));
+        assert_eq!(parse("]c"), action(None, None, Command::NextHunk));
+        assert_eq!(parse("2[c"), action(Some(2), None, Command::PrevHunk));
+        assert_eq!(parse("gg"), action(None, None, Command::Move(Motion::FileStart)));
+    }
+
+    #[test]
+    fn incomplete_sequences_wait_for_more() {
+        for keys in ["", "2", "\"", "\"a", "\"a2", "d", "d2", "di", "f", "dt", "g", "]"] {
+            assert_eq!(parse(keys), Parse::Incomplete, "{:?}", keys);
+        }
+    }
+
+    #[test]
+    fn invalid_sequences_are_dropped() {
+        for keys in ["z", "dz", "\"!", "diq", "gq", "]x", "d\"", "2\"%"] {
+            assert_eq!(parse(keys), Parse::Invalid, "{:?}", keys);
+        }
+    }
+
+    const TEXT: &str = "one two\n\nthree (four)";
+
+    fn target(pos: (usize, usize), motion: Motion, count: Option<usize>) -> Option<(usize, usize)> {
+        motion_target(&TextBuffer::from_text(TEXT), pos, motion, count)
+    }
+
+    #[test]
+    fn word_motions_stop_at_empty_lines() {
+        assert_eq!(target((0, 0), Motion::WordForward, None), Some((0, 4)));
+        assert_eq!(target((0, 0), Motion::WordForward, Some(2)), Some((1, 0)));
+        assert_eq!(target((0, 0), Motion::WordForward, Some(3)), Some((2, 0)));
+        assert_eq!(target((2, 0), Motion::WordBackward, None), Some((1, 0)));
+        assert_eq!(target((0, 0), Motion::WordEnd, None), Some((0, 2)));
+    }
+
+    #[test]
+    fn line_and_file_motions() {
+        assert_eq!(target((0, 3), Motion::LineEnd, None), Some((0, 6)));
+        assert_eq!(target((0, 3), Motion::LineStart, None), Some((0, 0)));
+        assert_eq!(target((0, 5), Motion::Down, None), Some((1, 0)));
+        assert_eq!(target((0, 5), Motion::Down, Some(2)), Some((2, 5)));
+        assert_eq!(target((0, 5), Motion::Down, Some(9)), Some((2, 5)));
+        assert_eq!(target((2, 3), Motion::Up, Some(9)), Some((0, 3)));
+        assert_eq!(target((0, 0), Motion::FileEnd, None), Some((2, 0)));
+        assert_eq!(target((2, 4), Motion::FileEnd, Some(2)), Some((1, 0)));
+    }
+
+    #[test]
+    fn find_motions_and_pairs() {
+        assert_eq!(target((2, 0), Motion::FindChar('('), None), Some((2, 6)));
+        assert_eq!(target((2, 0), Motion::TillChar('('), None), Some((2, 5)));
+        assert_eq!(target((2, 10), Motion::FindCharBack('('), None), Some((2, 6)));
+        assert_eq!(target((2, 10), Motion::TillCharBack('('), None), Some((2, 7)));
+        assert_eq!(target((2, 0), Motion::FindChar('z'), None), None);
+        assert_eq!(target((2, 0), Motion::MatchPair, None), Some((2, 11)));
+        assert_eq!(target((2, 11), Motion::MatchPair, None), Some((2, 6)));
+        assert_eq!(target((0, 0), Motion::MatchPair, None), None);
+    }
+
+    fn range(text: &str, pos: (usize, usize), object: TextObject) -> Option<((usize, usize), (usize, usize))> {
+        object_range(&TextBuffer::from_text(text), pos, object)
+    }
+
+    #[test]
+    fn word_objects() {
+        assert_eq!(range("foo bar", (0, 1), TextObject::Word { around: false }), Some(((0, 0), (0, 3))));
+        assert_eq!(range("foo bar", (0, 1), TextObject::Word { around: true }), Some(((0, 0), (0, 4))));
+        assert_eq!(range("foo bar", (0, 5), TextObject::Word { around: true }), Some(((0, 3), (0, 7))));
+        assert_eq!(range("", (0, 0), TextObject::Word { around: false }), None);
+    }
+
+    #[test]
+    fn pair_objects_skip_nested_pairs() {
+        let parens = |around| TextObject::Pair { open: '(', close: ')', around };
+        assert_eq!(range("f(a, (b))", (0, 2), parens(false)), Some(((0, 2), (0, 8))));
+        assert_eq!(range("f(a, (b))", (0, 2), parens(true)), Some(((0, 1), (0, 9))));
+        assert_eq!(range("f(a, (b))", (0, 6), parens(false)), Some(((0, 6), (0, 7))));
+
+        let braces = TextObject::Pair { open: '{', close: '}', around: false };
+        assert_eq!(range("fn f() {\n    x\n}", (1, 4), braces), Some(((0, 8), (2, 0))));
+    }
+
+    #[test]
+    fn pair_objects_outside_any_pair() {
+        let text = "no parens here\n".repeat(1000);
+        assert_eq!(range(&text, (500, 3), TextObject::Pair { open: '(', close: ')', around: false }), None);
+        assert_eq!(range("a ) b", (0, 0), TextObject::Pair { open: '(', close: ')', around: false }), None);
+    }
+
+    #[test]
+    fn quote_objects() {
+        let quote = |around| TextObject::Quote { quote: '"', around };
+        assert_eq!(range("say \"hi\" now", (0, 0), quote(false)), Some(((0, 5), (0, 7))));
+        assert_eq!(range("say \"hi\" now", (0, 6), quote(true)), Some(((0, 4), (0, 8))));
+        assert_eq!(range("say \"hi\" now", (0, 10), quote(false)), None);
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use crate::clipboard::ClipboardProvider;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RegisterKind {
+    Char,
+    Line,
+    Block,
+}
+
+// lines and blocks are kept without a trailing newline
+#[derive(Clone, PartialEq, Debug)]
+pub struct Register {
+    pub text: String,
+    pub kind: RegisterKind,
+}
+
+impl Register {
+    pub fn new(text: String, kind: RegisterKind) -> Self {
+        Register { text, kind }
+    }
+}
+
+// "" the unnamed register, the last text yanked or deleted
+// "a to "z named registers, "A to "Z append to them
+// "0 the last yank, "1 to "9 the last deletes (newest first)
+// "/ the last search pattern, read only
+// "+ and "* the system clipboard
+pub struct Registers {
+    unnamed: Option<Register>,
+    named: HashMap<char, Register>,
+    numbered: [Option<Register>; 10],
+    search: Option<String>,
+    clipboard: Box<dyn ClipboardProvider>,
+}
+
+pub fn is_register(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '"' | '/' | '+' | '*')
+}
+
+impl Registers {
+    pub fn new(clipboard: Box<dyn ClipboardProvider>) -> Self {
+        Registers {
+            unnamed: None,
+            named: HashMap::new(),
+            numbered: Default::default(),
+            search: None,
+            clipboard,
+        }
+    }
+
+    pub fn set_provider(&mut self, clipboard: Box<dyn ClipboardProvider>) {
+        self.clipboard = clipboard;
+    }
+
+    pub fn yank(&mut self, name: Option<char>, register: Register) -> Result<(), String> {
+        match name {
+            None | Some('"') => {
+                self.numbered[0] = Some(register.clone());
+                self.unnamed = Some(register);
+                Ok(())
+            },
+            Some(name) => self.write(name, register),
+        }
+    }
+
+    pub fn delete(&mut self, name: Option<char>, register: Register) -> Result<(), String> {
+        match name {
+            None | Some('"') => {
+                self.numbered[1..].rotate_right(1);
+                self.numbered[1] = Some(register.clone());
+                self.unnamed = Some(register);
+                Ok(())
+            },
+            Some(name) => self.write(name, register),
+        }
+    }
+
+    fn write(&mut self, name: char, register: Register) -> Result<(), String> {
+        match name {
+            'a'..='z' => {
+                self.named.insert(name, register.clone());
+            },
+            'A'..='Z' => {
+                let name = name.to_ascii_lowercase();
+                let register = match self.named.remove(&name) {
+                    Some(old) => append(old, register),
+                    None => register,
+                };
+                self.named.insert(name, register.clone());
+                self.unnamed = Some(register);
+                return Ok(());
+            },
+            '0'..='9' => {
+                self.numbered[name as usize - '0' as usize] = Some(register.clone());
+            },
+            '+' | '*' => {
+                let mut text = register.text.clone();
+                if register.kind == RegisterKind::Line {
+                    text.push('\n');
+                }
+                self.clipboard.set(&text)?;
+            },
+            '/' => return Err("Register / is read only".to_string()),
+            _ => return Err(format!("Invalid register: {}", name)),
+        }
+
+        self.unnamed = Some(register);
+        Ok(())
+    }
+
+    pub fn get(&mut self, name: Option<char>) -> Option<Register> {
+        match name {
+            None | Some('"') => self.unnamed.clone(),
+            Some(c @ 'a'..='z') => self.named.get(&c).cloned(),
+            Some(c @ 'A'..='Z') => self.named.get(&c.to_ascii_lowercase()).cloned(),
+            Some(c @ '0'..='9') => self.numbered[c as usize - '0' as usize].clone(),
+            Some('/') => self.search.clone().map(|text| Register::new(text, RegisterKind::Char)),
+            // text copied from other programs counts as lines when it ends
+            // with a newline
+            Some('+' | '*') => self.clipboard.get().map(|text| match text.strip_suffix('\n') {
+                Some(lines) => Register::new(lines.to_string(), RegisterKind::Line),
+                None => Register::new(text, RegisterKind::Char),
+            }),
+            Some(_) => None,
+        }
+    }
+
+    pub fn set_search(&mut self, pattern: &str) {
+        self.search = Some(pattern.to_string());
+    }
+}
+
+// "Ayy after "ayw gives lines: appending lines to anything makes the
+// result linewise
+fn append(old: Register, new: Register) -> Register {
+    if old.kind == RegisterKind::Char && new.kind == RegisterKind::Char {
+        Register::new(old.text + &new.text, RegisterKind::Char)
+    } else {
+        let kind = if old.kind == RegisterKind::Block && new.kind == RegisterKind::Block {
+            RegisterKind::Block
+        } else {
+            RegisterKind::Line
+        };
+        Register::new(format!("{}\n{}", old.text, new.text), kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard::StubProvider;
+
+    fn registers() -> Registers {
+        Registers::new(Box::<StubProvider>::default())
+    }
+
+    fn chars(text: &str) -> Register {
+        Register::new(text.to_string(), RegisterKind::Char)
+    }
+
+    fn lines(text: &str) -> Register {
+        Register::new(text.to_string(), RegisterKind::Line)
+    }
+
+    #[test]
+    fn uppercase_appends_to_the_named_register() {
+        let mut registers = registers();
+        registers.yank(Some('a'), chars("foo")).unwrap();
+        registers.yank(Some('A'), chars("bar")).unwrap();
+        assert_eq!(registers.get(Some('a')), Some(chars("foobar")));
+        assert_eq!(registers.get(None), Some(chars("foobar")));
+
+        registers.yank(Some('A'), lines("baz")).unwrap();
+        assert_eq!(registers.get(Some('A')), Some(lines("foobar\nbaz")));
+
+        registers.yank(Some('B'), chars("new")).unwrap();
+        assert_eq!(registers.get(Some('b')), Some(chars("new")));
+    }
+
+    #[test]
+    fn deletes_rotate_through_the_numbered_registers() {
+        let mut registers = registers();
+        for i in 1..=10 {
+            registers.delete(None, lines(&i.to_string())).unwrap();
+        }
+
+        assert_eq!(registers.get(Some('1')), Some(lines("10")));
+        assert_eq!(registers.get(Some('2')), Some(lines("9")));
+        assert_eq!(registers.get(Some('9')), Some(lines("2")));
+        assert_eq!(registers.get(None), Some(lines("10")));
+        assert_eq!(registers.get(Some('0')), None);
+    }
+
+    #[test]
+    fn yanks_go_to_register_zero() {
+        let mut registers = registers();
+        registers.yank(None, chars("kept")).unwrap();
+        registers.delete(None, chars("gone")).unwrap();
+
+        assert_eq!(registers.get(Some('0')), Some(chars("kept")));
+        assert_eq!(registers.get(Some('1')), Some(chars("gone")));
+        assert_eq!(registers.get(Some('"')), Some(chars("gone")));
+    }
+
+    #[test]
+    fn the_search_register_is_read_only() {
+        let mut registers = registers();
+        assert_eq!(registers.get(Some('/')), None);
+        registers.set_search("needle");
+        assert_eq!(registers.get(Some('/')), Some(chars("needle")));
+
+        assert!(registers.yank(Some('/'), chars("other")).is_err());
+        assert_eq!(registers.get(Some('/')), Some(chars("needle")));
+        assert!(registers.yank(Some('%'), chars("other")).is_err());
+    }
+
+    #[test]
+    fn the_clipboard_keeps_lines_and_chars_apart() {
+        let mut registers = registers();
+        assert_eq!(registers.get(Some('+')), None);
+
+        registers.yank(Some('+'), lines("one\ntwo")).unwrap();
+        assert_eq!(registers.get(Some('+')), Some(lines("one\ntwo")));
+        assert_eq!(registers.get(Some('*')), Some(lines("one\ntwo")));
+        assert_eq!(registers.get(None), Some(lines("one\ntwo")));
+
+        registers.yank(Some('*'), chars("word")).unwrap();
+        assert_eq!(registers.get(Some('+')), Some(chars("word")));
+    }
+}
@@ -0,0 +1,166 @@
+use crate::text_buffer::TextBuffer;
+use crate::unicode::char_to_grapheme;
+
+pub struct Search {
+    pub pattern: String,
+    pub forward: bool,
+    // every match, and the version of the text they were found in
+    found: Option<(u64, Vec<(usize, usize)>)>,
+}
+
+impl Search {
+    pub fn new(pattern: String, forward: bool) -> Self {
+        Search { pattern, forward, found: None }
+    }
+
+    // every match in the file, found once for each version of the text so
+    // n and N don't read it all again
+    pub fn matches(&mut self, content: &TextBuffer) -> &[(usize, usize)] {
+        let version = content.version();
+        let found = match self.found.take() {
+            Some((found_in, matches)) if found_in == version => matches,
+            _ => find_all(content, &self.pattern),
+        };
+        &self.found.insert((version, found)).1
+    }
+}
+
+// byte ranges of every match of `pattern` in `line`
+pub fn line_matches(line: &str, pattern: &str) -> Vec<(usize, usize)> {
+    if pattern.is_empty() {
+        return Vec::new();
+    }
+
+    line.match_indices(pattern)
+        .map(|(start, m)| (start, start + m.len()))
+        .collect()
+}
+
+// (line, grapheme column) of the matches in line `l`
+fn matches_in_line(content: &TextBuffer, l: usize, pattern: &str) -> Vec<(usize, usize)> {
+    let line = content.line(l);
+    line_matches(&line, pattern)
+        .into_iter()
+        .map(|(start, _)| (l, char_to_grapheme(&line, line[..start].chars().count())))
+        .collect()
+}
+
+// (line, grapheme column) of every match in the file, in order
+pub fn find_all(content: &TextBuffer, pattern: &str) -> Vec<(usize, usize)> {
+    (0..content.line_count()).flat_map(|l| matches_in_line(content, l, pattern)).collect()
+}
+
+// the match after (or before) `from` like next_match, but only reading the
+// lines up to it, for the search as it's typed
+pub fn find_next(content: &TextBuffer, pattern: &str, from: (usize, usize), forward: bool) -> Option<((usize, usize), bool)> {
+    if pattern.is_empty() {
+        return None;
+    }
+    let count = content.line_count();
+
+    // the line of `from` comes up twice, first for what's past `from` and
+    // after wrapping around for the rest
+    for step in 0..=count {
+        let (l, wrapped) = if forward {
+            ((from.0 + step) % count, from.0 + step >= count)
+        } else {
+            ((from.0 + 2 * count - step) % count, step > from.0)
+        };
+
+        let mut matches = matches_in_line(content, l, pattern);
+        if step == 0 {
+            matches.retain(|m| if forward { *m > from } else { *m < from });
+        }
+        if let Some(found) = if forward { matches.first() } else { matches.last() } {
+            return Some((*found, wrapped));
+        }
+    }
+
+    None
+}
+
+// index of the match after (or before) `from`, wrapping around the file.
+// the flag tells if the search wrapped
+pub fn next_match(matches: &[(usize, usize)], from: (usize, usize), forward: bool) -> Option<(usize, bool)> {
+    if matches.is_empty() {
+        return None;
+    }
+
+    if forward {
+        match matches.iter().position(|m| *m > from) {
+            Some(i) => Some((i, false)),
+            None => Some((0, true)),
+        }
+    } else {
+        match matches.iter().rposition(|m| *m < from) {
+            Some(i) => Some((i, false)),
+            None => Some((matches.len() - 1, true)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "ab ab\nxx\n日ab\n\nab";
+
+    #[test]
+    fn matches_in_grapheme_columns() {
+        let content = TextBuffer::from_text(TEXT);
+        assert_eq!(find_all(&content, "ab"), [(0, 0), (0, 3), (2, 1), (4, 0)]);
+        assert_eq!(find_all(&content, "zz"), []);
+        assert_eq!(find_all(&content, ""), []);
+        assert_eq!(line_matches("aaaa", "aa"), [(0, 2), (2, 4)]);
+    }
+
+    #[test]
+    fn next_match_wraps_both_ways() {
+        let matches = [(0, 0), (0, 3), (2, 1), (4, 0)];
+        assert_eq!(next_match(&matches, (0, 0), true), Some((1, false)));
+        assert_eq!(next_match(&matches, (1, 0), true), Some((2, false)));
+        assert_eq!(next_match(&matches, (4, 0), true), Some((0, true)));
+        assert_eq!(next_match(&matches, (9, 9), true), Some((0, true)));
+
+        assert_eq!(next_match(&matches, (2, 1), false), Some((1, false)));
+        assert_eq!(next_match(&matches, (0, 3), false), Some((0, false)));
+        assert_eq!(next_match(&matches, (0, 0), false), Some((3, true)));
+
+        // a single match is found again from itself, wrapping
+        assert_eq!(next_match(&[(1, 1)], (1, 1), true), Some((0, true)));
+        assert_eq!(next_match(&[(1, 1)], (1, 1), false), Some((0, true)));
+        assert_eq!(next_match(&[], (0, 0), true), None);
+    }
+
+    #[test]
+    fn find_next_agrees_with_next_match() {
+        for text in [TEXT, "ab", "x\nab\nx", "abab\nab", "no"] {
+            let content = TextBuffer::from_text(text);
+            let matches = find_all(&content, "ab");
+            for l in 0..content.line_count() {
+                for c in 0..=content.line_len(l) + 1 {
+                    for forward in [true, false] {
+                        let expected = next_match(&matches, (l, c), forward).map(|(i, wrapped)| (matches[i], wrapped));
+                        assert_eq!(find_next(&content, "ab", (l, c), forward), expected, "{:?} from {:?} {}", text, (l, c), forward);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn matches_are_found_again_only_after_an_edit() {
+        let mut content = TextBuffer::from_text(TEXT);
+        let mut search = Search::new("ab".to_string(), true);
+        assert_eq!(search.matches(&content).len(), 4);
+
+        let version = content.version();
+        let undo = content.clone();
+        content.insert(1, 0, "ab");
+        assert_ne!(content.version(), version);
+        assert_eq!(search.matches(&content), [(0, 0), (0, 3), (1, 0), (2, 1), (4, 0)]);
+        assert_eq!(search.matches(&undo).len(), 4);
+        // an edit that gives back the same text still counts as one
+        assert_eq!(search.matches(&TextBuffer::from_text(TEXT)).len(), 4);
+    }
+}
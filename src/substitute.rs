@@ -0,0 +1,295 @@
+use regex::{Captures, Regex, RegexBuilder};
+
+// state of a :s command. it is kept around between key presses while the
+// c flag asks for confirmation of each match
+pub struct Substitution {
+    pub regex: Regex,
+    pub replacement: String,
+    pub global: bool,
+    pub confirm: bool,
+
+    pub line: usize,
+    pub byte: usize,
+    pub end: usize,
+
+    pub count: usize,
+    pub lines: usize,
+    pub last_changed: Option<usize>,
+    pub recorded: bool,
+}
+
+impl Substitution {
+    pub fn expand(&self, caps: &Captures) -> String {
+        let mut text = String::new();
+        caps.expand(&self.replacement, &mut text);
+        text
+    }
+}
+
+// parses "[range]s/pattern/replacement/[flags]" (without the leading ':').
+// lines are 0 based, `visual` is the last visual selection used by '<,'>
+pub fn parse(command: &str, cursor_l: usize, line_count: usize, visual: Option<(usize, usize)>) -> Result<Substitution, String> {
+    let (start, end, rest) = parse_range(command, cursor_l, line_count, visual)?;
+
+    let rest = rest.strip_prefix("substitute")
+        .or_else(|| rest.strip_prefix('s'))
+        .ok_or_else(|| format!("Not a substitute command: {}", command))?;
+
+    let delimiter = rest.chars().next().ok_or("Usage: :s/pattern/replacement/[flags]")?;
+    if delimiter.is_alphanumeric() || delimiter == '\\' || delimiter == ' ' {
+        return Err(format!("Invalid delimiter: {}", delimiter));
+    }
+
+    let parts = split_unescaped(&rest[delimiter.len_utf8()..], delimiter);
+    let pattern = parts.first().cloned().unwrap_or_default();
+    let replacement = parts.get(1).cloned().unwrap_or_default();
+    let flags = parts.get(2).cloned().unwrap_or_default();
+
+    if parts.len() > 3 {
+        return Err(format!("Trailing characters: {}", parts[3..].join(&delimiter.to_string())));
+    }
+    if pattern.is_empty() {
+        return Err("Empty pattern".to_string());
+    }
+
+    let mut global = false;
+    let mut confirm = false;
+    let mut ignore_case = false;
+
+    for flag in flags.chars() {
+        match flag {
+            'g' => global = true,
+            'c' => confirm = true,
+            'i' => ignore_case = true,
+            'I' => ignore_case = false,
+            _ => return Err(format!("Invalid flag: {}", flag)),
+        }
+    }
+
+    let regex = RegexBuilder::new(&pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|_| format!("Invalid pattern: {}", pattern))?;
+
+    Ok(Substitution {
+        regex,
+        replacement: expand_replacement(&replacement),
+        global,
+        confirm,
+        line: start,
+        byte: 0,
+        end,
+        count: 0,
+        lines: 0,
+        last_changed: None,
+        recorded: false,
+    })
+}
+
+// % | addr[,addr] with addr being a line number, '.', '$', '< or '>
+pub fn parse_range(command: &str, cursor_l: usize, line_count: usize, visual: Option<(usize, usize)>) -> Result<(usize, usize, &str), String> {
+    let last = line_count.saturating_sub(1);
+
+    if let Some(rest) = command.strip_prefix('%') {
+        return Ok((0, last, rest));
+    }
+
+    let (first, rest) = parse_address(command, cursor_l, last, visual)?;
+    let Some(first) = first else {
+        return Ok((cursor_l, cursor_l, rest));
+    };
+
+    let (second, rest) = match rest.strip_prefix(',') {
+        Some(rest) => match parse_address(rest, cursor_l, last, visual)? {
+            (Some(second), rest) => (second, rest),
+            (None, _) => return Err(format!("Invalid range: {}", command)),
+        },
+        None => (first, rest),
+    };
+
+    if first > last || second > last {
+        return Err(format!("Invalid range: line {} does not exist", first.max(second) + 1));
+    }
+
+    Ok((first.min(second), first.max(second), rest))
+}
+
+fn parse_address(s: &str, cursor_l: usize, last: usize, visual: Option<(usize, usize)>) -> Result<(Option<usize>, &str), String> {
+    if let Some(rest) = s.strip_prefix('.') {
+        return Ok((Some(cursor_l), rest));
+    }
+    if let Some(rest) = s.strip_prefix('$') {
+        return Ok((Some(last), rest));
+    }
+    if let Some(rest) = s.strip_prefix("'<").or_else(|| s.strip_prefix("'>")) {
+        let (start, end) = visual.ok_or("No visual selection")?;
+        let line = if s.starts_with("'<") { start } else { end };
+        return Ok((Some(line), rest));
+    }
+
+    let digits = s.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits == 0 {
+        return Ok((None, s));
+    }
+
+    let number: usize = s[..digits].parse().map_err(|_| format!("Invalid range: {}", &s[..digits]))?;
+    if number == 0 {
+        return Err("Invalid range: line 0 does not exist".to_string());
+    }
+    Ok((Some(number - 1), &s[digits..]))
+}
+
+// splits on the delimiter, "\/" gives a literal delimiter and every other
+// escape is kept for the regex
+fn split_unescaped(s: &str, delimiter: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some(next) if next == delimiter => parts.last_mut().unwrap().push(next),
+                Some(next) => {
+                    parts.last_mut().unwrap().push('\\');
+                    parts.last_mut().unwrap().push(next);
+                },
+                None => parts.last_mut().unwrap().push('\\'),
+            }
+        } else if c == delimiter {
+            parts.push(String::new());
+        } else {
+            parts.last_mut().unwrap().push(c);
+        }
+    }
+
+    parts
+}
+
+// turns vim replacement syntax (\1, &, \n) into the regex crate one (${1})
+fn expand_replacement(replacement: &str) -> String {
+    let mut out = String::new();
+    let mut chars = replacement.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(d) if d.is_ascii_digit() => out.push_str(&format!("${{{}}}", d)),
+                Some('n') | Some('r') => out.push('\n'),
+                Some('t') => out.push('\t'),
+                Some('$') => out.push_str("$$"),
+                Some(other) => out.push(other),
+                None => out.push('\\'),
+            },
+            '&' => out.push_str("${0}"),
+            '$' => out.push_str("$$"),
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+pub fn is_substitute(command: &str) -> bool {
+    let rest = command.trim_start_matches(|c: char| c.is_ascii_digit() || ",.$%'<>".contains(c));
+    let rest = rest.strip_prefix("substitute").or_else(|| rest.strip_prefix('s'));
+
+    matches!(rest.and_then(|r| r.chars().next()), Some(c) if !c.is_alphanumeric() && c != ' ')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // cursor on the third of ten lines, the last visual selection on lines 5 to 7
+    fn sub(command: &str) -> Substitution {
+        parse(command, 2, 10, Some((4, 6))).unwrap_or_else(|err| panic!("{}: {}", command, err))
+    }
+
+    fn error(command: &str) -> String {
+        parse(command, 2, 10, Some((4, 6))).err().unwrap_or_else(|| panic!("{} parsed", command))
+    }
+
+    fn replace(command: &str, text: &str) -> String {
+        let substitution = sub(command);
+        let caps = substitution.regex.captures(text).unwrap();
+        substitution.expand(&caps)
+    }
+
+    #[test]
+    fn ranges() {
+        let range = |command| parse_range(command, 2, 10, Some((4, 6))).map(|(start, end, rest)| (start, end, rest.to_string()));
+        assert_eq!(range("s/a/b/"), Ok((2, 2, "s/a/b/".to_string())));
+        assert_eq!(range("%s"), Ok((0, 9, "s".to_string())));
+        assert_eq!(range("3,5s"), Ok((2, 4, "s".to_string())));
+        assert_eq!(range("5,3s"), Ok((2, 4, "s".to_string())));
+        assert_eq!(range(".,$s"), Ok((2, 9, "s".to_string())));
+        assert_eq!(range("7s"), Ok((6, 6, "s".to_string())));
+        assert_eq!(range("'<,'>s"), Ok((4, 6, "s".to_string())));
+        assert_eq!(range("'>,.s"), Ok((2, 6, "s".to_string())));
+
+        assert_eq!(range("0s"), Err("Invalid range: line 0 does not exist".to_string()));
+        assert_eq!(range("3,11s"), Err("Invalid range: line 11 does not exist".to_string()));
+        assert_eq!(range("3,s"), Err("Invalid range: 3,s".to_string()));
+        assert_eq!(parse_range("'<,'>s", 2, 10, None).err(), Some("No visual selection".to_string()));
+        // an empty buffer still has its one line
+        assert_eq!(parse_range("%s", 0, 0, None), Ok((0, 0, "s")));
+    }
+
+    #[test]
+    fn escaped_delimiters() {
+        assert_eq!(split_unescaped("a\\/b/c/g", '/'), ["a/b", "c", "g"]);
+        assert_eq!(split_unescaped("a\\.b/\\1", '/'), ["a\\.b", "\\1"]);
+        assert_eq!(split_unescaped("a/b#c#", '#'), ["a/b", "c", ""]);
+        assert_eq!(split_unescaped("end\\", '/'), ["end\\"]);
+        assert_eq!(sub("s#/usr#/opt#").regex.as_str(), "/usr");
+        assert_eq!(sub("s/a\\/b/x/").regex.as_str(), "a/b");
+    }
+
+    #[test]
+    fn replacements_become_regex_syntax() {
+        assert_eq!(expand_replacement("\\1-&"), "${1}-${0}");
+        assert_eq!(expand_replacement("$5 and \\$"), "$$5 and $$");
+        assert_eq!(expand_replacement("\\n\\r\\t"), "\n\n\t");
+        assert_eq!(expand_replacement("\\&\\\\"), "&\\");
+        assert_eq!(expand_replacement("end\\"), "end\\");
+
+        assert_eq!(replace("s/(\\w+) (\\w+)/\\2 \\1/", "hello world"), "world hello");
+        assert_eq!(replace("s/o/[&]/", "foo"), "[o]");
+        assert_eq!(replace("s/o/$1 \\&/", "foo"), "$1 &");
+        assert_eq!(replace("s/(x)?o/<\\1>/", "foo"), "<>");
+    }
+
+    #[test]
+    fn flags() {
+        let plain = sub("s/a/b/");
+        assert!(!plain.global && !plain.confirm);
+        let both = sub("s/a/b/gc");
+        assert!(both.global && both.confirm);
+
+        assert!(sub("s/A/b/i").regex.is_match("a"));
+        assert!(!sub("s/A/b/iI").regex.is_match("a"));
+        assert_eq!(error("s/a/b/gx"), "Invalid flag: x");
+    }
+
+    #[test]
+    fn bad_commands() {
+        assert_eq!(error("s"), "Usage: :s/pattern/replacement/[flags]");
+        assert_eq!(error("sxaxbx"), "Invalid delimiter: x");
+        assert_eq!(error("s a b"), "Invalid delimiter:  ");
+        assert_eq!(error("s//b/"), "Empty pattern");
+        assert_eq!(error("s/a/b/g/x/y"), "Trailing characters: x/y");
+        assert_eq!(error("s/(/b/"), "Invalid pattern: (");
+        assert_eq!(error("3d"), "Not a substitute command: 3d");
+        assert_eq!(error("0s/a/b/"), "Invalid range: line 0 does not exist");
+    }
+
+    #[test]
+    fn substitutes_are_told_from_other_commands() {
+        for command in ["s/a/b/", "%s#a#b#", "substitute/a/b/", "'<,'>s/x/y/", "1,$s|a|b|"] {
+            assert!(is_substitute(command), "{}", command);
+        }
+        for command in ["set", "sp", "s", "split", "substitute", "wq", "10"] {
+            assert!(!is_substitute(command), "{}", command);
+        }
+    }
+}
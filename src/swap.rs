@@ -0,0 +1,145 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{self, Path, PathBuf};
+use std::process;
+use std::time::SystemTime;
+use crate::command;
+
+const HEADER: &str = "reedit swap";
+
+// the unsaved text of a buffer, written now and then while it has changes so
+// a crash doesn't lose them. it names the editor that wrote it, a swap whose
+// editor is gone was left behind by a crash
+pub struct Swap {
+    pub pid: u32,
+    pub cursor: (usize, usize),
+    pub text: String,
+    pub written: Option<SystemTime>,
+}
+
+impl Swap {
+    pub fn read(swap_path: &Path) -> Option<Self> {
+        let data = fs::read_to_string(swap_path).ok()?;
+        let (header, text) = data.split_once("\n\n")?;
+        let mut lines = header.lines();
+        if lines.next()? != HEADER {
+            return None;
+        }
+
+        let mut swap = Swap {
+            pid: 0,
+            cursor: (0, 0),
+            text: text.to_string(),
+            written: fs::metadata(swap_path).and_then(|m| m.modified()).ok(),
+        };
+        for line in lines {
+            match line.split_once(' ') {
+                Some(("pid", pid)) => swap.pid = pid.parse().ok()?,
+                Some(("cursor", cursor)) => {
+                    let (l, c) = cursor.split_once(' ')?;
+                    swap.cursor = (l.parse().ok()?, c.parse().ok()?);
+                },
+                _ => {},
+            }
+        }
+        Some(swap)
+    }
+
+    // the editor that wrote it still runs. a crashed one's pid can be taken
+    // by something else, which only means asking about a swap too little
+    pub fn in_use(&self, alive: fn(u32) -> bool) -> bool {
+        self.pid != process::id() && alive(self.pid)
+    }
+
+    // "5 min ago" and the like
+    pub fn age(&self) -> String {
+        let secs = self.written.and_then(|time| time.elapsed().ok()).map_or(0, |age| age.as_secs());
+        match secs {
+            0..60 => "just now".to_string(),
+            60..3600 => format!("{} min ago", secs / 60),
+            3600..86400 => format!("{} h ago", secs / 3600),
+            _ => format!("{} days ago", secs / 86400),
+        }
+    }
+}
+
+// signal 0 only checks the process is there. a live process of another
+// user refuses it with EPERM, only ESRCH means it's gone
+pub fn is_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else { return false };
+    if pid == 0 {
+        return false;
+    }
+    let sent = unsafe { libc::kill(pid, 0) } == 0;
+    sent || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+// ~/.local/state/reedit/swap/%home%me%notes.txt.swp for /home/me/notes.txt
+pub fn path(file_path: &str) -> Option<PathBuf> {
+    let file = Path::new(file_path);
+    let file = file.canonicalize().or_else(|_| path::absolute(file)).ok()?;
+    let name = file.to_string_lossy().replace(path::MAIN_SEPARATOR, "%");
+
+    Some(command::state_dir()?.join("swap").join(format!("{}.swp", name)))
+}
+
+// goes through a temp file so a crash while writing keeps the last swap
+pub fn write(swap_path: &Path, cursor: (usize, usize), text: &str) -> io::Result<()> {
+    if let Some(dir) = swap_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    // only the user can read it, whatever the file it keeps the text of
+    let temp = swap_path.with_extension("swp.tmp");
+    let _ = fs::remove_file(&temp);
+    let mut file = OpenOptions::new().write(true).create_new(true).mode(0o600).open(&temp)?;
+    write!(file, "{}\npid {}\ncursor {} {}\n\n{}", HEADER, process::id(), cursor.0, cursor.1, text)?;
+    fs::rename(&temp, swap_path)
+}
+
+// only a swap this editor wrote, one left by a crash stays until it's
+// recovered or deleted
+pub fn remove(swap_path: &Path) {
+    if Swap::read(swap_path).is_some_and(|swap| swap.pid == process::id()) {
+        let _ = fs::remove_file(swap_path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::process::Command;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn processes_of_other_users_are_alive() {
+        assert!(is_alive(process::id()));
+        // init belongs to root, signalling it fails with EPERM for anyone else
+        assert!(is_alive(1));
+
+        let mut child = Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        assert!(!is_alive(pid));
+        assert!(!is_alive(0));
+    }
+
+    #[test]
+    fn only_the_user_can_read_a_swap() {
+        let dir = TempDir::new("swap-mode");
+        let swap_path = dir.join("secret.swp");
+        // one left by an older version, and a temp file a crash left behind
+        fs::write(&swap_path, "old").unwrap();
+        fs::set_permissions(&swap_path, fs::Permissions::from_mode(0o644)).unwrap();
+        fs::write(swap_path.with_extension("swp.tmp"), "half").unwrap();
+
+        write(&swap_path, (0, 2), "password").unwrap();
+        assert_eq!(fs::metadata(&swap_path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert!(!swap_path.with_extension("swp.tmp").exists());
+
+        let swap = Swap::read(&swap_path).unwrap();
+        assert_eq!((swap.pid, swap.cursor, swap.text.as_str()), (process::id(), (0, 2), "password"));
+    }
+}
@@ -0,0 +1,673 @@
+use crossterm::style::Color;
+use crate::filetype;
+use crate::text_buffer::TextBuffer;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Token {
+    Normal,
+    Keyword,
+    Type,
+    Literal,
+    String,
+    Comment,
+    Function,
+    Macro,
+    Key,
+    Heading,
+}
+
+pub fn token_color(token: Token) -> Color {
+    match token {
+        Token::Normal => Color::White,
+        Token::Keyword => Color::Magenta,
+        Token::Type => Color::Yellow,
+        Token::Literal => Color::Cyan,
+        Token::String => Color::Green,
+        Token::Comment => Color::DarkGrey,
+        Token::Function => Color::Blue,
+        Token::Macro => Color::DarkCyan,
+        Token::Key => Color::Blue,
+        Token::Heading => Color::Red,
+    }
+}
+
+// byte range of a line painted with one token
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub token: Token,
+}
+
+// what is still open at the end of a line: block comments (with their
+// nesting depth), strings that go on to the next line and markdown fences
+#[derive(Clone, Copy, PartialEq)]
+pub enum State {
+    Normal,
+    Comment(u8),
+    Str(char),
+    TripleStr(char),
+    RawStr(u8),
+    Fence,
+}
+
+#[derive(PartialEq)]
+enum Kind {
+    Code,
+    Json,
+    Toml,
+    Markdown,
+}
+
+pub struct Syntax {
+    kind: Kind,
+    keywords: &'static [&'static str],
+    types: &'static [&'static str],
+    literals: &'static [&'static str],
+    line_comment: Option<&'static str>,
+    block_comment: Option<(&'static str, &'static str)>,
+    nested_comments: bool,
+    quotes: &'static [char],
+    triple_quotes: bool,
+    raw_strings: bool,
+    char_literals: bool,
+    multiline_strings: bool,
+    preprocessor: bool,
+    decorators: bool,
+    capitalized_types: bool,
+}
+
+const CODE: Syntax = Syntax {
+    kind: Kind::Code,
+    keywords: &[],
+    types: &[],
+    literals: &[],
+    line_comment: None,
+    block_comment: None,
+    nested_comments: false,
+    quotes: &['"'],
+    triple_quotes: false,
+    raw_strings: false,
+    char_literals: false,
+    multiline_strings: false,
+    preprocessor: false,
+    decorators: false,
+    capitalized_types: false,
+};
+
+static RUST: Syntax = Syntax {
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+        "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "type",
+        "unsafe", "use", "where", "while",
+    ],
+    types: &[
+        "bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32",
+        "i64", "i128", "isize", "f32", "f64",
+    ],
+    literals: &["true", "false"],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    nested_comments: true,
+    raw_strings: true,
+    char_literals: true,
+    multiline_strings: true,
+    capitalized_types: true,
+    ..CODE
+};
+
+static PYTHON: Syntax = Syntax {
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+        "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in",
+        "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+        "with", "yield", "self",
+    ],
+    types: &["int", "float", "str", "bool", "list", "dict", "set", "tuple", "bytes", "object"],
+    literals: &["True", "False", "None"],
+    line_comment: Some("#"),
+    quotes: &['"', '\''],
+    triple_quotes: true,
+    decorators: true,
+    capitalized_types: true,
+    ..CODE
+};
+
+static C: Syntax = Syntax {
+    keywords: &[
+        "auto", "break", "case", "class", "const", "continue", "default", "delete", "do",
+        "else", "enum", "extern", "for", "goto", "if", "inline", "namespace", "new", "private",
+        "protected", "public", "register", "return", "sizeof", "static", "struct", "switch",
+        "template", "this", "typedef", "union", "using", "virtual", "volatile", "while",
+    ],
+    types: &[
+        "bool", "char", "double", "float", "int", "long", "short", "signed", "unsigned", "void",
+        "size_t", "int8_t", "int16_t", "int32_t", "int64_t", "uint8_t", "uint16_t", "uint32_t",
+        "uint64_t",
+    ],
+    literals: &["true", "false", "NULL", "nullptr"],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+    preprocessor: true,
+    ..CODE
+};
+
+static GO: Syntax = Syntax {
+    keywords: &[
+        "break", "case", "chan", "const", "continue", "default", "defer", "else", "fallthrough",
+        "for", "func", "go", "goto", "if", "import", "interface", "map", "package", "range",
+        "return", "select", "struct", "switch", "type", "var",
+    ],
+    types: &[
+        "bool", "byte", "error", "float32", "float64", "int", "int8", "int16", "int32", "int64",
+        "rune", "string", "uint", "uint8", "uint16", "uint32", "uint64", "uintptr",
+    ],
+    literals: &["true", "false", "nil", "iota"],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\'', '`'],
+    ..CODE
+};
+
+static JAVASCRIPT: Syntax = Syntax {
+    keywords: &[
+        "async", "await", "break", "case", "catch", "class", "const", "continue", "default",
+        "delete", "do", "else", "export", "extends", "finally", "for", "from", "function", "if",
+        "import", "in", "instanceof", "interface", "let", "new", "of", "return", "static",
+        "switch", "this", "throw", "try", "type", "typeof", "var", "void", "while", "yield",
+    ],
+    types: &["string", "number", "boolean", "any", "unknown", "never", "object"],
+    literals: &["true", "false", "null", "undefined", "NaN"],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\'', '`'],
+    capitalized_types: true,
+    ..CODE
+};
+
+static JAVA: Syntax = Syntax {
+    keywords: &[
+        "abstract", "break", "case", "catch", "class", "continue", "default", "do", "else",
+        "enum", "extends", "final", "finally", "for", "if", "implements", "import", "instanceof",
+        "interface", "new", "package", "private", "protected", "public", "return", "static",
+        "super", "switch", "synchronized", "this", "throw", "throws", "try", "var", "while",
+    ],
+    types: &["boolean", "byte", "char", "double", "float", "int", "long", "short", "void"],
+    literals: &["true", "false", "null"],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+    decorators: true,
+    capitalized_types: true,
+    ..CODE
+};
+
+static JSON: Syntax = Syntax {
+    kind: Kind::Json,
+    literals: &["true", "false", "null"],
+    ..CODE
+};
+
+static TOML: Syntax = Syntax {
+    kind: Kind::Toml,
+    literals: &["true", "false"],
+    line_comment: Some("#"),
+    quotes: &['"', '\''],
+    triple_quotes: true,
+    ..CODE
+};
+
+static MARKDOWN: Syntax = Syntax {
+    kind: Kind::Markdown,
+    ..CODE
+};
+
+// language registry, keyed by the same file types as the icons
+pub fn syntax_for(path: &str) -> Option<&'static Syntax> {
+    match filetype::detect(path) {
+        "rust" => Some(&RUST),
+        "python" => Some(&PYTHON),
+        "c" | "cpp" | "header" => Some(&C),
+        "go" => Some(&GO),
+        "javascript" | "typescript" => Some(&JAVASCRIPT),
+        "java" => Some(&JAVA),
+        "json" => Some(&JSON),
+        "toml" => Some(&TOML),
+        "markdown" => Some(&MARKDOWN),
+        _ => None,
+    }
+}
+
+// keeps the state at the start of each line so only the lines after an edit
+// have to be tokenized again
+pub struct Highlighter {
+    pub syntax: Option<&'static Syntax>,
+    states: Vec<State>,
+}
+
+impl Highlighter {
+    pub fn new(path: &str) -> Self {
+        Highlighter {
+            syntax: syntax_for(path),
+            states: vec![State::Normal],
+        }
+    }
+
+    pub fn invalidate(&mut self, line: usize) {
+        self.states.truncate(line + 1);
+    }
+
+    // fills the cache up to `line`, so render can ask for any visible line
+    pub fn prepare(&mut self, content: &TextBuffer, line: usize) {
+        let Some(syntax) = self.syntax else { return };
+        let line = line.min(content.line_count());
+
+        while self.states.len() <= line {
+            let i = self.states.len() - 1;
+            let (_, state) = highlight_line(syntax, &content.line(i), self.states[i]);
+            self.states.push(state);
+        }
+    }
+
+    pub fn spans(&self, line: usize, text: &str) -> Vec<Span> {
+        match (self.syntax, self.states.get(line)) {
+            (Some(syntax), Some(state)) => highlight_line(syntax, text, *state).0,
+            _ => Vec::new(),
+        }
+    }
+}
+
+pub fn highlight_line(syntax: &Syntax, line: &str, state: State) -> (Vec<Span>, State) {
+    if syntax.kind == Kind::Markdown {
+        return highlight_markdown(line, state);
+    }
+
+    let mut spans = Vec::new();
+    let mut state = state;
+    let mut i = 0;
+
+    while i < line.len() {
+        let rest = &line[i..];
+
+        match state {
+            State::Comment(depth) => {
+                let (end, depth) = comment_end(syntax, rest, depth);
+                spans.push(Span { start: i, end: i + end, token: Token::Comment });
+                state = if depth == 0 { State::Normal } else { State::Comment(depth) };
+                i += end;
+                continue;
+            },
+            State::Str(quote) => {
+                let (end, closed) = string_end(rest, quote);
+                spans.push(Span { start: i, end: i + end, token: Token::String });
+                state = if closed { State::Normal } else { State::Str(quote) };
+                i += end;
+                continue;
+            },
+            State::TripleStr(quote) => {
+                let close: String = std::iter::repeat_n(quote, 3).collect();
+                let (end, closed) = match rest.find(&close) {
+                    Some(pos) => (pos + 3, true),
+                    None => (rest.len(), false),
+                };
+                spans.push(Span { start: i, end: i + end, token: Token::String });
+                state = if closed { State::Normal } else { State::TripleStr(quote) };
+                i += end;
+                continue;
+            },
+            State::RawStr(hashes) => {
+                let close = format!("\"{}", "#".repeat(hashes as usize));
+                let (end, closed) = match rest.find(&close) {
+                    Some(pos) => (pos + close.len(), true),
+                    None => (rest.len(), false),
+                };
+                spans.push(Span { start: i, end: i + end, token: Token::String });
+                state = if closed { State::Normal } else { State::RawStr(hashes) };
+                i += end;
+                continue;
+            },
+            State::Fence | State::Normal => {},
+        }
+
+        let c = rest.chars().next().unwrap();
+        let prev_is_ident = i > 0 && is_ident(line[..i].chars().next_back().unwrap());
+
+        if let Some(comment) = syntax.line_comment && rest.starts_with(comment) {
+            spans.push(Span { start: i, end: line.len(), token: Token::Comment });
+            break;
+        }
+
+        if let Some((open, _)) = syntax.block_comment && rest.starts_with(open) {
+            state = State::Comment(1);
+            spans.push(Span { start: i, end: i + open.len(), token: Token::Comment });
+            i += open.len();
+            continue;
+        }
+
+        if syntax.preprocessor && c == '#' && line[..i].trim().is_empty() {
+            spans.push(Span { start: i, end: line.len(), token: Token::Macro });
+            break;
+        }
+
+        if syntax.decorators && c == '@' {
+            let end = i + 1 + ident_len(&rest[1..]);
+            spans.push(Span { start: i, end, token: Token::Macro });
+            i = end;
+            continue;
+        }
+
+        if syntax.raw_strings && !prev_is_ident && let Some((len, hashes)) = raw_string_start(rest) {
+            state = State::RawStr(hashes);
+            spans.push(Span { start: i, end: i + len, token: Token::String });
+            i += len;
+            continue;
+        }
+
+        if syntax.triple_quotes && (rest.starts_with("\"\"\"") || rest.starts_with("'''")) {
+            state = State::TripleStr(c);
+            spans.push(Span { start: i, end: i + 3, token: Token::String });
+            i += 3;
+            continue;
+        }
+
+        if syntax.char_literals && c == '\'' {
+            if let Some(len) = char_literal_len(rest) {
+                spans.push(Span { start: i, end: i + len, token: Token::String });
+                i += len;
+            } else {
+                // a lifetime or a label
+                let end = i + 1 + ident_len(&rest[1..]);
+                spans.push(Span { start: i, end, token: Token::Type });
+                i = end;
+            }
+            continue;
+        }
+
+        if syntax.quotes.contains(&c) {
+            let (end, closed) = string_end(&rest[1..], c);
+            let end = i + 1 + end;
+            let after = line[end..].trim_start();
+            let is_key = matches!(syntax.kind, Kind::Json | Kind::Toml)
+                && (after.starts_with(':') || (syntax.kind == Kind::Toml && after.starts_with('=')));
+
+            spans.push(Span { start: i, end, token: if is_key { Token::Key } else { Token::String } });
+            if !closed && syntax.multiline_strings {
+                state = State::Str(c);
+            }
+            i = end;
+            continue;
+        }
+
+        if syntax.kind == Kind::Toml && c == '[' && line[..i].trim().is_empty() {
+            spans.push(Span { start: i, end: line.len(), token: Token::Heading });
+            break;
+        }
+
+        if c.is_ascii_digit() && !prev_is_ident {
+            let len = number_len(rest, syntax.kind == Kind::Toml);
+            spans.push(Span { start: i, end: i + len, token: Token::Literal });
+            i += len;
+            continue;
+        }
+
+        if is_ident(c) {
+            let len = ident_len(rest);
+            let word = &rest[..len];
+            let next = line[i + len..].trim_start();
+
+            let token = if syntax.keywords.contains(&word) {
+                Token::Keyword
+            } else if syntax.literals.contains(&word) {
+                Token::Literal
+            } else if syntax.types.contains(&word) {
+                Token::Type
+            } else if syntax.kind == Kind::Toml && (next.starts_with('=') || next.starts_with('.')) {
+                Token::Key
+            } else if syntax.raw_strings && next.starts_with('!') && !next.starts_with("!=") {
+                Token::Macro
+            } else if next.starts_with('(') {
+                Token::Function
+            } else if syntax.capitalized_types && word.starts_with(|ch: char| ch.is_uppercase()) {
+                Token::Type
+            } else {
+                Token::Normal
+            };
+
+            if token != Token::Normal {
+                // the ! may come after whitespace of any width
+                let end = if token == Token::Macro { line.len() - next.len() + 1 } else { i + len };
+                spans.push(Span { start: i, end, token });
+            }
+            i += len;
+            continue;
+        }
+
+        i += c.len_utf8();
+    }
+
+    // only rust strings and block comments go on after the end of the line
+    if let State::Str(_) = state && !syntax.multiline_strings {
+        state = State::Normal;
+    }
+
+    (spans, state)
+}
+
+fn highlight_markdown(line: &str, state: State) -> (Vec<Span>, State) {
+    let trimmed = line.trim_start();
+    let indent = line.len() - trimmed.len();
+    let whole = |token| vec![Span { start: 0, end: line.len(), token }];
+
+    if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+        let next = if state == State::Fence { State::Normal } else { State::Fence };
+        return (whole(Token::Macro), next);
+    }
+
+    if state == State::Fence {
+        return (whole(Token::String), State::Fence);
+    }
+
+    if trimmed.starts_with('#') {
+        return (whole(Token::Heading), State::Normal);
+    }
+
+    if trimmed.starts_with('>') {
+        return (whole(Token::Comment), State::Normal);
+    }
+
+    let mut spans = Vec::new();
+    let mut i = indent;
+
+    let marker = if trimmed.starts_with("- ") || trimmed.starts_with("* ") || trimmed.starts_with("+ ") {
+        1
+    } else {
+        let digits = trimmed.chars().take_while(|c| c.is_ascii_digit()).count();
+        if digits > 0 && trimmed[digits..].starts_with(". ") { digits + 1 } else { 0 }
+    };
+    if marker > 0 {
+        spans.push(Span { start: i, end: i + marker, token: Token::Keyword });
+        i += marker;
+    }
+
+    while i < line.len() {
+        let rest = &line[i..];
+
+        let delimited = if let Some(code) = rest.strip_prefix('`') {
+            code.find('`').map(|end| (end + 2, Token::String))
+        } else if rest.starts_with("**") || rest.starts_with("__") {
+            rest[2..].find(&rest[..2]).map(|end| (end + 4, Token::Type))
+        } else if rest.starts_with('*') || rest.starts_with('_') {
+            rest[1..].find(&rest[..1]).filter(|end| *end > 0).map(|end| (end + 2, Token::Type))
+        } else if rest.starts_with('[') {
+            rest.find("](")
+                .and_then(|mid| rest[mid..].find(')').map(|end| (mid + end + 1, Token::Function)))
+        } else {
+            None
+        };
+
+        match delimited {
+            Some((len, token)) => {
+                spans.push(Span { start: i, end: i + len, token });
+                i += len;
+            },
+            None => i += rest.chars().next().unwrap().len_utf8(),
+        }
+    }
+
+    (spans, State::Normal)
+}
+
+// 42, 0xff, 1.5e3, 10_000u32. toml dates and times (1979-05-27T07:32:00)
+// are read as one literal too
+fn number_len(s: &str, dates: bool) -> usize {
+    let mut end = 0;
+
+    for (i, c) in s.char_indices() {
+        let fraction = c == '.' && s[i + 1..].starts_with(|d: char| d.is_ascii_digit());
+        if c.is_ascii_alphanumeric() || c == '_' || fraction || (dates && (c == '-' || c == ':')) {
+            end = i + c.len_utf8();
+        } else {
+            break;
+        }
+    }
+
+    end
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn ident_len(s: &str) -> usize {
+    s.find(|c: char| !is_ident(c)).unwrap_or(s.len())
+}
+
+// length up to and including the closing quote, and whether it was found
+fn string_end(s: &str, quote: char) -> (usize, bool) {
+    let mut escaped = false;
+
+    for (i, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return (i + c.len_utf8(), true);
+        }
+    }
+
+    (s.len(), false)
+}
+
+fn comment_end(syntax: &Syntax, s: &str, mut depth: u8) -> (usize, u8) {
+    let Some((open, close)) = syntax.block_comment else { return (s.len(), 0) };
+    let mut i = 0;
+
+    while i < s.len() {
+        let rest = &s[i..];
+        if rest.starts_with(close) {
+            depth -= 1;
+            i += close.len();
+            if depth == 0 {
+                return (i, 0);
+            }
+        } else if syntax.nested_comments && rest.starts_with(open) {
+            depth = depth.saturating_add(1);
+            i += open.len();
+        } else {
+            i += rest.chars().next().unwrap().len_utf8();
+        }
+    }
+
+    (s.len(), depth)
+}
+
+// r"..", r#".."#, br".." -> length of the opening and number of #
+fn raw_string_start(s: &str) -> Option<(usize, u8)> {
+    let rest = s.strip_prefix('b').unwrap_or(s);
+    let rest = rest.strip_prefix('r')?;
+    let hashes = rest.chars().take_while(|c| *c == '#').count();
+
+    if rest[hashes..].starts_with('"') {
+        Some((s.len() - rest.len() + hashes + 1, hashes as u8))
+    } else {
+        None
+    }
+}
+
+// 'a', '\n', '\u{1F600}' but not the lifetime 'a
+fn char_literal_len(s: &str) -> Option<usize> {
+    let body = &s[1..];
+
+    if let Some(escaped) = body.strip_prefix('\\') {
+        // the escaped char may be the quote itself, as in '\''
+        let first_len = escaped.chars().next()?.len_utf8();
+        let end = escaped[first_len..].find('\'')?;
+        return Some(first_len + end + 3);
+    }
+
+    let c = body.chars().next()?;
+    if body[c.len_utf8()..].starts_with('\'') {
+        Some(c.len_utf8() + 2)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(line: &str, token: Token) -> Vec<(usize, usize)> {
+        let (spans, _) = highlight_line(&RUST, line, State::Normal);
+        spans.iter().filter(|span| span.token == token).map(|span| (span.start, span.end)).collect()
+    }
+
+    #[test]
+    fn macro_spans_end_after_the_bang() {
+        assert_eq!(spans("println!(\"hi\")", Token::Macro), [(0, 8)]);
+        assert_eq!(spans("vec !", Token::Macro), [(0, 5)]);
+
+        // a no-break space is two bytes
+        let line = "a\u{a0}!";
+        assert_eq!(spans(line, Token::Macro), [(0, line.len())]);
+        assert!(spans("a != b", Token::Macro).is_empty());
+    }
+
+    #[test]
+    fn char_literals_end_at_the_closing_quote() {
+        assert_eq!(spans("'a' x", Token::String), [(0, 3)]);
+        assert_eq!(spans("'\\n' x", Token::String), [(0, 4)]);
+        assert_eq!(spans("'\\'' x", Token::String), [(0, 4)]);
+        assert_eq!(spans("'\\\\' x", Token::String), [(0, 4)]);
+        assert_eq!(spans("'\\u{1F600}' x", Token::String), [(0, 11)]);
+    }
+
+    #[test]
+    fn spans_stay_on_char_boundaries() {
+        for line in ["a\u{a0}!", "x\u{3000}!()", "let ç = \"ã\"; // é", "/* 日本 */ fn f()"] {
+            let (spans, _) = highlight_line(&RUST, line, State::Normal);
+            for span in spans {
+                assert!(line.is_char_boundary(span.start) && line.is_char_boundary(span.end), "{:?}", line);
+            }
+        }
+    }
+
+    #[test]
+    fn block_comments_nest_and_carry_over() {
+        let (_, state) = highlight_line(&RUST, "/* a /* b */", State::Normal);
+        assert!(matches!(state, State::Comment(1)));
+        let (spans, state) = highlight_line(&RUST, "c */ fn", state);
+        assert!(matches!(state, State::Normal));
+        assert!(spans.iter().any(|span| span.token == Token::Keyword && (span.start, span.end) == (5, 7)));
+    }
+
+    #[test]
+    fn very_deep_comments_do_not_overflow() {
+        let (_, state) = highlight_line(&RUST, &"/*".repeat(300), State::Normal);
+        assert!(matches!(state, State::Comment(u8::MAX)));
+        let (_, state) = highlight_line(&RUST, &"*/ ".repeat(300), state);
+        assert!(matches!(state, State::Normal));
+    }
+}
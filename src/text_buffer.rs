@@ -0,0 +1,101 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use ropey::{Rope, RopeSlice};
+
+// rope backed text model. lines are separated by '\n' only and positions are
+// given as (line, column) with the column counted in chars
+#[derive(Clone)]
+pub struct TextBuffer {
+    rope: Rope,
+    version: u64,
+}
+
+// versions come from one counter, so two different texts never share one
+static VERSIONS: AtomicU64 = AtomicU64::new(0);
+
+fn next_version() -> u64 {
+    VERSIONS.fetch_add(1, Ordering::Relaxed)
+}
+
+impl TextBuffer {
+    pub fn new() -> Self {
+        TextBuffer { rope: Rope::new(), version: next_version() }
+    }
+
+    pub fn from_text(text: &str) -> Self {
+        TextBuffer { rope: Rope::from_str(text), version: next_version() }
+    }
+
+    // changes with every edit, so what's worked out from the text can be
+    // kept while it stays the same. a clone keeps it, undo brings it back
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    pub fn line(&self, i: usize) -> String {
+        let mut line = self.rope.line(i).to_string();
+        if line.ends_with('\n') {
+            line.pop();
+        }
+        line
+    }
+
+    // the line without its '\n', borrowed from the rope
+    pub fn line_slice(&self, i: usize) -> RopeSlice<'_> {
+        let line = self.rope.line(i);
+        let len = line.len_chars();
+        if len > 0 && line.char(len - 1) == '\n' {
+            line.slice(..len - 1)
+        } else {
+            line
+        }
+    }
+
+    pub fn line_len(&self, i: usize) -> usize {
+        let line = self.rope.line(i);
+        let len = line.len_chars();
+        if len > 0 && line.char(len - 1) == '\n' {
+            len - 1
+        } else {
+            len
+        }
+    }
+
+    pub fn insert(&mut self, line: usize, col: usize, text: &str) {
+        let idx = self.char_idx(line, col);
+        self.rope.insert(idx, text);
+        self.version = next_version();
+    }
+
+    pub fn delete(&mut self, start: (usize, usize), end: (usize, usize)) {
+        let start = self.char_idx(start.0, start.1);
+        let end = self.char_idx(end.0, end.1);
+        if start < end {
+            self.rope.remove(start..end);
+            self.version = next_version();
+        }
+    }
+
+    pub fn slice(&self, start: (usize, usize), end: (usize, usize)) -> String {
+        let start = self.char_idx(start.0, start.1);
+        let end = self.char_idx(end.0, end.1);
+        self.rope.slice(start..end).to_string()
+    }
+
+    pub fn text(&self) -> String {
+        self.rope.to_string()
+    }
+
+    pub fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        self.rope.write_to(writer)
+    }
+
+    fn char_idx(&self, line: usize, col: usize) -> usize {
+        let line = line.min(self.line_count() - 1);
+        self.rope.line_to_char(line) + col.min(self.line_len(line))
+    }
+}
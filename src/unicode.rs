@@ -0,0 +1,107 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+// cursor columns are counted in grapheme clusters, so "ç" written as
+// 'c' + U+0327 or a family emoji still moves as a single column
+
+pub fn grapheme_count(s: &str) -> usize {
+    s.graphemes(true).count()
+}
+
+// char index where the grapheme `col` starts (or the end of the line)
+pub fn grapheme_to_char(s: &str, col: usize) -> usize {
+    s.graphemes(true).take(col).map(|g| g.chars().count()).sum()
+}
+
+// byte offset where the grapheme `col` starts (or the end of the line)
+pub fn grapheme_to_byte(s: &str, col: usize) -> usize {
+    s.grapheme_indices(true).nth(col).map_or(s.len(), |(idx, _)| idx)
+}
+
+// first grapheme starting at or after the char `idx`
+pub fn char_to_grapheme(s: &str, idx: usize) -> usize {
+    let mut chars = 0;
+    for (i, g) in s.graphemes(true).enumerate() {
+        if chars >= idx {
+            return i;
+        }
+        chars += g.chars().count();
+    }
+    grapheme_count(s)
+}
+
+pub fn display_width(s: &str) -> usize {
+    UnicodeWidthStr::width(s)
+}
+
+// terminal columns taken by the first `col` graphemes
+pub fn screen_col(s: &str, col: usize) -> usize {
+    s.graphemes(true).take(col).map(UnicodeWidthStr::width).sum()
+}
+
+// longest prefix of `s` that fits in `width` terminal columns
+pub fn clip_width(s: &str, width: usize) -> &str {
+    let mut used = 0;
+    for (idx, g) in s.grapheme_indices(true) {
+        used += UnicodeWidthStr::width(g);
+        if used > width {
+            return &s[..idx];
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // c + combining cedilla, a family emoji and two wide CJK chars
+    const LINE: &str = "c\u{327}ã👨\u{200d}👩\u{200d}👧日本";
+
+    #[test]
+    fn graphemes_are_single_columns() {
+        assert_eq!(grapheme_count(LINE), 5);
+        assert_eq!(grapheme_count(""), 0);
+    }
+
+    #[test]
+    fn grapheme_columns_map_to_chars_and_bytes() {
+        assert_eq!(grapheme_to_char(LINE, 0), 0);
+        assert_eq!(grapheme_to_char(LINE, 1), 2);
+        assert_eq!(grapheme_to_char(LINE, 2), 3);
+        assert_eq!(grapheme_to_char(LINE, 3), 8);
+        assert_eq!(grapheme_to_char(LINE, 5), 10);
+        assert_eq!(grapheme_to_char(LINE, 9), 10);
+
+        assert_eq!(grapheme_to_byte(LINE, 1), 3);
+        assert_eq!(grapheme_to_byte(LINE, 2), 5);
+        assert_eq!(grapheme_to_byte(LINE, 9), LINE.len());
+    }
+
+    #[test]
+    fn chars_inside_a_grapheme_round_up() {
+        assert_eq!(char_to_grapheme(LINE, 0), 0);
+        assert_eq!(char_to_grapheme(LINE, 1), 1);
+        assert_eq!(char_to_grapheme(LINE, 2), 1);
+        assert_eq!(char_to_grapheme(LINE, 4), 3);
+        assert_eq!(char_to_grapheme(LINE, 8), 3);
+        assert_eq!(char_to_grapheme(LINE, 100), 5);
+    }
+
+    #[test]
+    fn wide_chars_take_two_screen_columns() {
+        assert_eq!(display_width("日本"), 4);
+        assert_eq!(screen_col(LINE, 2), 2);
+        assert_eq!(screen_col(LINE, 4), 6);
+        assert_eq!(screen_col(LINE, 5), 8);
+    }
+
+    #[test]
+    fn clipping_never_splits_a_grapheme() {
+        assert_eq!(clip_width(LINE, 1), "c\u{327}");
+        assert_eq!(clip_width(LINE, 5), "c\u{327}ã👨\u{200d}👩\u{200d}👧");
+        assert_eq!(clip_width("日本", 3), "日");
+        assert_eq!(clip_width("日本", 1), "");
+        assert_eq!(clip_width(LINE, 100), LINE);
+    }
+}
@@ -0,0 +1,103 @@
+// a view into a buffer. several windows can show the same buffer, each one
+// with its own cursor and scroll
+#[derive(Clone)]
+pub struct Window {
+    pub buffer: usize,
+    pub cursor_l: usize,
+    pub cursor_c: usize,
+    pub scroll_offset: usize,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Direction {
+    // :split, windows stacked on top of each other
+    Horizontal,
+    // :vsplit, windows side by side
+    Vertical,
+}
+
+#[derive(Clone, Copy)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+pub enum Layout {
+    Window(usize),
+    Split(Direction, Vec<Layout>),
+}
+
+impl Layout {
+    // puts window `new` right after `target`, reusing the parent split when
+    // it already goes in the same direction
+    pub fn split(&mut self, target: usize, new: usize, direction: Direction) -> bool {
+        match self {
+            Layout::Window(id) if *id == target => {
+                *self = Layout::Split(direction, vec![Layout::Window(target), Layout::Window(new)]);
+                true
+            },
+            Layout::Window(_) => false,
+            Layout::Split(dir, children) => {
+                if *dir == direction
+                    && let Some(pos) = children.iter().position(|c| matches!(c, Layout::Window(id) if *id == target))
+                {
+                    children.insert(pos + 1, Layout::Window(new));
+                    return true;
+                }
+                children.iter_mut().any(|c| c.split(target, new, direction))
+            },
+        }
+    }
+
+    // drops window `target` and shifts the ids above it down by one, the same
+    // way Vec::remove shifts the windows list
+    pub fn remove(&mut self, target: usize) {
+        if let Layout::Split(_, children) = self {
+            children.retain(|c| !matches!(c, Layout::Window(id) if *id == target));
+            for child in children.iter_mut() {
+                child.remove(target);
+            }
+            if children.len() == 1 {
+                *self = children.pop().unwrap();
+            }
+        }
+
+        if let Layout::Window(id) = self && *id > target {
+            *id -= 1;
+        }
+    }
+
+    // screen area of every window. one row or column between siblings is
+    // left for the separator
+    pub fn rects(&self, area: Rect, out: &mut Vec<(usize, Rect)>) {
+        match self {
+            Layout::Window(id) => out.push((*id, area)),
+            Layout::Split(direction, children) => {
+                let n = children.len() as u16;
+                let total = match direction {
+                    Direction::Horizontal => area.height,
+                    Direction::Vertical => area.width,
+                };
+                let size = total.saturating_sub(n - 1) / n;
+                let mut offset = 0;
+
+                for (i, child) in children.iter().enumerate() {
+                    let len = if i as u16 == n - 1 {
+                        total.saturating_sub(offset)
+                    } else {
+                        size
+                    };
+
+                    let rect = match direction {
+                        Direction::Horizontal => Rect { y: area.y + offset, height: len, ..area },
+                        Direction::Vertical => Rect { x: area.x + offset, width: len, ..area },
+                    };
+                    child.rects(rect, out);
+                    offset += len + 1;
+                }
+            },
+        }
+    }
+}